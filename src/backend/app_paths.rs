@@ -0,0 +1,208 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use directories_next::ProjectDirs;
+use log::info;
+
+/// Command line option enabling the portable mode
+const PORTABLE_ARG: &str = "--portable";
+/// File next to the executable enabling the portable mode
+const PORTABLE_MARKER: &str = "raspirus.portable";
+/// Directory next to the executable everything is kept in while in portable mode
+const PORTABLE_DIR: &str = "raspirus_data";
+
+/// Command line options overriding a location, followed by the directory
+const DATA_DIR_ARG: &str = "--data-dir";
+const LOG_DIR_ARG: &str = "--log-dir";
+const CACHE_DIR_ARG: &str = "--cache-dir";
+const CONFIG_DIR_ARG: &str = "--config-dir";
+
+/// Environment variables overriding a location, used unless the command line overrides it as well
+const DATA_DIR_ENV: &str = "RASPIRUS_DATA_DIR";
+const LOG_DIR_ENV: &str = "RASPIRUS_LOG_DIR";
+const CACHE_DIR_ENV: &str = "RASPIRUS_CACHE_DIR";
+const CONFIG_DIR_ENV: &str = "RASPIRUS_CONFIG_DIR";
+
+/// Where the application keeps its files. Resolved once at start and handed to every component,
+/// so that tests and special setups can point them somewhere else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPaths {
+    /// The signature database and its bloom filter
+    pub data_dir: PathBuf,
+    /// Scan logs in `logs` and update logs in `updates`
+    pub log_dir: PathBuf,
+    /// Files that can be fetched again, like partial downloads and the newest file known per mirror
+    pub cache_dir: PathBuf,
+    /// The config file
+    pub config_dir: PathBuf,
+    /// Whether everything is kept next to the executable instead of the user profile
+    pub portable: bool,
+}
+
+impl AppPaths {
+    /// Returns the default locations of the platform.
+    /// The config is kept next to the database, where it has always been.
+    pub fn platform() -> Self {
+        let data = ProjectDirs::from("com", "Raspirus", "Data").expect("Failed to get project directories.");
+        let logs = ProjectDirs::from("com", "Raspirus", "Logs").expect("Failed to get project directories.");
+        AppPaths {
+            data_dir: data.data_dir().to_owned(),
+            log_dir: logs.data_local_dir().to_owned(),
+            cache_dir: data.cache_dir().to_owned(),
+            config_dir: data.data_dir().to_owned(),
+            portable: false,
+        }
+    }
+
+    /// Returns locations inside a single directory, e.g. a temporary one for tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The directory to keep everything in
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let paths = AppPaths::in_dir(Path::new("/tmp/raspirus"));
+    /// assert_eq!(paths.database(), PathBuf::from("/tmp/raspirus/data/signatures.db"));
+    /// ```
+    pub fn in_dir(root: &Path) -> Self {
+        AppPaths {
+            data_dir: root.join("data"),
+            log_dir: root.join("logs"),
+            cache_dir: root.join("cache"),
+            config_dir: root.join("config"),
+            portable: false,
+        }
+    }
+
+    /// Returns the locations of the portable mode, in a directory beside the executable.
+    /// Used to run from a stick on machines the user does not own, without leaving files in their profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `exe_dir` - The directory containing the executable
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let paths = AppPaths::portable(Path::new("/media/usb"));
+    /// assert_eq!(paths.data_dir, PathBuf::from("/media/usb/raspirus_data/data"));
+    /// ```
+    pub fn portable(exe_dir: &Path) -> Self {
+        AppPaths {
+            portable: true,
+            ..Self::in_dir(&exe_dir.join(PORTABLE_DIR))
+        }
+    }
+
+    /// Picks the default locations: the portable ones if `--portable` was passed or a `raspirus.portable`
+    /// file lies next to the executable, the ones of the platform otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line arguments, starting with the program name
+    /// * `exe_dir` - The directory containing the executable, None if it could not be determined
+    pub(crate) fn defaults(args: &[String], exe_dir: Option<&Path>) -> Result<Self, String> {
+        let requested = args.iter().skip(1).any(|arg| arg == PORTABLE_ARG);
+        match exe_dir {
+            Some(dir) if requested || dir.join(PORTABLE_MARKER).is_file() => Ok(Self::portable(dir)),
+            None if requested => Err(String::from("Portable mode needs the directory of the executable, which could not be determined")),
+            _ => Ok(Self::platform()),
+        }
+    }
+
+    /// Resolves the locations from the defaults, see [`AppPaths::defaults`], overridden by the environment variables
+    /// `RASPIRUS_DATA_DIR`, `RASPIRUS_LOG_DIR`, `RASPIRUS_CACHE_DIR` and `RASPIRUS_CONFIG_DIR`,
+    /// which are in turn overridden by the command line options `--data-dir`, `--log-dir`, `--cache-dir` and `--config-dir`.
+    /// Returns the locations and the remaining arguments, or an error if an option lacks its directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line arguments, starting with the program name
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let args: Vec<String> = std::env::args().collect();
+    /// let (paths, args) = AppPaths::resolve(&args).unwrap();
+    /// ```
+    pub fn resolve(args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let exe_dir = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf));
+        let paths = Self::defaults(args, exe_dir.as_deref())?;
+        if paths.portable {
+            info!("Running in portable mode, keeping files in {}", paths.data_dir.display());
+        } else {
+            info!("Running in installed mode, keeping files in the user profile");
+        }
+        paths.with_overrides(args, |name| env::var_os(name).map(PathBuf::from))
+    }
+
+    /// Applies the overrides of the environment, as returned by `var`, and of the command line.
+    /// The portable option was already handled by [`AppPaths::defaults`] and is dropped from the arguments.
+    pub(crate) fn with_overrides(
+        mut self,
+        args: &[String],
+        var: impl Fn(&str) -> Option<PathBuf>,
+    ) -> Result<(Self, Vec<String>), String> {
+        for (name, dir) in [
+            (DATA_DIR_ENV, &mut self.data_dir),
+            (LOG_DIR_ENV, &mut self.log_dir),
+            (CACHE_DIR_ENV, &mut self.cache_dir),
+            (CONFIG_DIR_ENV, &mut self.config_dir),
+        ] {
+            if let Some(value) = var(name).filter(|value| !value.as_os_str().is_empty()) {
+                info!("{} is set, using {}", name, value.display());
+                *dir = value;
+            }
+        }
+
+        let mut remaining = Vec::with_capacity(args.len());
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let dir = match arg.as_str() {
+                DATA_DIR_ARG => &mut self.data_dir,
+                LOG_DIR_ARG => &mut self.log_dir,
+                CACHE_DIR_ARG => &mut self.cache_dir,
+                CONFIG_DIR_ARG => &mut self.config_dir,
+                PORTABLE_ARG => continue,
+                _ => {
+                    remaining.push(arg.clone());
+                    continue;
+                }
+            };
+            match args.next() {
+                Some(value) if !value.is_empty() => *dir = PathBuf::from(value),
+                _ => return Err(format!("{} needs a directory", arg)),
+            }
+        }
+        Ok((self, remaining))
+    }
+
+    /// Returns the path of the signature database, creating its directory if needed.
+    /// The persisted bloom filter lives next to it, with the extension `bloom`.
+    pub fn database(&self) -> PathBuf {
+        fs::create_dir_all(&self.data_dir).expect("Failed to create program directory.");
+        self.data_dir.join("signatures.db")
+    }
+
+    /// Returns the path of the config file, creating its directory if needed
+    pub fn config_file(&self) -> PathBuf {
+        fs::create_dir_all(&self.config_dir).expect("Failed to create config directory.");
+        self.config_dir.join("raspirus.config.json")
+    }
+
+    /// Returns the directory scan logs are written to
+    pub fn scan_logs(&self) -> PathBuf {
+        self.log_dir.join("logs")
+    }
+
+    /// Returns the directory update logs are written to
+    pub fn update_logs(&self) -> PathBuf {
+        self.log_dir.join("updates")
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    path::Path,
+};
+
+/// Magic bytes written at the start of a persisted filter, followed by the format version
+const MAGIC: &[u8; 4] = b"RBLM";
+const VERSION: u8 = 2;
+
+/// Probabilistic set used to answer "definitely not present" for hash lookups without touching the database.
+/// False positives are possible and must be confirmed against SQLite, false negatives are not.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    /// The bit array, stored in 64-bit words
+    bits: Vec<u64>,
+    /// Amount of usable bits in the array
+    num_bits: u64,
+    /// Amount of bit positions set per inserted item
+    num_hashes: u32,
+    /// Amount of items inserted
+    items: u64,
+    /// Generation of the database the filter was built from, used to detect a filter that no longer matches it
+    generation: u64,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for the given amount of items and false positive rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_items` - The amount of items the filter will hold
+    /// * `false_positive_rate` - The desired probability of a false positive, e.g. `0.01`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let filter = BloomFilter::new(1_000_000, 0.01);
+    /// ```
+    pub fn new(expected_items: u64, false_positive_rate: f64) -> Self {
+        let items = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(items * false_positive_rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / items) * ln2).round().clamp(1.0, 16.0) as u32;
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+            items: 0,
+            generation: 0,
+        }
+    }
+
+    /// Adds the given hash to the filter
+    pub fn insert(&mut self, hash: &str) {
+        let (h1, h2) = Self::seeds(hash);
+        for i in 0..self.num_hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits;
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.items += 1;
+    }
+
+    /// Returns false if the hash is definitely not in the filter, true if it might be
+    pub fn might_contain(&self, hash: &str) -> bool {
+        let (h1, h2) = Self::seeds(hash);
+        (0..self.num_hashes as u64).all(|i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits;
+            self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    /// Returns the amount of items inserted into the filter
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// Returns the generation of the database the filter was built from
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Sets the generation of the database the filter was built from, which is persisted with it
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    /// Writes the filter to the given path, overwriting any previous filter.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let filter = BloomFilter::new(1_000_000, 0.01);
+    /// filter.save(Path::new("signatures.bloom")).unwrap();
+    /// ```
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.num_hashes.to_le_bytes())?;
+        writer.write_all(&self.num_bits.to_le_bytes())?;
+        writer.write_all(&self.items.to_le_bytes())?;
+        writer.write_all(&self.generation.to_le_bytes())?;
+        for word in &self.bits {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a filter previously written with `save`.
+    /// Returns an error if the file is missing, truncated or was written by an incompatible version.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let filter = BloomFilter::load(Path::new("signatures.bloom")).unwrap();
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a valid bloom filter file"));
+        }
+        let mut buf4 = [0u8; 4];
+        let mut buf8 = [0u8; 8];
+        reader.read_exact(&mut buf4)?;
+        let num_hashes = u32::from_le_bytes(buf4);
+        reader.read_exact(&mut buf8)?;
+        let num_bits = u64::from_le_bytes(buf8);
+        reader.read_exact(&mut buf8)?;
+        let items = u64::from_le_bytes(buf8);
+        reader.read_exact(&mut buf8)?;
+        let generation = u64::from_le_bytes(buf8);
+        if num_bits == 0 || num_hashes == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Bloom filter header is corrupted"));
+        }
+
+        let mut bits = Vec::with_capacity(num_bits.div_ceil(64) as usize);
+        for _ in 0..num_bits.div_ceil(64) {
+            reader.read_exact(&mut buf8)?;
+            bits.push(u64::from_le_bytes(buf8));
+        }
+        Ok(BloomFilter {
+            bits,
+            num_bits,
+            num_hashes,
+            items,
+            generation,
+        })
+    }
+
+    /// Derives the two base hashes used for double hashing from the MD5 digest of the input
+    fn seeds(hash: &str) -> (u64, u64) {
+        let digest = md5::compute(hash.as_bytes()).0;
+        let mut low = [0u8; 8];
+        let mut high = [0u8; 8];
+        low.copy_from_slice(&digest[..8]);
+        high.copy_from_slice(&digest[8..]);
+        // The second hash must be odd so that all probes don't collapse onto the same bit
+        (u64::from_le_bytes(low), u64::from_le_bytes(high) | 1)
+    }
+}
@@ -1,9 +1,15 @@
-use std::fs::{self, File};
+use std::fs::{File, OpenOptions};
 use std::io::{self, Read};
-use directories_next::ProjectDirs;
+use log::info;
 use serde::{Deserialize, Serialize};
 
+use super::{
+    app_paths::AppPaths,
+    sources::{http::HttpConfig, virusshare::DEFAULT_MIRROR_URL, SourceConfig},
+};
+
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub hashes_in_db: u32,
     pub last_db_update: String,
@@ -11,6 +17,28 @@ pub struct Config {
     pub obfuscated_is_active: bool,
     pub db_update_weekday: i32,
     pub db_update_time: String,
+    /// Bytes freed by migrating the database to the compact storage format
+    pub db_space_saved: u64,
+    /// Sources the signature database is updated from, in the order they are fetched
+    pub signature_sources: Vec<SourceConfig>,
+    /// Base URL of a server providing the `VirusShare_NNNNN.md5` files, e.g. a mirror in the local network
+    pub mirror_url: String,
+    /// Username sent to the mirror, only used together with a token
+    pub mirror_username: String,
+    /// Token sent to the mirror, as bearer token or as password if a username is set
+    pub mirror_token: String,
+    /// Hex encoded Ed25519 key the mirror's checksum manifest has to be signed with, empty to accept unsigned mirrors
+    pub update_public_key: String,
+    /// Amount of hash files downloaded at the same time during an update
+    pub download_workers: usize,
+    /// Proxy, timeouts, user agent and trusted CAs used for all network requests
+    pub http: HttpConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
 }
 
 impl Config {
@@ -22,35 +50,64 @@ impl Config {
             obfuscated_is_active: true,
             db_update_weekday: -1,
             db_update_time: "22:00:00".to_string(),
+            db_space_saved: 0,
+            signature_sources: vec![SourceConfig::VirusShare],
+            mirror_url: DEFAULT_MIRROR_URL.to_string(),
+            mirror_username: String::new(),
+            mirror_token: String::new(),
+            update_public_key: String::new(),
+            download_workers: 4,
+            http: HttpConfig::default(),
         }
     }
 
-    pub fn set_path(&self) -> Result<String, io::Error> {
+    pub fn set_path(&self, paths: &AppPaths) -> Result<String, io::Error> {
         // Sets the path/location of where to store the config files
-        let project_dirs =
-            ProjectDirs::from("com", "Raspirus", "Data").expect("Failed to get project directories.");
-        let program_dir = project_dirs.data_dir();
-        fs::create_dir_all(&program_dir).expect("Failed to create program directory.");
-        let db_file_path = program_dir.join("raspirus.config.json");
-        let db_file_str = db_file_path.to_str().expect("Failed to get database path");
-        Ok(db_file_str.to_string())
+        let config_file_path = paths.config_file();
+        let config_file_str = config_file_path.to_str().expect("Failed to get config path");
+        Ok(config_file_str.to_string())
     }
 
-    pub fn save(&self) -> Result<(), io::Error> {
+    pub fn save(&self, paths: &AppPaths) -> Result<(), io::Error> {
         // Writes to the path set above
-        let path = self.set_path().expect("Couldn't get path to Data directories");
-        let file = File::create(path)?;
-        serde_json::to_writer_pretty(file, self)?;
+        let path = self.set_path(paths).expect("Couldn't get path to Data directories");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The mirror token and proxy password are stored in plain text, so only the owner may read them.
+        // On Windows the config is kept in the user profile, which other users can't read already.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            let file = options.open(&path)?;
+            // The mode only applies to new files, older configs may still be readable by everyone
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+            serde_json::to_writer_pretty(file, self)?;
+        }
+        #[cfg(not(unix))]
+        serde_json::to_writer_pretty(options.open(&path)?, self)?;
         Ok(())
     }
 
-    pub fn load(&self) -> Result<Self, io::Error> {
+    pub fn load(&self, paths: &AppPaths) -> Result<Self, io::Error> {
         // Returns this class as object with data filled from file
-        let path = Config::set_path(&self)?;
+        let path = Config::set_path(self, paths)?;
         let mut file = File::open(path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
         let config: Config = serde_json::from_str(&contents)?;
         Ok(config)
     }
+
+    /// Writes the default config if there is none yet, which is the case on the first start.
+    /// Returns whether it was written.
+    pub fn create_default(paths: &AppPaths) -> Result<bool, io::Error> {
+        let path = paths.config_file();
+        if path.exists() {
+            return Ok(false);
+        }
+        info!("No config found at {}, creating the default one", path.display());
+        Config::new().save(paths)?;
+        Ok(true)
+    }
 }
@@ -0,0 +1,285 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::{info, warn};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    app_paths::AppPaths,
+    db_migrations,
+    db_ops::{BatchInfo, DBOps},
+};
+
+/// Version of the backup layout, increased whenever the manifest or database layout changes
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Names of the entries inside a backup archive
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "signatures.db";
+
+/// Describes the content of a backup, stored next to the database inside the archive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    /// Time the backup was created, in RFC 3339 format
+    pub created: String,
+    pub hash_count: u64,
+    /// SHA-256 of the database file inside the archive, in hexadecimal representation
+    pub sha256: String,
+    pub batches: Vec<BatchInfo>,
+}
+
+/// A backup that was extracted to a temporary directory and passed all integrity checks.
+/// The temporary directory is removed once this is dropped.
+#[derive(Debug)]
+pub struct VerifiedBackup {
+    dir: PathBuf,
+    pub manifest: BackupManifest,
+}
+
+impl VerifiedBackup {
+    /// Path of the verified database file
+    pub fn database(&self) -> PathBuf {
+        self.dir.join(DATABASE_ENTRY)
+    }
+}
+
+impl Drop for VerifiedBackup {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.dir) {
+            warn!("Failed removing {}: {}", self.dir.display(), err);
+        }
+    }
+}
+
+/// Exports the database to a gzip compressed tar archive containing a snapshot of the database
+/// and a manifest with its checksum, hash count and batches.
+///
+/// # Arguments
+///
+/// * `db_ops` - The database to export
+/// * `paths` - Where the application keeps its files, the snapshot is assembled in the cache directory
+/// * `target` - Path of the archive to create
+///
+/// # Examples
+///
+/// ```
+/// let paths = AppPaths::platform();
+/// let db_ops = DBOps::new(&paths, None).unwrap();
+/// let manifest = db_backup::export(&db_ops, &paths, Path::new("/media/usb/raspirus-backup.tar.gz")).unwrap();
+/// println!("Exported {} hashes", manifest.hash_count);
+/// ```
+pub fn export(db_ops: &DBOps, paths: &AppPaths, target: &Path) -> Result<BackupManifest, String> {
+    let work_dir = create_work_dir(paths)?;
+    let snapshot = work_dir.join(DATABASE_ENTRY);
+    let result = db_ops
+        .snapshot(&snapshot)
+        .map_err(|err| err.to_string())
+        .and_then(|_| export_snapshot(&snapshot, target));
+    if let Err(err) = fs::remove_dir_all(&work_dir) {
+        warn!("Failed removing {}: {}", work_dir.display(), err);
+    }
+    result
+}
+
+/// Writes the backup archive for a snapshot taken with [`DBOps::snapshot`].
+/// The hash count and batches of the manifest are read from the snapshot itself,
+/// so changes to the live database after the snapshot was taken do not end up in it.
+///
+/// # Arguments
+///
+/// * `snapshot` - The database snapshot to archive
+/// * `target` - Path of the archive to create
+pub(crate) fn export_snapshot(snapshot: &Path, target: &Path) -> Result<BackupManifest, String> {
+    let conn = Connection::open_with_flags(snapshot, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| err.to_string())?;
+    let hash_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM signatures", [], |row| row.get(0))
+        .map_err(|err| err.to_string())?;
+    let batches = DBOps::read_batches(&conn).map_err(|err| err.to_string())?;
+    drop(conn);
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        created: Utc::now().to_rfc3339(),
+        hash_count: hash_count as u64,
+        sha256: sha256_file(snapshot).map_err(|err| err.to_string())?,
+        batches,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|err| err.to_string())?;
+
+    // Written to a temporary name first, so an interrupted export never looks like a valid backup
+    let partial = target.with_extension("partial");
+    let encoder = GzEncoder::new(
+        File::create(&partial).map_err(|err| err.to_string())?,
+        Compression::default(),
+    );
+    let mut archive = tar::Builder::new(encoder);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, MANIFEST_ENTRY, manifest_json.as_slice())
+        .map_err(|err| err.to_string())?;
+    archive
+        .append_path_with_name(snapshot, DATABASE_ENTRY)
+        .map_err(|err| err.to_string())?;
+    let mut file = archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|err| err.to_string())?;
+    file.flush().map_err(|err| err.to_string())?;
+    fs::rename(&partial, target).map_err(|err| err.to_string())?;
+
+    info!(
+        "Exported {} hashes in {} batches to {}",
+        manifest.hash_count,
+        manifest.batches.len(),
+        target.display()
+    );
+    Ok(manifest)
+}
+
+/// Extracts the given backup and checks it before it may be restored or merged:
+/// the checksum of the database has to match the manifest, SQLite's integrity check has to pass
+/// and the amount of hashes has to match the manifest. The extracted copy is then migrated to the current schema.
+///
+/// # Arguments
+///
+/// * `paths` - Where the application keeps its files, the backup is extracted to the cache directory
+/// * `path` - Path of the backup archive
+///
+/// # Examples
+///
+/// ```
+/// let backup = db_backup::verify(&AppPaths::platform(), Path::new("/media/usb/raspirus-backup.tar.gz")).unwrap();
+/// println!("Backup from {} with {} hashes", backup.manifest.created, backup.manifest.hash_count);
+/// ```
+pub fn verify(paths: &AppPaths, path: &Path) -> Result<VerifiedBackup, String> {
+    info!("Verifying backup {}", path.display());
+    let mut backup = VerifiedBackup {
+        dir: create_work_dir(paths)?,
+        manifest: BackupManifest {
+            format_version: 0,
+            created: String::new(),
+            hash_count: 0,
+            sha256: String::new(),
+            batches: Vec::new(),
+        },
+    };
+
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut manifest = None;
+    for entry in archive.entries().map_err(|err| err.to_string())? {
+        let mut entry = entry.map_err(|err| err.to_string())?;
+        let entry_path = entry.path().map_err(|err| err.to_string())?.into_owned();
+        match entry_path.to_str() {
+            Some(MANIFEST_ENTRY) => {
+                let mut content = String::new();
+                entry
+                    .read_to_string(&mut content)
+                    .map_err(|err| err.to_string())?;
+                manifest = Some(
+                    serde_json::from_str::<BackupManifest>(&content)
+                        .map_err(|err| format!("Invalid backup manifest: {}", err))?,
+                );
+            }
+            Some(DATABASE_ENTRY) => {
+                let mut target = File::create(backup.database()).map_err(|err| err.to_string())?;
+                io::copy(&mut entry, &mut target).map_err(|err| err.to_string())?;
+            }
+            _ => warn!("Ignoring unexpected backup entry {}", entry_path.display()),
+        }
+    }
+
+    let manifest = manifest.ok_or("Backup does not contain a manifest")?;
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(format!(
+            "Backup format {} is newer than the supported format {}",
+            manifest.format_version, BACKUP_FORMAT_VERSION
+        ));
+    }
+    if !backup.database().exists() {
+        return Err(String::from("Backup does not contain a database"));
+    }
+    let checksum = sha256_file(&backup.database()).map_err(|err| err.to_string())?;
+    if checksum != manifest.sha256 {
+        return Err(format!(
+            "Backup checksum mismatch: expected {}, got {}",
+            manifest.sha256, checksum
+        ));
+    }
+
+    let conn = Connection::open_with_flags(backup.database(), OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| err.to_string())?;
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|err| err.to_string())?;
+    if integrity != "ok" {
+        return Err(format!("Backup database is corrupt: {}", integrity));
+    }
+    let hash_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM signatures", [], |row| row.get(0))
+        .map_err(|err| err.to_string())?;
+    if hash_count as u64 != manifest.hash_count {
+        return Err(format!(
+            "Backup should contain {} hashes, but contains {}",
+            manifest.hash_count, hash_count
+        ));
+    }
+    drop(conn);
+
+    // Older backups are brought to the current schema, newer ones are refused before touching the database
+    let conn = Connection::open(backup.database()).map_err(|err| err.to_string())?;
+    db_migrations::migrate(&conn).map_err(|err| err.to_string())?;
+    drop(conn);
+
+    info!(
+        "Backup from {} with {} hashes is valid",
+        manifest.created, manifest.hash_count
+    );
+    backup.manifest = manifest;
+    Ok(backup)
+}
+
+/// Returns the SHA-256 of the given file in hexadecimal representation
+fn sha256_file(path: &Path) -> Result<String, io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Creates an empty directory in the cache directory for extracting or assembling a backup.
+/// Kept out of the system's temporary directory, which may be too small for the database or not writable in portable mode.
+fn create_work_dir(paths: &AppPaths) -> Result<PathBuf, String> {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos())
+        .unwrap_or_default();
+    let dir = paths.cache_dir.join(format!("backup-{}-{}", process::id(), started));
+    fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    Ok(dir)
+}
@@ -0,0 +1,375 @@
+use std::{collections::HashMap, fs, path::Path, time};
+
+use log::{info, warn};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    db_migrations::{self, BATCH_TRACKING_VERSION},
+    db_ops::BatchInfo,
+};
+
+/// Tables whose rows are keyed by a hash and can therefore be rescued in ranges of hashes
+const HASH_TABLES: [(&str, &str); 3] = [
+    ("signatures", "hash, file_nr"),
+    ("signature_meta", "hash, name, severity, first_seen"),
+    ("removals", "hash, list, reason, was_present"),
+];
+
+/// A batch by its id, together with the amount of hashes it actually holds
+type BatchCount = (i64, BatchInfo, u64);
+
+/// Outcome of a database health check
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Everything found wrong with the database, empty if it is healthy
+    pub problems: Vec<String>,
+    /// Amount of hashes in the `signatures` table
+    pub hash_count: u64,
+    /// Amount of hashes the imported batches should add up to
+    pub expected_hashes: u64,
+    /// Batches holding a different amount of hashes than recorded when they were imported
+    pub damaged_batches: Vec<BatchInfo>,
+}
+
+impl HealthReport {
+    /// Returns true if no problems were found and the database may be used for scanning
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Returns the first problems found, short enough to be shown to the user
+    pub fn summary(&self) -> String {
+        let mut summary = self.problems.iter().take(3).cloned().collect::<Vec<_>>().join("; ");
+        if self.problems.len() > 3 {
+            summary.push_str(&format!(" (and {} more)", self.problems.len() - 3));
+        }
+        summary
+    }
+}
+
+/// Outcome of rebuilding a damaged database
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Amount of hashes rescued into the rebuilt database
+    pub recovered_hashes: u64,
+    /// Batches that lost hashes and were removed, so that the next update downloads them again
+    pub redownload: Vec<BatchInfo>,
+}
+
+/// Checks the database at the given path for corruption and compares the amount of hashes
+/// of every batch with the amount recorded when it was imported.
+/// The quick check skips verifying the indexes and is fast enough to run at every start,
+/// the thorough check runs SQLite's full integrity check.
+/// A database that does not exist yet is healthy, it is created empty on first use.
+///
+/// # Arguments
+///
+/// * `db_file` - Path of the database to check
+/// * `thorough` - Whether to run `PRAGMA integrity_check` instead of `PRAGMA quick_check`
+///
+/// # Examples
+///
+/// ```
+/// let report = db_health::check(&AppPaths::platform().database(), false);
+/// if !report.is_healthy() {
+///     println!("Database is damaged: {}", report.summary());
+/// }
+/// ```
+pub fn check(db_file: &Path, thorough: bool) -> HealthReport {
+    info!("Checking health of database {}", db_file.display());
+    let big_tic = time::Instant::now();
+    let mut report = HealthReport::default();
+    if !db_file.exists() {
+        return report;
+    }
+
+    // Opened writable, so that a transaction interrupted by a power loss gets rolled back first
+    let conn = match Connection::open_with_flags(db_file, OpenFlags::SQLITE_OPEN_READ_WRITE) {
+        Ok(conn) => conn,
+        Err(err) => {
+            report.problems.push(format!("Database can not be opened: {}", err));
+            return report;
+        }
+    };
+    let pragma = if thorough { "PRAGMA integrity_check" } else { "PRAGMA quick_check" };
+    match integrity_problems(&conn, pragma) {
+        Ok(problems) if problems.is_empty() => {}
+        Ok(problems) => {
+            report.problems = problems;
+            return report;
+        }
+        Err(err) => {
+            report.problems.push(format!("Database is unreadable: {}", err));
+            return report;
+        }
+    }
+
+    // Databases from before batches were tracked are brought up to date when first opened
+    match db_migrations::schema_version(&conn) {
+        Ok(version) if version < BATCH_TRACKING_VERSION => return report,
+        Ok(_) => {}
+        Err(err) => {
+            report.problems.push(format!("Database is unreadable: {}", err));
+            return report;
+        }
+    }
+    match batch_counts(&conn) {
+        Ok((hash_count, batches)) => {
+            report.hash_count = hash_count;
+            for (_, batch, found) in batches {
+                report.expected_hashes += batch.hash_count;
+                if found != batch.hash_count {
+                    report.problems.push(format!(
+                        "Batch {} of {} holds {} of {} hashes",
+                        batch.name, batch.source, found, batch.hash_count
+                    ));
+                    report.damaged_batches.push(batch);
+                }
+            }
+        }
+        Err(err) => report.problems.push(format!("Failed counting hashes: {}", err)),
+    }
+
+    let big_toc = time::Instant::now();
+    info!(
+        "=> Checked {} hashes in {} seconds, {} problem(s) found",
+        report.hash_count,
+        big_toc.duration_since(big_tic).as_secs_f64(),
+        report.problems.len()
+    );
+    report
+}
+
+/// Rebuilds the database at the given path from all rows that can still be read.
+/// The rows are copied to a new database next to it, which then replaces the damaged one.
+/// Batches that lost hashes are removed together with their remaining hashes,
+/// so that the next update downloads them again in full. The bloom filter is removed to be rebuilt.
+///
+/// # Examples
+///
+/// ```
+/// let report = db_health::repair(&AppPaths::platform().database()).unwrap();
+/// println!("Recovered {} hashes, {} batches to download again", report.recovered_hashes, report.redownload.len());
+/// ```
+pub fn repair(db_file: &Path) -> Result<RepairReport, String> {
+    info!("Rebuilding database {} from its readable rows", db_file.display());
+    let big_tic = time::Instant::now();
+    let staged = db_file.with_extension("rebuild");
+    if staged.exists() {
+        // Left over by an interrupted repair
+        fs::remove_file(&staged).map_err(|err| err.to_string())?;
+    }
+
+    let rebuilt = (|| {
+        let target = Connection::open(&staged).map_err(|err| err.to_string())?;
+        db_migrations::migrate(&target).map_err(|err| err.to_string())?;
+        match Connection::open_with_flags(db_file, OpenFlags::SQLITE_OPEN_READ_WRITE) {
+            Ok(damaged) => rescue(&damaged, &target).map_err(|err| err.to_string())?,
+            Err(err) => warn!("Nothing to rescue, the database can not be opened: {}", err),
+        }
+        let report = drop_incomplete_batches(&target).map_err(|err| err.to_string())?;
+        target.close().map_err(|(_, err)| err.to_string())?;
+        Ok(report)
+    })();
+    let report = match rebuilt {
+        Ok(report) => report,
+        Err(err) => {
+            let _ = fs::remove_file(&staged);
+            return Err(err);
+        }
+    };
+
+    // A leftover journal would otherwise be applied to the rebuilt database
+    for suffix in ["-journal", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", db_file.display(), suffix));
+    }
+    fs::rename(&staged, db_file).map_err(|err| err.to_string())?;
+    if let Err(err) = fs::remove_file(db_file.with_extension("bloom")) {
+        info!("No bloom filter removed: {err}");
+    }
+
+    let big_toc = time::Instant::now();
+    info!(
+        "=> Rebuilt database with {} hashes in {} seconds, {} batch(es) to download again",
+        report.recovered_hashes,
+        big_toc.duration_since(big_tic).as_secs_f64(),
+        report.redownload.len()
+    );
+    Ok(report)
+}
+
+/// Runs the given integrity check pragma and returns the problems it reported
+fn integrity_problems(conn: &Connection, pragma: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(pragma)?;
+    let results = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(results.into_iter().filter(|result| result != "ok").collect())
+}
+
+/// Returns the total amount of hashes and every batch with its id and the amount of hashes it actually holds.
+/// The hashes are counted in a single pass, as there is no index on their batch.
+fn batch_counts(conn: &Connection) -> Result<(u64, Vec<BatchCount>), rusqlite::Error> {
+    let mut found = HashMap::new();
+    let mut hash_count = 0;
+    let mut stmt = conn.prepare("SELECT file_nr, COUNT(*) FROM signatures GROUP BY file_nr")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let count = row.get::<_, i64>(1)? as u64;
+        found.insert(row.get::<_, i64>(0)?, count);
+        hash_count += count;
+    }
+
+    let mut stmt = conn.prepare("SELECT id, source, name, hash_count, imported FROM batches ORDER BY source, id")?;
+    let batches = stmt
+        .query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            Ok((
+                id,
+                BatchInfo {
+                    source: row.get(1)?,
+                    name: row.get(2)?,
+                    hash_count: row.get::<_, i64>(3)? as u64,
+                    imported: row.get(4)?,
+                },
+                found.get(&id).copied().unwrap_or(0),
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((hash_count, batches))
+}
+
+/// Copies every readable row of the damaged database into the target.
+/// Hash keyed tables are read in 256 ranges of their first byte, so a damaged page only costs the rows of its range.
+fn rescue(damaged: &Connection, target: &Connection) -> Result<(), rusqlite::Error> {
+    let transact = target.unchecked_transaction()?;
+    let (batches, err) = copy_rows(
+        damaged,
+        &transact,
+        "SELECT id, source, name, hash_count, imported FROM batches",
+        &[],
+        "INSERT OR IGNORE INTO batches(id, source, name, hash_count, imported) VALUES (?, ?, ?, ?, ?)",
+    );
+    if let Some(err) = err {
+        warn!("Stopped rescuing batches after {} rows: {}", batches, err);
+    }
+    if has_table(damaged, "removal_lists") {
+        let (lists, err) = copy_rows(
+            damaged,
+            &transact,
+            "SELECT id, source, name, applied FROM removal_lists",
+            &[],
+            "INSERT OR IGNORE INTO removal_lists(id, source, name, applied) VALUES (?, ?, ?, ?)",
+        );
+        if let Some(err) = err {
+            warn!("Stopped rescuing removal lists after {} rows: {}", lists, err);
+        }
+    }
+
+    for (table, columns) in HASH_TABLES {
+        if !has_table(damaged, table) {
+            warn!("Table {} is missing or unreadable, nothing to rescue", table);
+            continue;
+        }
+        let insert = format!(
+            "INSERT OR IGNORE INTO {}({}) VALUES ({})",
+            table,
+            columns,
+            vec!["?"; columns.split(',').count()].join(", ")
+        );
+        let mut rescued = 0;
+        let mut lost_ranges = 0;
+        for first in 0..=u8::MAX {
+            let lower = [first];
+            let upper = [first.wrapping_add(1)];
+            let (select, bounds): (String, Vec<&[u8]>) = if first == u8::MAX {
+                (format!("SELECT {} FROM {} WHERE hash >= ?", columns, table), vec![&lower[..]])
+            } else {
+                (
+                    format!("SELECT {} FROM {} WHERE hash >= ? AND hash < ?", columns, table),
+                    vec![&lower[..], &upper[..]],
+                )
+            };
+            let (copied, err) = copy_rows(damaged, &transact, &select, &bounds, &insert);
+            rescued += copied;
+            if let Some(err) = err {
+                lost_ranges += 1;
+                warn!("Stopped rescuing {} starting with {:02x} after {} rows: {}", table, first, copied, err);
+            }
+        }
+        info!("Rescued {} rows of {}, {} of 256 ranges were damaged", rescued, table, lost_ranges);
+    }
+    transact.commit()
+}
+
+/// Returns true if the table is listed in the schema of the database
+fn has_table(conn: &Connection, table: &str) -> bool {
+    conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+        params![table],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+    .unwrap_or(false)
+}
+
+/// Copies the rows returned by `select` into the target with `insert`, until the first row that can not be read.
+/// Returns the amount of copied rows and the error that stopped the copy, if any.
+fn copy_rows(
+    source: &Connection,
+    target: &Connection,
+    select: &str,
+    bounds: &[&[u8]],
+    insert: &str,
+) -> (u64, Option<rusqlite::Error>) {
+    let mut copied = 0;
+    let result = (|| {
+        let mut select = source.prepare(select)?;
+        let columns = select.column_count();
+        let mut insert = target.prepare_cached(insert)?;
+        let mut rows = select.query(params_from_iter(bounds))?;
+        while let Some(row) = rows.next()? {
+            let values = (0..columns)
+                .map(|column| row.get::<_, Value>(column))
+                .collect::<Result<Vec<_>, _>>()?;
+            insert.execute(params_from_iter(values))?;
+            copied += 1;
+        }
+        Ok(())
+    })();
+    (copied, result.err())
+}
+
+/// Removes the batches of the rebuilt database that lost hashes, together with the hashes they still hold,
+/// so that they are no longer considered imported. Returns the rescued hashes and the removed batches.
+fn drop_incomplete_batches(conn: &Connection) -> Result<RepairReport, rusqlite::Error> {
+    let transact = conn.unchecked_transaction()?;
+    let (_, batches) = batch_counts(&transact)?;
+    let mut redownload = Vec::new();
+    for (id, batch, found) in batches {
+        if found > batch.hash_count {
+            // Holds more hashes than recorded, nothing was lost and only the count is corrected
+            transact.execute("UPDATE batches SET hash_count = ? WHERE id = ?", params![found as i64, id])?;
+        }
+        if found >= batch.hash_count {
+            continue;
+        }
+        warn!(
+            "Batch {} of {} lost {} of {} hashes, it will be downloaded again",
+            batch.name,
+            batch.source,
+            batch.hash_count - found,
+            batch.hash_count
+        );
+        transact.execute("DELETE FROM signatures WHERE file_nr = ?", params![id])?;
+        transact.execute("DELETE FROM batches WHERE id = ?", params![id])?;
+        redownload.push(batch);
+    }
+    let recovered_hashes: i64 = transact.query_row("SELECT COUNT(*) FROM signatures", [], |row| row.get(0))?;
+    transact.commit()?;
+    Ok(RepairReport {
+        recovered_hashes: recovered_hashes as u64,
+        redownload,
+    })
+}
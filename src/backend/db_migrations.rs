@@ -0,0 +1,297 @@
+use std::{fmt, time};
+
+use log::{debug, info};
+use rusqlite::{params, Connection, Transaction};
+
+use super::db_ops::DBOps;
+
+/// Schema version of `signatures.db` this build works with, stored in `PRAGMA user_version`
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// Version after which the signatures are stored in the compact binary layout
+pub const COMPACT_STORAGE_VERSION: u32 = 2;
+
+/// Version after which every signature belongs to a batch in the `batches` table
+pub const BATCH_TRACKING_VERSION: u32 = 3;
+
+/// Errors that can occur while bringing a database to the current schema
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer version of the application and may not be opened
+    NewerSchema { found: u32, supported: u32 },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(err) => write!(f, "{}", err),
+            MigrationError::NewerSchema { found, supported } => write!(
+                f,
+                "Database schema version {} is newer than the supported version {}, please update Raspirus",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(err: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(err)
+    }
+}
+
+/// A single schema change. Steps run in order of their version, each in its own transaction
+/// together with the update of `user_version`, so a failing step leaves the database untouched.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Transaction) -> Result<(), rusqlite::Error>,
+}
+
+/// All migrations, ordered by version. Steps have to cope with databases created before versioning,
+/// which report version 0 but may already contain the tables of later steps.
+const MIGRATIONS: [Migration; 6] = [
+    Migration {
+        version: 1,
+        description: "create signatures table",
+        apply: create_signatures,
+    },
+    Migration {
+        version: 2,
+        description: "store signatures as binary hashes",
+        apply: compact_signatures,
+    },
+    Migration {
+        version: 3,
+        description: "track imported batches per source",
+        apply: create_batches,
+    },
+    Migration {
+        version: 4,
+        description: "store signature metadata",
+        apply: create_signature_meta,
+    },
+    Migration {
+        version: 5,
+        description: "track retracted signatures",
+        apply: create_removals,
+    },
+    Migration {
+        version: 6,
+        description: "count changes to the signatures",
+        apply: create_generation,
+    },
+];
+
+/// Returns the schema version stored in the database, 0 for new or unversioned databases
+pub fn schema_version(conn: &Connection) -> Result<u32, rusqlite::Error> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Brings the database to the current schema version and returns the versions that were applied.
+///
+/// # Examples
+///
+/// ```
+/// let conn = rusqlite::Connection::open("signatures.db").unwrap();
+/// let applied = db_migrations::migrate(&conn).unwrap();
+/// assert_eq!(db_migrations::schema_version(&conn).unwrap(), db_migrations::SCHEMA_VERSION);
+/// ```
+pub fn migrate(conn: &Connection) -> Result<Vec<u32>, MigrationError> {
+    migrate_to(conn, SCHEMA_VERSION)
+}
+
+/// Applies all pending migrations up to and including the given version.
+/// Refuses databases with a schema newer than this build supports.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database to migrate
+/// * `target` - The version to stop at
+///
+/// # Examples
+///
+/// ```
+/// let conn = rusqlite::Connection::open_in_memory().unwrap();
+/// assert_eq!(db_migrations::migrate_to(&conn, 1).unwrap(), vec![1]);
+/// ```
+pub fn migrate_to(conn: &Connection, target: u32) -> Result<Vec<u32>, MigrationError> {
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(MigrationError::NewerSchema {
+            found: current,
+            supported: SCHEMA_VERSION,
+        });
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current && migration.version <= target)
+    {
+        info!(
+            "Migrating database to version {}: {}",
+            migration.version, migration.description
+        );
+        let big_tic = time::Instant::now();
+        let transact = conn.unchecked_transaction()?;
+        (migration.apply)(&transact)?;
+        transact.pragma_update(None, "user_version", migration.version)?;
+        transact.commit()?;
+        let big_toc = time::Instant::now();
+        info!(
+            "=> Migrated to version {} in {} seconds",
+            migration.version,
+            big_toc.duration_since(big_tic).as_secs_f64()
+        );
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+/// Version 1: the original layout, with every hash stored as text next to the number of its file
+fn create_signatures(transact: &Transaction) -> Result<(), rusqlite::Error> {
+    transact.execute(
+        "CREATE TABLE IF NOT EXISTS signatures (
+                                  hash varchar(32) PRIMARY KEY,
+                                  file_nr varchar(5))",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 2: hashes are stored as 16 byte blobs and file numbers as integers,
+/// in a `WITHOUT ROWID` table so that the primary key index is the table itself.
+/// Invalid hashes are dropped. The database should be vacuumed afterwards to release the freed space.
+fn compact_signatures(transact: &Transaction) -> Result<(), rusqlite::Error> {
+    let hash_type: String = transact.query_row(
+        "SELECT type FROM pragma_table_info('signatures') WHERE name = 'hash'",
+        [],
+        |row| row.get(0),
+    )?;
+    if hash_type.eq_ignore_ascii_case("BLOB") {
+        return Ok(());
+    }
+
+    transact.execute(
+        "CREATE TABLE signatures_compact (
+                                  hash BLOB PRIMARY KEY,
+                                  file_nr INTEGER NOT NULL) WITHOUT ROWID",
+        [],
+    )?;
+    let mut migrated = 0;
+    let mut skipped = 0;
+    {
+        let mut select = transact.prepare("SELECT hash, file_nr FROM signatures")?;
+        let mut insert = transact
+            .prepare("INSERT OR IGNORE INTO signatures_compact(hash, file_nr) VALUES (?, ?)")?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let hash: String = row.get(0)?;
+            let file_nr: Option<String> = row.get(1)?;
+            match DBOps::hash_to_blob(&hash) {
+                Some(blob) => {
+                    let file_nr = file_nr.and_then(|nr| nr.parse::<i64>().ok()).unwrap_or(0);
+                    insert.execute(params![&blob[..], file_nr])?;
+                    migrated += 1;
+                }
+                None => {
+                    skipped += 1;
+                    debug!("Dropping invalid hash during migration: {}", hash);
+                }
+            }
+        }
+    }
+    transact.execute("DROP TABLE signatures", [])?;
+    transact.execute("ALTER TABLE signatures_compact RENAME TO signatures", [])?;
+    info!("=> Converted: {}, Skipped: {}", migrated, skipped);
+    Ok(())
+}
+
+/// Version 3: the `batches` table tracks the imported batches of every signature source,
+/// the `file_nr` of each signature refers to the `id` of its batch.
+/// Existing databases only contain VirusShare files, so their file numbers become VirusShare batches.
+fn create_batches(transact: &Transaction) -> Result<(), rusqlite::Error> {
+    transact.execute(
+        "CREATE TABLE IF NOT EXISTS batches (
+                                  id INTEGER PRIMARY KEY,
+                                  source TEXT NOT NULL,
+                                  name TEXT NOT NULL,
+                                  hash_count INTEGER NOT NULL DEFAULT 0,
+                                  imported TEXT,
+                                  UNIQUE(source, name))",
+        [],
+    )?;
+    let backfilled = transact.execute(
+        "INSERT INTO batches(id, source, name, hash_count)
+            SELECT file_nr, 'virusshare', printf('%05d', file_nr), COUNT(*) FROM signatures
+            WHERE NOT EXISTS (SELECT 1 FROM batches)
+            GROUP BY file_nr",
+        [],
+    )?;
+    if backfilled > 0 {
+        info!("Registered {} existing VirusShare file(s) as batches", backfilled);
+    }
+    Ok(())
+}
+
+/// Version 4: optional metadata per signature, only stored for signatures whose feed provides any
+fn create_signature_meta(transact: &Transaction) -> Result<(), rusqlite::Error> {
+    transact.execute(
+        "CREATE TABLE IF NOT EXISTS signature_meta (
+                                  hash BLOB PRIMARY KEY,
+                                  name TEXT,
+                                  severity INTEGER,
+                                  first_seen TEXT) WITHOUT ROWID",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 5: removal lists retract signatures, e.g. known false positives.
+/// Every retracted hash is kept together with the list it came from and the reason,
+/// so retractions can be audited and the hash is not imported again.
+fn create_removals(transact: &Transaction) -> Result<(), rusqlite::Error> {
+    transact.execute(
+        "CREATE TABLE IF NOT EXISTS removal_lists (
+                                  id INTEGER PRIMARY KEY,
+                                  source TEXT NOT NULL,
+                                  name TEXT NOT NULL,
+                                  applied TEXT,
+                                  UNIQUE(source, name))",
+        [],
+    )?;
+    transact.execute(
+        "CREATE TABLE IF NOT EXISTS removals (
+                                  hash BLOB PRIMARY KEY,
+                                  list INTEGER NOT NULL,
+                                  reason TEXT,
+                                  was_present INTEGER NOT NULL) WITHOUT ROWID",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 6: a counter bumped by every change to the signatures, so a persisted bloom filter
+/// can tell whether it still matches the database even if as many hashes were removed as added.
+/// It starts at a random value, so that a different database with the same amount of changes does not match either.
+fn create_generation(transact: &Transaction) -> Result<(), rusqlite::Error> {
+    transact.execute("CREATE TABLE IF NOT EXISTS db_generation (value INTEGER NOT NULL)", [])?;
+    transact.execute(
+        "INSERT INTO db_generation (value)
+         SELECT random() & 0xFFFFFFFFFFFF WHERE NOT EXISTS (SELECT 1 FROM db_generation)",
+        [],
+    )?;
+    for (name, event) in [("signatures_inserted", "INSERT"), ("signatures_deleted", "DELETE"), ("signatures_updated", "UPDATE")] {
+        transact.execute(
+            &format!(
+                "CREATE TRIGGER IF NOT EXISTS {} AFTER {} ON signatures
+                 BEGIN UPDATE db_generation SET value = value + 1; END",
+                name, event
+            ),
+            [],
+        )?;
+    }
+    Ok(())
+}
@@ -1,104 +1,412 @@
-use std::{time, fs};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    thread,
+    time::{self, Duration},
+};
 
-use directories_next::ProjectDirs;
 use log::{debug, error, info, warn};
-use reqwest::StatusCode;
-use rusqlite::{params, Connection};
+use chrono::Utc;
+use rusqlite::{params, params_from_iter, Connection, ErrorCode, OptionalExtension, Transaction};
 use flume::Sender;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    app_paths::AppPaths,
+    bloom_filter::BloomFilter,
+    config_file::Config,
+    db_migrations::{self, MigrationError, COMPACT_STORAGE_VERSION},
+    file_log::UpdateLog,
+    signature::{Removal, RemovalInfo, Severity, Signature, SignatureInfo},
+    sources::{self, integrity::Verification, retry::RetryPolicy, SignatureSource, SourceError},
+};
+
+/// Maximum amount of hashes looked up in a single query, stays below SQLite's default variable limit of 999
+const LOOKUP_CHUNK_SIZE: usize = 500;
+
+/// Target false positive rate of the in-memory prefilter. At 1% it costs roughly 10 bits per signature.
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Size of one line of a plain hash list, a hex encoded MD5 hash and the line break
+const BYTES_PER_HASH: u64 = 33;
+
+/// How many batches per source are asked for their size when estimating an update
+const SIZE_SAMPLES: usize = 5;
+
+/// How long a connection waits for another one to finish writing before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The bloom filter of a database, shared by all connections to it so that readers see new hashes at once
+pub type SharedBloom = Arc<RwLock<Option<BloomFilter>>>;
+
+/// A batch handed from a download worker to the importing thread, by its index in the list of batches
+type FetchedBatch = (usize, Result<(Verification, Vec<Signature>), SourceError>);
+
+/// A batch of signatures as tracked in the `batches` table
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchInfo {
+    pub source: String,
+    pub name: String,
+    pub hash_count: u64,
+    pub imported: Option<String>,
+}
+
+/// What an update would download, as estimated by [`DBOps::check_updates`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateCheck {
+    /// Batches that have not been imported yet
+    pub new_files: usize,
+    /// Estimated size of the new batches in bytes
+    pub download_size: u64,
+    /// Estimated amount of hashes in the new batches
+    pub new_hashes: u64,
+}
 
 #[allow(unused)]
 pub struct DBOps {
+    paths: AppPaths,
     db_conn: Connection,
     db_file: String,
     file_nr: i32,
     total_files: i32,
-    sender: Option<Sender<f32>>
+    sender: Option<Sender<f32>>,
+    bloom: SharedBloom,
+    bloom_file: String,
+    update_log: Option<UpdateLog>,
+    cancel: Arc<AtomicBool>,
+    retry: RetryPolicy,
+    failure: Option<String>,
 }
 
 impl DBOps {
-    /// Returns a new `DBOps` struct with a connection to the specified database file
-    /// and initializes the table if it does not exist.
+    /// Returns a new `DBOps` struct with a connection to the database at the given locations
+    /// and migrates its schema to the current version.
+    /// Fails if the database was created by a newer version of the application.
     ///
     /// # Arguments
     ///
+    /// * `paths` - The locations of the application, also used to find the config
     /// * `sender` - The senders struct
     ///
     /// # Examples
     ///
     /// ```
-    /// use rusqlite::Connection;
-    /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert_eq!(db_ops.db_conn, Connection::open("signatures.db").unwrap());
-    /// ```
-    pub fn new(sender: Option<Sender<f32>>) -> Result<Self, rusqlite::Error> {
-        
-        let project_dirs = ProjectDirs::from("com", "Raspirus", "Data")
-            .expect("Failed to get project directories.");
-        let program_dir = project_dirs.data_dir();
-        fs::create_dir_all(&program_dir).expect("Failed to create program directory.");
-        let db_file_path = program_dir.join("signatures.db");
+    /// use virus_scanner::backend::{app_paths::AppPaths, db_ops::DBOps};
+    /// let db_ops = DBOps::new(&AppPaths::in_dir(Path::new("/tmp/raspirus")), None).unwrap();
+    /// assert_eq!(db_ops.count_hashes().unwrap(), 0);
+    /// ```
+    pub fn new(paths: &AppPaths, sender: Option<Sender<f32>>) -> Result<Self, MigrationError> {
+        let db_file_path = paths.database();
         let db_file_str: &str = db_file_path.to_str().expect("Failed to get database path");
+        let bloom_file_path = db_file_path.with_extension("bloom");
+        let bloom_file_str: &str = bloom_file_path.to_str().expect("Failed to get bloom filter path");
 
         let conn = match Connection::open(db_file_str) {
             Ok(conn) => conn,
-            Err(err) => return Err(err),
+            Err(err) => return Err(err.into()),
         };
         info!("New database connection at: {}", db_file_str);
+        Self::configure(&conn)?;
+        // Lets readers continue while an update writes, instead of waiting for every commit
+        if let Err(err) = conn.pragma_update(None, "journal_mode", "WAL") {
+            warn!("Failed switching database to write-ahead logging: {err}");
+        }
 
-        let ret = DBOps {
+        let mut ret = DBOps {
+            paths: paths.clone(),
             db_conn: conn,
             db_file: db_file_str.to_owned(),
             file_nr: 0,
             total_files: 0,
-            sender
+            sender,
+            bloom: Arc::new(RwLock::new(None)),
+            bloom_file: bloom_file_str.to_owned(),
+            update_log: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+            retry: RetryPolicy::default(),
+            failure: None,
         };
         ret.init_table()?;
+        ret.load_bloom_filter()?;
         Ok(ret)
     }
 
-    /// Initializes the `signatures` table if it does not exist.
+    /// Opens another connection to the database, sharing the given bloom filter with the connection it belongs to.
+    /// The schema is not checked, that connection already migrated it.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The locations the connection the bloom filter belongs to was opened with
+    /// * `bloom` - The bloom filter of an open connection, see [`DBOps::shared_bloom`]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use virus_scanner::backend::{app_paths::AppPaths, db_ops::DBOps};
+    /// let paths = AppPaths::platform();
+    /// let db_ops = DBOps::new(&paths, None).unwrap();
+    /// let reader = DBOps::open_shared(&paths, db_ops.shared_bloom()).unwrap();
+    /// assert_eq!(reader.count_hashes().unwrap(), db_ops.count_hashes().unwrap());
+    /// ```
+    pub fn open_shared(paths: &AppPaths, bloom: SharedBloom) -> Result<Self, rusqlite::Error> {
+        let db_file_path = paths.database();
+        let conn = Connection::open(&db_file_path)?;
+        Self::configure(&conn)?;
+        Ok(DBOps {
+            paths: paths.clone(),
+            db_conn: conn,
+            db_file: db_file_path.to_string_lossy().into_owned(),
+            file_nr: 0,
+            total_files: 0,
+            sender: None,
+            bloom,
+            bloom_file: db_file_path.with_extension("bloom").to_string_lossy().into_owned(),
+            update_log: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+            retry: RetryPolicy::default(),
+            failure: None,
+        })
+    }
+
+    /// Returns the bloom filter of this connection, to open further connections sharing it
+    pub fn shared_bloom(&self) -> SharedBloom {
+        self.bloom.clone()
+    }
+
+    /// Makes the connection wait for other connections to finish writing, instead of failing at once
+    fn configure(conn: &Connection) -> Result<(), rusqlite::Error> {
+        conn.busy_timeout(BUSY_TIMEOUT)
+    }
+
+    /// Sets where the progress of updates and imports is sent to, in percent
+    pub fn set_sender(&mut self, sender: Option<Sender<f32>>) {
+        self.sender = sender;
+    }
+
+    /// Sets the log that update results, such as the verification of every downloaded batch, are written to
+    pub fn set_update_log(&mut self, update_log: Option<UpdateLog>) {
+        self.update_log = update_log;
+    }
+
+    /// Sets the flag that cancels a running update or import once it is raised.
+    /// The update stops between files, everything imported until then is kept.
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+
+    /// Returns how many of the files of the last update or import were processed, if it was cancelled
+    pub fn cancelled(&self) -> Option<(i32, i32)> {
+        if self.cancel.load(Ordering::SeqCst) {
+            Some((self.file_nr, self.total_files))
+        } else {
+            None
+        }
+    }
+
+    /// Sets how often failed downloads are retried before the update stops
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Returns why the last update or import stopped early, if it did
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    /// Remembers why the update stopped, the first failure is the one reported
+    fn record_failure(&mut self, failure: String) {
+        error!("{}", failure);
+        self.log_update(&format!("DB update stopped: {}", failure));
+        self.failure.get_or_insert(failure);
+    }
+
+    /// Writes the given message to the update log, if one is set
+    fn log_update(&self, message: &str) {
+        if let Some(update_log) = &self.update_log {
+            update_log.log(message);
+        }
+    }
+
+    /// Loads the persisted bloom filter, or rebuilds it from the `signatures` table if it is
+    /// missing, unreadable or was built from a different generation of the database.
+    fn load_bloom_filter(&mut self) -> Result<(), rusqlite::Error> {
+        let generation = self.generation()?;
+        match BloomFilter::load(Path::new(&self.bloom_file)) {
+            Ok(bloom) if bloom.generation() == generation => {
+                info!("Loaded bloom filter with {} hashes", bloom.items());
+                *self.bloom.write().unwrap_or_else(PoisonError::into_inner) = Some(bloom);
+                Ok(())
+            }
+            Ok(bloom) => {
+                warn!(
+                    "Bloom filter is outdated (generation {}, database is at {}), rebuilding",
+                    bloom.generation(),
+                    generation
+                );
+                self.rebuild_bloom_filter()
+            }
+            Err(err) => {
+                info!("No usable bloom filter found ({err}), rebuilding");
+                self.rebuild_bloom_filter()
+            }
+        }
+    }
+
+    /// Builds a new bloom filter from all hashes in the `signatures` table and writes it to disk.
+    /// Failing to persist the filter is not fatal, it will simply be rebuilt on the next start.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// assert!(db_ops.rebuild_bloom_filter().is_ok());
+    /// ```
+    pub fn rebuild_bloom_filter(&mut self) -> Result<(), rusqlite::Error> {
+        let big_tic = time::Instant::now();
+        let hash_count = self.count_hashes()?;
+        let mut bloom = BloomFilter::new(hash_count, BLOOM_FALSE_POSITIVE_RATE);
+        bloom.set_generation(self.generation()?);
+
+        let mut stmt = self.db_conn.prepare("SELECT hash FROM signatures")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let hash: Vec<u8> = row.get(0)?;
+            bloom.insert(&Self::blob_to_hash(&hash));
+        }
+
+        if let Err(err) = bloom.save(Path::new(&self.bloom_file)) {
+            warn!("Failed saving bloom filter to {}: {err}", self.bloom_file);
+        }
+        let big_toc = time::Instant::now();
+        info!(
+            "=> Built bloom filter with {} hashes in {} seconds",
+            bloom.items(),
+            big_toc.duration_since(big_tic).as_secs_f64()
+        );
+        *self.bloom.write().unwrap_or_else(PoisonError::into_inner) = Some(bloom);
+        Ok(())
+    }
+
+    /// Brings the database schema up to date by running all pending migrations.
+    /// If the signatures were converted to the compact layout, the database is vacuumed
+    /// and the amount of bytes saved is written to the config.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
     /// assert!(db_ops.init_table().is_ok());
     /// ```
-    pub fn init_table(&self) -> Result<(), rusqlite::Error> {
-        info!("Creating table if not present...");
-        match self.db_conn.execute(
-            "CREATE TABLE IF NOT EXISTS signatures (
-                                      hash varchar(32) PRIMARY KEY,
-                                      file_nr varchar(5))",
-            [],
-        ) {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err),
+    pub fn init_table(&self) -> Result<(), MigrationError> {
+        info!("Checking database schema...");
+        let size_before = fs::metadata(&self.db_file).map(|md| md.len()).unwrap_or(0);
+        let applied = db_migrations::migrate(&self.db_conn)?;
+        if !applied.contains(&COMPACT_STORAGE_VERSION) {
+            return Ok(());
+        }
+
+        self.db_conn.execute("VACUUM", [])?;
+        let size_after = fs::metadata(&self.db_file).map(|md| md.len()).unwrap_or(0);
+        let saved = size_before.saturating_sub(size_after);
+        if saved > 0 {
+            info!("Compact storage saved {} mb", saved as f64 * 0.000001);
+            let mut config = Config::new().load(&self.paths).unwrap_or_else(|_| Config::new());
+            config.db_space_saved = saved;
+            if let Err(err) = config.save(&self.paths) {
+                warn!("Failed saving migration results to config: {err}");
+            }
         }
+        Ok(())
+    }
+
+    /// Converts a hexadecimal MD5 hash to its 16 byte representation.
+    /// Returns None if the string is not a valid MD5 hash.
+    pub(crate) fn hash_to_blob(hash: &str) -> Option<[u8; 16]> {
+        let hash = hash.trim().as_bytes();
+        // from_str_radix would also accept a sign, like in "+f"
+        if hash.len() != 32 || !hash.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let mut blob = [0u8; 16];
+        for (i, pair) in hash.chunks(2).enumerate() {
+            let pair = std::str::from_utf8(pair).ok()?;
+            blob[i] = u8::from_str_radix(pair, 16).ok()?;
+        }
+        Some(blob)
     }
 
-    /// Updates the database by downloading any missing files and inserting their hashes into the `signatures` table.
+    /// Converts a stored 16 byte hash back to its lowercase hexadecimal representation
+    fn blob_to_hash(blob: &[u8]) -> String {
+        blob.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Updates the database by fetching any batches of the configured sources that have not been imported yet
+    /// and inserting their hashes into the `signatures` table.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let mut db_ops = DBOps::new("signatures.db").unwrap();
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
     /// db_ops.update_db();
     /// ```
     pub fn update_db(&mut self) -> Result<u64, rusqlite::Error> {
         info!("Updating database...");
-        let web_files = self.get_diff_file();
+        let config = Config::new().load(&self.paths).unwrap_or_else(|_| Config::new());
+        let mut pending = Vec::new();
+        self.failure = None;
+        let configured = match sources::from_config(&config, &self.paths, &self.cancel) {
+            Ok(configured) => configured,
+            Err(err) => {
+                self.record_failure(format!("Failed setting up the signature sources: {}", err));
+                Vec::new()
+            }
+        };
+        for source in configured {
+            match self.get_diff_removals(source.as_ref()) {
+                Ok(lists) if !lists.is_empty() => {
+                    info!("Source {} published {} new removal list(s)", source.name(), lists.len());
+                    self.update_removals(source.as_ref(), lists);
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    self.record_failure(format!("Failed listing removal lists of {}: {}", source.name(), err));
+                }
+            }
+            let batches = match self.get_diff_file(source.as_ref()) {
+                Ok(batches) => batches,
+                Err(err) => {
+                    self.record_failure(format!("Failed listing batches of {}: {}", source.name(), err));
+                    continue;
+                }
+            };
+            if !batches.is_empty() {
+                info!("Source {} is missing {} batch(es)", source.name(), batches.len());
+                pending.push((source, batches));
+            }
+        }
 
-        if let Some(last_element) = web_files.last() {
-            self.total_files = *last_element;
+        self.total_files = pending.iter().map(|(_, batches)| batches.len() as i32).sum();
+        self.file_nr = 0;
+        if self.total_files > 0 {
             info!("Database not up-to-date!");
-            info!("Downloading {} file(s)", web_files.len());
-            self.download_files(web_files);
+            info!("Downloading {} batch(es)", self.total_files);
+            for (source, batches) in pending {
+                if self.cancelled().is_some() {
+                    break;
+                }
+                self.download_files(source.as_ref(), batches, config.download_workers);
+            }
+            self.rebuild_bloom_filter()?;
         } else {
             match &self.sender {
                 Some(sender) => sender.send(100.0).expect("Error while sending"),
@@ -109,145 +417,476 @@ impl DBOps {
         Ok(self.count_hashes().unwrap_or(0))
     }
 
-    /// Downloads the specified files and inserts their hashes into the signatures table.
+    /// Checks how many batches of the configured sources are new and estimates the size of an update,
+    /// without downloading or inserting anything.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let check = db_ops.check_updates().unwrap();
+    /// println!("{} new signature files available", check.new_files);
+    /// ```
+    pub fn check_updates(&self) -> Result<UpdateCheck, SourceError> {
+        info!("Checking for updates...");
+        let config = Config::new().load(&self.paths).unwrap_or_else(|_| Config::new());
+        let mut total = UpdateCheck::default();
+        for source in sources::from_config(&config, &self.paths, &self.cancel)? {
+            let check = self.check_source(source.as_ref())?;
+            total.new_files += check.new_files;
+            total.download_size += check.download_size;
+            total.new_hashes += check.new_hashes;
+        }
+        info!(
+            "{} new file(s) with about {} hashes, {} bytes to download",
+            total.new_files, total.new_hashes, total.download_size
+        );
+        Ok(total)
+    }
+
+    /// Checks how many batches of the given source are new and estimates their size.
+    /// Asking for the size of hundreds of batches takes long, so only a few batches spread across
+    /// the new ones are asked and their average size is extrapolated.
+    pub fn check_source(&self, source: &dyn SignatureSource) -> Result<UpdateCheck, SourceError> {
+        let batches = self.get_diff_file(source)?;
+        if batches.is_empty() {
+            return Ok(UpdateCheck::default());
+        }
+        let sizes: Vec<u64> = batches
+            .iter()
+            .step_by((batches.len() / SIZE_SAMPLES).max(1))
+            .take(SIZE_SAMPLES)
+            .filter_map(|batch| match source.batch_size(batch) {
+                Ok(size) => size,
+                Err(err) => {
+                    warn!("Failed getting size of batch {} of {}: {}", batch, source.name(), err);
+                    None
+                }
+            })
+            .collect();
+        let average_size = match sizes.len() {
+            0 => 0,
+            samples => sizes.iter().sum::<u64>() / samples as u64,
+        };
+        let download_size = average_size * batches.len() as u64;
+        Ok(UpdateCheck {
+            new_files: batches.len(),
+            download_size,
+            new_hashes: download_size / BYTES_PER_HASH,
+        })
+    }
+
+    /// Imports every batch and applies every removal list the given source offers, regardless of whether
+    /// they were imported before. Used for offline imports, where the user explicitly picked the lists to import.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// use virus_scanner::backend::sources;
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let source = sources::open_import(Path::new("/media/usb/hashes")).unwrap();
+    /// db_ops.import_from(source.as_ref()).unwrap();
+    /// ```
+    pub fn import_from(&mut self, source: &dyn SignatureSource) -> Result<u64, String> {
+        info!("Importing signatures from {}...", source.name());
+        let batches = source.list_batches().map_err(|err| err.to_string())?;
+        let removals = source.list_removals().map_err(|err| err.to_string())?;
+        if batches.is_empty() && removals.is_empty() {
+            return Err(String::from("No hash lists found to import"));
+        }
+        let hashes_before = self.count_hashes().map_err(|err| err.to_string())?;
+
+        self.total_files = batches.len() as i32;
+        self.file_nr = 0;
+        self.failure = None;
+        let config = Config::new().load(&self.paths).unwrap_or_else(|_| Config::new());
+        self.update_removals(source, removals);
+        self.download_files(source, batches, config.download_workers);
+        self.rebuild_bloom_filter().map_err(|err| err.to_string())?;
+        if let Some(sender) = &self.sender {
+            sender.send(100.0).expect("Error while sending");
+        }
+
+        let hashes_after = self.count_hashes().map_err(|err| err.to_string())?;
+        info!(
+            "Imported {} batch(es) with {} new hashes",
+            self.file_nr,
+            hashes_after.saturating_sub(hashes_before)
+        );
+        Ok(hashes_after)
+    }
+
+    /// Fetches the specified batches from the given source and inserts their hashes into the signatures table.
+    /// Up to `workers` batches are downloaded and parsed at the same time, while the calling thread
+    /// imports them one after another. If a worker fails, the others finish their current batch and stop.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source offering the batches
+    /// * `batches` - The identifiers of the batches to fetch, as returned by the source
+    /// * `workers` - The maximum amount of concurrent downloads
     ///
     /// # Examples
     ///
     /// ```
-    /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let mut db_ops = DBOps::new("signatures.db").unwrap();
-    /// db_ops.download_files(vec![1, 2, 3]);
+    /// use virus_scanner::backend::sources::virusshare::{VirusShareSource, DEFAULT_MIRROR_URL};
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let source = VirusShareSource::new(reqwest::blocking::Client::new(), DEFAULT_MIRROR_URL, "", "", &AppPaths::platform().cache_dir);
+    /// db_ops.download_files(&source, vec!["00001".to_owned(), "00002".to_owned()], 4);
     /// ```
-    pub fn download_files(&mut self, files: Vec<i32>) {
-        if files.len() == 0 {
+    pub fn download_files(&mut self, source: &dyn SignatureSource, batches: Vec<String>, workers: usize) {
+        if batches.is_empty() {
             return;
         }
 
-        info!("Trying to fetch files...");
-        let mut _retry = false;
-        let mut i = 0;
+        let workers = workers.clamp(1, batches.len());
+        info!("Trying to fetch {} batch(es) from {} with {} worker(s)...", batches.len(), source.name(), workers);
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let cancel = self.cancel.clone();
+        let retry = self.retry;
+        // Bounded, so that fast downloads can not pile up parsed batches in memory while the import lags behind
+        let (results, received) = flume::bounded(workers);
         let last_percentage: &mut f32 = &mut -1.0;
-        while i < files.len() {
-            _retry = false;
-            match Self::download_file(files[i]) {
-                Ok(hashes) => match hashes {
-                    Some(hashes) => {
-
-                        if let Err(_) = Self::calculate_progress(
-                            self,
-                            last_percentage,
-                            i.try_into().unwrap(),
-                            self.total_files,
-                        ) {
-                            error!("Progress calculation is broken");
-                            break;
-                        }
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let results = results.clone();
+                let (batches, next, stop, cancel) = (&batches, &next, &stop, cancel.as_ref());
+                scope.spawn(move || Self::download_worker(source, batches, next, [stop, cancel], &retry, results));
+            }
+            drop(results);
 
-                        match self.insert_hashes(hashes) {
-                            Ok(_) => i += 1,
-                            Err(err) => error!("{err}"),
-                        };
+            for (index, result) in received.iter() {
+                let batch = &batches[index];
+                match result {
+                    Ok((verification, signatures)) => {
+                        info!("Batch {} of {}: {}", batch, source.name(), verification);
+                        self.log_update(&format!("{} batch {}: {}", source.name(), batch, verification));
+                        match self.import_batch(source.name(), batch, signatures) {
+                            Ok(_) => source.finish_batch(batch),
+                            Err(err) => {
+                                self.record_failure(format!("Failed importing batch {} of {}: {}", batch, source.name(), err));
+                                stop.store(true, Ordering::SeqCst);
+                            }
+                        }
+                    }
+                    Err(SourceError::NotFound(batch)) => {
+                        info!("Batch {} not found, no more batches to download", batch);
+                        stop.store(true, Ordering::SeqCst);
                     }
+                    Err(SourceError::Rejected(reason)) => {
+                        // Not recorded as imported, so the batch is fetched again during the next update
+                        warn!("Skipping batch {} of {}: {}", batch, source.name(), reason);
+                        self.log_update(&format!("{} batch {} rejected: {}", source.name(), batch, reason));
+                        source.finish_batch(batch);
+                    }
+                    Err(err) => {
+                        self.record_failure(format!("Failed downloading batch {} of {}: {}", batch, source.name(), err));
+                        stop.store(true, Ordering::SeqCst);
+                    }
+                };
+
+                self.file_nr += 1;
+                if Self::calculate_progress(self, last_percentage, self.file_nr, self.total_files).is_err() {
+                    error!("Progress calculation is broken");
+                    stop.store(true, Ordering::SeqCst);
+                }
+                if cancel.load(Ordering::SeqCst) && !stop.swap(true, Ordering::SeqCst) {
+                    info!("Update cancelled after {} of {} files", self.file_nr, self.total_files);
+                    self.log_update(&format!("DB update cancelled after {} of {} files", self.file_nr, self.total_files));
+                }
+            }
+        });
+        info!("Done updating DB from {}", source.name());
+    }
+
+    /// Takes batches off the shared list and fetches, verifies and parses them, until none are left
+    /// or one of the stop flags is raised. Failed requests and interrupted downloads are retried
+    /// as long as the retry policy allows.
+    /// Runs on its own thread and sends the results to the thread importing them.
+    fn download_worker(
+        source: &dyn SignatureSource,
+        batches: &[String],
+        next: &AtomicUsize,
+        stop: [&AtomicBool; 2],
+        retry: &RetryPolicy,
+        results: flume::Sender<FetchedBatch>,
+    ) {
+        let stopped = || stop.iter().any(|flag| flag.load(Ordering::SeqCst));
+        while !stopped() {
+            let index = next.fetch_add(1, Ordering::SeqCst);
+            let Some(batch) = batches.get(index) else {
+                break;
+            };
+            let result = retry.run(&format!("batch {} of {}", batch, source.name()), stopped, || {
+                let content = source.fetch_batch(batch)?;
+                let verification = source.verify_batch(batch, &content)?;
+                Ok((verification, source.parse_batch(batch, &content)?))
+            });
+            if result.is_err() && stopped() {
+                return;
+            }
+            if results.send((index, result)).is_err() {
+                break;
+            }
+        }
+    }
 
-                    None => break,
+    /// Fetches the given removal lists of a source and retracts their hashes.
+    /// Lists failing verification are skipped and fetched again during the next update,
+    /// any other failure stops applying the lists of this source.
+    fn update_removals(&mut self, source: &dyn SignatureSource, lists: Vec<String>) {
+        for list in lists {
+            if self.cancelled().is_some() {
+                break;
+            }
+            let result = self.retry.run(
+                &format!("removal list {} of {}", list, source.name()),
+                || self.cancel.load(Ordering::SeqCst),
+                || {
+                    let content = source.fetch_removals(&list)?;
+                    let verification = source.verify_removals(&list, &content)?;
+                    Ok((verification, sources::parse_removal_list(&content)?))
                 },
-                Err(err) => {
-                    if err.status().unwrap_or(StatusCode::NO_CONTENT) == StatusCode::NOT_FOUND {
-                        info!("No more files to download");
+            );
+            match result {
+                Ok((verification, removals)) => {
+                    info!("Removal list {} of {}: {}", list, source.name(), verification);
+                    self.log_update(&format!("{} removal list {}: {}", source.name(), list, verification));
+                    if let Err(err) = self.apply_removals(source.name(), &list, removals) {
+                        self.record_failure(format!("Failed applying removal list {} of {}: {}", list, source.name(), err));
                         break;
                     }
-                    warn!("Retrying download despite error: {}", err);
-                    _retry = true;
                 }
-            };
+                Err(SourceError::Rejected(reason)) => {
+                    warn!("Skipping removal list {} of {}: {}", list, source.name(), reason);
+                    self.log_update(&format!("{} removal list {} rejected: {}", source.name(), list, reason));
+                }
+                Err(err) => {
+                    self.record_failure(format!("Failed downloading removal list {} of {}: {}", list, source.name(), err));
+                    break;
+                }
+            }
         }
-        info!("Done updating DB");
     }
 
-    /// Downloads the specified file and returns its content and file number as a tuple in the form of (file_nr, content).
-    /// Returns None if the file does not exist or if there was an error creating a String from the file's bytes.
+    /// Retracts the hashes of a removal list and records every retraction together with its reason,
+    /// so that the user can audit what was removed. The hashes are removed and the list is recorded as applied
+    /// in a single transaction. Retracted hashes are not imported again, even if a batch still contains them.
+    /// Returns how many of the hashes were in the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the source the list came from
+    /// * `list` - The identifier of the removal list at its source
+    /// * `removals` - The retracted hashes
     ///
     /// # Examples
     ///
     /// ```
-    /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let file = DBOps::download_file(1).unwrap();
-    /// assert!(file.is_some());
+    /// use virus_scanner::backend::sources::parse_removal_list;
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let removals = parse_removal_list(b"93fe4fb85a682907137b0b1051991332,Signed Windows driver").unwrap();
+    /// db_ops.apply_removals("local", "false-positives.removed", removals).unwrap();
     /// ```
-    pub fn download_file(file_nr: i32) -> Result<Option<Vec<(String, String)>>, reqwest::Error> {
-        let url = format!(
-            "https://virusshare.com/hashfiles/VirusShare_{}.md5",
-            format!("{:0>5}", file_nr)
-        );
-        info!("Downloading {url}");
-        let big_tic = time::Instant::now();
-        let file = reqwest::blocking::get(&url)?;
-        let size = file.content_length().unwrap_or(0);
-        let file_as_string = match String::from_utf8(file.bytes()?.to_vec()) {
-            Ok(file_as_string) => file_as_string,
-            Err(err) => {
-                warn!("Failed creating string from bytes: {}", err);
-                return Ok(None);
+    pub fn apply_removals(&mut self, source: &str, list: &str, removals: Vec<Removal>) -> Result<u64, rusqlite::Error> {
+        let transact = self.db_conn.transaction()?;
+        transact.execute(
+            "INSERT OR IGNORE INTO removal_lists(source, name) VALUES (?, ?)",
+            params![source, list],
+        )?;
+        let list_id: i64 = transact.query_row(
+            "SELECT id FROM removal_lists WHERE source = ? AND name = ?",
+            params![source, list],
+            |row| row.get(0),
+        )?;
+        let mut removed = Vec::new();
+        for removal in removals {
+            let Some(blob) = Self::hash_to_blob(&removal.hash) else {
+                debug!("Skipping invalid hash: {}", removal.hash);
+                continue;
+            };
+            let file_nr: Option<i64> = transact
+                .query_row(
+                    "SELECT file_nr FROM signatures WHERE hash = ?",
+                    params![&blob[..]],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(file_nr) = file_nr {
+                transact.execute("DELETE FROM signatures WHERE hash = ?", params![&blob[..]])?;
+                transact.execute("DELETE FROM signature_meta WHERE hash = ?", params![&blob[..]])?;
+                // Keeps the batch in line with the hashes it holds, which the health check compares
+                transact.execute(
+                    "UPDATE batches SET hash_count = hash_count - 1 WHERE id = ? AND hash_count > 0",
+                    params![file_nr],
+                )?;
             }
-        };
-        info!("Parsing file of size {} mb", size as f64 * 0.000001);
-        let big_tuc = time::Instant::now();
-        let lines = file_as_string.lines();
-        if lines.clone().count() == 9 {
-            println!("{file_as_string}");
-            return Ok(None);
-        }
-        let mut hashes: Vec<(String, String)> = Vec::new();
-        for l in lines {
-            if !l.starts_with("#") {
-                hashes.push((l.to_owned(), format!("{}", file_nr.clone())));
+            // The first retraction of a hash is the one kept
+            transact.execute(
+                "INSERT OR IGNORE INTO removals(hash, list, reason, was_present) VALUES (?, ?, ?, ?)",
+                params![&blob[..], list_id, removal.reason, file_nr.is_some()],
+            )?;
+            if file_nr.is_some() {
+                removed.push(removal);
             }
         }
-        let big_toc = time::Instant::now();
-        info!(
-            "=> Downloaded file in {} seconds, Parsing took {} seconds",
-            big_tuc.duration_since(big_tic).as_secs_f64(),
-            big_toc.duration_since(big_tuc).as_secs_f64(),
-        );
-        Ok(Some(hashes))
+        transact.execute(
+            "UPDATE removal_lists SET applied = ? WHERE id = ?",
+            params![Utc::now().to_rfc3339(), list_id],
+        )?;
+        transact.commit()?;
+
+        for removal in &removed {
+            let reason = removal.reason.as_deref().unwrap_or("no reason given");
+            info!("Retracted hash {} ({})", removal.hash, reason);
+            self.log_update(&format!("{} removal list {} retracted {}: {}", source, list, removal.hash, reason));
+        }
+        info!("=> Applied removal list {} of {}, {} hashes removed", list, source, removed.len());
+        Ok(removed.len() as u64)
     }
 
-    /// Inserts the given hashes into the signatures table.
+    /// Records the given batch in the `batches` table and inserts its signatures,
+    /// so that it is not fetched again during the next update.
+    /// Metadata provided by the feed is stored as well, without overwriting what is already known.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the source the batch came from
+    /// * `batch` - The identifier of the batch at its source
+    /// * `signatures` - The signatures contained in the batch
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// use virus_scanner::backend::sources::parse_hash_list;
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let signatures = parse_hash_list(b"93fe4fb85a682907137b0b1051991332,Emotet,high").unwrap();
+    /// db_ops.import_batch("local", "list.md5", signatures).unwrap();
+    /// ```
+    pub fn import_batch(&mut self, source: &str, batch: &str, signatures: Vec<Signature>) -> Result<(), rusqlite::Error> {
+        // A single transaction, so an interrupted import never records a batch without its hashes
+        let transact = self.db_conn.transaction()?;
+        transact.execute(
+            "INSERT OR IGNORE INTO batches(source, name) VALUES (?, ?)",
+            params![source, batch],
+        )?;
+        let batch_id: i64 = transact.query_row(
+            "SELECT id FROM batches WHERE source = ? AND name = ?",
+            params![source, batch],
+            |row| row.get(0),
+        )?;
+        Self::insert_metadata(&transact, &signatures)?;
+        let new_hashes = Self::insert_into(
+            &transact,
+            signatures
+                .into_iter()
+                .map(|signature| (signature.hash, batch_id.to_string()))
+                .collect(),
+        )?;
+        transact.execute(
+            "UPDATE batches SET hash_count = hash_count + ?, imported = ? WHERE id = ?",
+            params![new_hashes.len() as i64, Utc::now().to_rfc3339(), batch_id],
+        )?;
+        transact.commit()?;
+        self.add_to_bloom_filter(&new_hashes);
+        Ok(())
+    }
+
+    /// Stores the metadata of the given signatures in the `signature_meta` table.
+    /// Fields already known are kept, so the first feed naming a malware wins.
+    fn insert_metadata(transact: &Transaction, signatures: &[Signature]) -> Result<(), rusqlite::Error> {
+        let mut stmt = transact.prepare(
+            "INSERT INTO signature_meta(hash, name, severity, first_seen) VALUES (?, ?, ?, ?)
+                ON CONFLICT(hash) DO UPDATE SET
+                    name = COALESCE(name, excluded.name),
+                    severity = COALESCE(severity, excluded.severity),
+                    first_seen = COALESCE(first_seen, excluded.first_seen)",
+        )?;
+        for signature in signatures.iter().filter(|signature| signature.has_metadata()) {
+            if let Some(blob) = Self::hash_to_blob(&signature.hash) {
+                stmt.execute(params![
+                    &blob[..],
+                    signature.name,
+                    signature.severity.map(|severity| severity.level()),
+                    signature.first_seen
+                ])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts the given hashes into the signatures table and returns how many of them were new.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let mut db_ops = DBOps::new("signatures.db").unwrap();
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
     /// db_ops.insert_hashes(vec![("abcdef".to_owned(), "1".to_owned())]).unwrap();
     /// ```
-    pub fn insert_hashes(&mut self, hashes: Vec<(String, String)>) -> Result<(), rusqlite::Error> {
-        info!("Inserting File {}", hashes[0].1);
+    pub fn insert_hashes(&mut self, hashes: Vec<(String, String)>) -> Result<u64, rusqlite::Error> {
+        if hashes.is_empty() {
+            return Ok(0);
+        }
         let transact = match self.db_conn.transaction() {
             Ok(transact) => transact,
             Err(err) => return Err(err),
         };
+        let new_hashes = Self::insert_into(&transact, hashes)?;
+        transact.commit()?;
+        self.add_to_bloom_filter(&new_hashes);
+        Ok(new_hashes.len() as u64)
+    }
+
+    /// Inserts the given hashes within the given transaction and returns the ones that were new.
+    /// Only duplicates are skipped, any other error aborts, so that a failing database is not taken for a complete one.
+    fn insert_into(transact: &Transaction, hashes: Vec<(String, String)>) -> Result<Vec<String>, rusqlite::Error> {
+        if let Some((_, file_nr)) = hashes.first() {
+            info!("Inserting File {}", file_nr);
+        }
         let big_tic = time::Instant::now();
         let mut inserted = 0;
         let mut skipped = 0;
+        let mut new_hashes = Vec::new();
         for (hash, file_nr) in hashes {
+            let blob = match Self::hash_to_blob(&hash) {
+                Some(blob) => blob,
+                None => {
+                    skipped += 1;
+                    debug!("Skipping invalid hash: {}", hash);
+                    continue;
+                }
+            };
+            // Retracted hashes are skipped like duplicates
             match transact.execute(
-                "INSERT INTO signatures(hash, file_nr) VALUES (?, ?)",
-                [hash.clone(), file_nr.clone()],
+                "INSERT INTO signatures(hash, file_nr)
+                    SELECT ?1, ?2 WHERE NOT EXISTS (SELECT 1 FROM removals WHERE hash = ?1)",
+                params![&blob[..], file_nr.parse::<i64>().unwrap_or(0)],
             ) {
+                Ok(0) => {
+                    skipped += 1;
+                    debug!("Skipping retracted hash: {}", hash);
+                }
                 Ok(_) => {
                     inserted += 1;
+                    new_hashes.push(hash);
                     // debug!("[File {file_nr}]: Inserted {}", hash)
                 }
-                Err(err) => {
+                Err(rusqlite::Error::SqliteFailure(err, _)) if err.code == ErrorCode::ConstraintViolation => {
                     skipped += 1;
-                    debug!(
-                        "Continuing after trying to insert hash and receiving: {}",
-                        err
-                    )
+                    debug!("Skipping duplicate hash: {}", hash);
+                }
+                Err(err) => {
+                    error!("Failed inserting hash {}: {}", hash, err);
+                    return Err(err);
                 }
             };
         }
@@ -258,162 +897,175 @@ impl DBOps {
             skipped,
             big_toc.duration_since(big_tic).as_secs_f64()
         );
-        transact.commit()?;
-        Ok(())
+        Ok(new_hashes)
     }
 
-    /// Returns true or false depending on if the given hash gets found in the database
+    /// Keeps the prefilter in sync, so that new hashes are not reported as definitely absent.
+    /// Hashes are stored case-insensitively, so the filter always holds their lowercase form.
+    fn add_to_bloom_filter(&mut self, new_hashes: &[String]) {
+        if let Some(bloom) = self.bloom.write().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            for hash in new_hashes {
+                bloom.insert(&hash.to_lowercase());
+            }
+        }
+    }
+
+    /// Returns true or false depending on if the given hash gets found in the database.
+    /// Hashes rejected by the bloom filter are reported as absent without querying SQLite.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
     /// assert_eq!(db_ops.hash_exists("abcd1234").unwrap(), false);
     /// ```
     pub fn hash_exists(&self, hash_str: &str) -> Result<Option<bool>, rusqlite::Error> {
-        info!("Now scanning: {}", hash_str);
+        debug!("Now scanning: {}", hash_str);
+
+        if let Some(bloom) = self.bloom.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            if !bloom.might_contain(&hash_str.to_lowercase()) {
+                return Ok(Some(false));
+            }
+        }
+        let blob = match Self::hash_to_blob(hash_str) {
+            Some(blob) => blob,
+            None => return Ok(Some(false)),
+        };
 
         let mut stmt = self
             .db_conn
             .prepare("SELECT COUNT(*) FROM signatures WHERE hash = ?")?;
-        let count: i64 = stmt.query_row(params![hash_str], |row| row.get(0))?;
+        let count: i64 = stmt.query_row(params![&blob[..]], |row| row.get(0))?;
 
         Ok(Some(count > 0))
     }
 
-    /// Returns the number of hashes in the `signatures` table.
+    /// Looks up many hashes at once and returns the ones found in the database,
+    /// mapped to what is known about them: malware name, severity, source and when they were added.
+    /// Hashes are first filtered through the bloom filter and then queried in chunks.
     ///
-    /// # Examples
+    /// # Arguments
     ///
-    /// ```
-    /// use rusqlite::Connection;
-    /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert_eq!(db_ops.count_hashes().unwrap(), 0);
-    /// ```
-    pub fn count_hashes(&self) -> Result<u64, rusqlite::Error> {
-        let mut stmt = self.db_conn.prepare("SELECT COUNT(hash) FROM signatures")?;
-        let count: i64 = stmt.query_row([], |row| row.get(0))?;
-        Ok(count as u64)
-    }
-
-    /// Removes the specified hash from the `signatures` table.
+    /// * `hashes` - The hashes to look up, in hexadecimal representation
     ///
     /// # Examples
     ///
     /// ```
-    /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert!(db_ops._remove_hash("abcd1234").is_ok());
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let found = db_ops.hashes_exist(&["abcd1234".to_owned()]).unwrap();
+    /// assert!(found.is_empty());
     /// ```
-    pub fn _remove_hash(&self, hash_str: &str) -> Result<(), rusqlite::Error> {
-        self.db_conn
-            .execute("DELETE FROM signatures WHERE hash = ?", &[hash_str])?;
-        Ok(())
+    pub fn hashes_exist(&self, hashes: &[String]) -> Result<HashMap<String, SignatureInfo>, rusqlite::Error> {
+        debug!("Now scanning batch of {} hashes", hashes.len());
+        let bloom = self.bloom.read().unwrap_or_else(PoisonError::into_inner);
+        let candidates: Vec<[u8; 16]> = hashes
+            .iter()
+            .filter(|hash| match bloom.as_ref() {
+                Some(bloom) => bloom.might_contain(&hash.to_lowercase()),
+                None => true,
+            })
+            .filter_map(|hash| Self::hash_to_blob(hash))
+            .collect();
+        drop(bloom);
+
+        let mut found = HashMap::new();
+        for chunk in candidates.chunks(LOOKUP_CHUNK_SIZE) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut stmt = self.db_conn.prepare(&format!(
+                "SELECT s.hash, m.name, m.severity, m.first_seen, b.source, b.imported
+                    FROM signatures s
+                    LEFT JOIN signature_meta m ON m.hash = s.hash
+                    LEFT JOIN batches b ON b.id = s.file_nr
+                    WHERE s.hash IN ({})",
+                placeholders
+            ))?;
+            let mut rows = stmt.query(params_from_iter(chunk.iter().map(|blob| &blob[..])))?;
+            while let Some(row) = rows.next()? {
+                let hash: Vec<u8> = row.get(0)?;
+                let severity: Option<i64> = row.get(2)?;
+                found.insert(
+                    Self::blob_to_hash(&hash),
+                    SignatureInfo {
+                        name: row.get(1)?,
+                        severity: severity.and_then(Severity::from_level),
+                        first_seen: row.get(3)?,
+                        source: row.get(4)?,
+                        added: row.get(5)?,
+                    },
+                );
+            }
+        }
+        Ok(found)
     }
 
-    /// Returns the file numbers of the files that are present online.
+    /// Returns the number of hashes in the `signatures` table.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert!(db_ops.get_file_list() > 0);
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// assert_eq!(db_ops.count_hashes().unwrap(), 0);
     /// ```
-    pub fn get_file_list(&self) -> i32 {
-        let mut curr_fn = 0;
-        let mut err_retry = false;
-        // Loops in steps of +10
-        loop {
-            // If file exists
-            if match Self::file_exists(curr_fn) {
-                Ok(val) => val,
-                Err(err) => {
-                    warn!("Retrying because of error: {err}");
-                    err_retry = true;
-                    false
-                }
-            } {
-                curr_fn += 10;
-            } else {
-                //if err is true retry otherwise break
-                if err_retry {
-                    err_retry = false;
-                } else {
-                    break;
-                }
-            }
-        }
-        curr_fn -= 1;
-        //-1 loop
-        loop {
-            //if file exists
-            if !match Self::file_exists(curr_fn) {
-                Ok(val) => val,
-                Err(err) => {
-                    warn!("Retrying because of error: {err}");
-                    err_retry = true;
-                    false
-                }
-            } {
-                curr_fn -= 1;
-            } else {
-                //if err is true retry otherwise break
-                if err_retry {
-                    err_retry = false;
-                } else {
-                    break;
-                }
-            }
-        }
+    pub fn count_hashes(&self) -> Result<u64, rusqlite::Error> {
+        let mut stmt = self.db_conn.prepare("SELECT COUNT(*) FROM signatures")?;
+        let count: i64 = stmt.query_row([], |row| row.get(0))?;
+        Ok(count as u64)
+    }
 
-        curr_fn
+    /// Returns the generation of the database, which changes with every insert or delete of a signature
+    pub fn generation(&self) -> Result<u64, rusqlite::Error> {
+        let generation: i64 = self.db_conn.query_row("SELECT value FROM db_generation", [], |row| row.get(0))?;
+        Ok(generation as u64)
     }
 
-    /// Returns whether the file with the specified file number exists online.
+    /// Removes the specified hash from the `signatures` table, together with its metadata and any retraction of it.
+    /// The batch it was imported with counts one hash less.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert!(db_ops.file_exists(123).unwrap_or(false));
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// assert!(db_ops._remove_hash("abcd1234").is_ok());
     /// ```
-    pub fn file_exists(file_nr: i32) -> Result<bool, reqwest::Error> {
-        let url = format!(
-            "https://virusshare.com/hashfiles/VirusShare_{}.md5",
-            format!("{:0>5}", file_nr)
-        );
-        let client = reqwest::blocking::Client::new();
-        info!("Checking if file {file_nr} exists...");
-        let code = client.get(url).send()?;
-        if code.status() == StatusCode::NOT_FOUND {
-            Ok(false)
-        } else {
-            Ok(true)
+    pub fn _remove_hash(&self, hash_str: &str) -> Result<(), rusqlite::Error> {
+        if let Some(blob) = Self::hash_to_blob(hash_str) {
+            // Keeps the batch in line with the hashes it holds, which the health check compares
+            self.db_conn.execute(
+                "UPDATE batches SET hash_count = hash_count - 1
+                 WHERE id = (SELECT file_nr FROM signatures WHERE hash = ?) AND hash_count > 0",
+                params![&blob[..]],
+            )?;
+            self.db_conn
+                .execute("DELETE FROM signatures WHERE hash = ?", params![&blob[..]])?;
+            self.db_conn
+                .execute("DELETE FROM signature_meta WHERE hash = ?", params![&blob[..]])?;
+            self.db_conn
+                .execute("DELETE FROM removals WHERE hash = ?", params![&blob[..]])?;
         }
+        Ok(())
     }
 
-    /// Returns a vector of the file numbers of the files that are present in the `signatures` table.
+    /// Returns the identifiers of the batches of the given source that are present in the `batches` table.
     ///
     /// # Examples
     ///
     /// ```
     /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert!(db_ops.get_db_files().is_some());
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// assert!(db_ops.get_db_files("virusshare").is_some());
     /// ```
-    pub fn get_db_files(&self) -> Option<Vec<i32>> {
+    pub fn get_db_files(&self, source: &str) -> Option<Vec<String>> {
         let mut stmt = match self
             .db_conn
-            .prepare("SELECT DISTINCT file_nr FROM signatures")
+            .prepare("SELECT name FROM batches WHERE source = ?")
         {
             Ok(stmt) => stmt,
             Err(err) => {
@@ -421,7 +1073,7 @@ impl DBOps {
                 return None;
             }
         };
-        let mut rows = match stmt.query(params![]) {
+        let mut rows = match stmt.query(params![source]) {
             Ok(stmt) => stmt,
             Err(err) => {
                 warn!("Failed preparing statement: {err}");
@@ -429,7 +1081,7 @@ impl DBOps {
             }
         };
 
-        let mut file_nr_values = Vec::new();
+        let mut batch_names = Vec::new();
         loop {
             match rows.next() {
                 Ok(row) => {
@@ -437,17 +1089,14 @@ impl DBOps {
                         Some(row) => row,
                         None => break,
                     };
-                    let value = match tmp.get(0) {
-                        Ok(value) => {
-                            let a: String = value;
-                            a.parse::<i32>().unwrap_or(0)
-                        }
+                    let value: String = match tmp.get(0) {
+                        Ok(value) => value,
                         Err(err) => {
                             warn!("Failed getting value: {err}");
                             break;
                         }
                     };
-                    file_nr_values.push(value);
+                    batch_names.push(value);
                 }
                 Err(err) => {
                     warn!("Failed getting row: {err}");
@@ -455,28 +1104,277 @@ impl DBOps {
                 }
             }
         }
-        Some(file_nr_values)
+        Some(batch_names)
     }
 
-    /// Returns a list of file numbers for which there are no corresponding hashes in the signatures table.
+    /// Returns the batches offered by the given source that have not been imported yet.
+    /// Listing the batches is retried as long as the retry policy allows.
     ///
     /// # Examples
     ///
     /// ```
-    /// use rusqlite::Connection;
     /// use virus_scanner::backend::db_ops::DBOps;
-    /// let db_ops = DBOps::new("signatures.db").unwrap();
-    /// assert!(db_ops.get_diff_file().len() >= 0);
+    /// use virus_scanner::backend::sources::virusshare::{VirusShareSource, DEFAULT_MIRROR_URL};
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let source = VirusShareSource::new(reqwest::blocking::Client::new(), DEFAULT_MIRROR_URL, "", "", &AppPaths::platform().cache_dir);
+    /// assert!(db_ops.get_diff_file(&source).is_ok());
     /// ```
-    pub fn get_diff_file(&self) -> Vec<i32> {
-        let mut web_files: Vec<i32> = (0..=self.get_file_list()).collect();
-        //let mut web_files: Vec<i32> = (0..=20).collect();
-        let db_files = match self.get_db_files() {
+    pub fn get_diff_file(&self, source: &dyn SignatureSource) -> Result<Vec<String>, SourceError> {
+        let mut web_files = self.retry.run(
+            &format!("listing batches of {}", source.name()),
+            || self.cancel.load(Ordering::SeqCst),
+            || source.list_batches(),
+        )?;
+        let db_files = match self.get_db_files(source.name()) {
             Some(db_files) => db_files,
             None => Vec::new(),
         };
         web_files.retain(|x| !db_files.contains(x));
-        web_files
+        Ok(web_files)
+    }
+
+    /// Returns the removal lists offered by the given source that have not been applied yet.
+    /// Listing them is retried as long as the retry policy allows.
+    /// If the applied lists can not be read, all lists are returned, as applying a list again changes nothing.
+    pub fn get_diff_removals(&self, source: &dyn SignatureSource) -> Result<Vec<String>, SourceError> {
+        let mut lists = self.retry.run(
+            &format!("listing removal lists of {}", source.name()),
+            || self.cancel.load(Ordering::SeqCst),
+            || source.list_removals(),
+        )?;
+        let applied = self
+            .db_conn
+            .prepare("SELECT name FROM removal_lists WHERE source = ? AND applied IS NOT NULL")
+            .and_then(|mut stmt| {
+                stmt.query_map(params![source.name()], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .unwrap_or_else(|err| {
+                warn!("Failed reading applied removal lists: {err}");
+                Vec::new()
+            });
+        lists.retain(|list| !applied.contains(list));
+        Ok(lists)
+    }
+
+    /// Returns every retracted hash together with the list that retracted it and why, oldest first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// for removal in db_ops.get_removals().unwrap() {
+    ///     println!("{} retracted by {}: {:?}", removal.hash, removal.list, removal.reason);
+    /// }
+    /// ```
+    pub fn get_removals(&self) -> Result<Vec<RemovalInfo>, rusqlite::Error> {
+        let mut stmt = self.db_conn.prepare(
+            "SELECT r.hash, r.reason, l.source, l.name, l.applied, r.was_present
+                FROM removals r
+                JOIN removal_lists l ON l.id = r.list
+                ORDER BY l.applied, l.source, l.name, r.hash",
+        )?;
+        let removals = stmt
+            .query_map([], |row| {
+                let hash: Vec<u8> = row.get(0)?;
+                Ok(RemovalInfo {
+                    hash: Self::blob_to_hash(&hash),
+                    reason: row.get(1)?,
+                    source: row.get(2)?,
+                    list: row.get(3)?,
+                    applied: row.get(4)?,
+                    was_present: row.get(5)?,
+                })
+            })?
+            .collect();
+        removals
+    }
+
+    /// Returns all batches in the `batches` table, grouped by source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// assert!(db_ops.get_batches().is_ok());
+    /// ```
+    pub fn get_batches(&self) -> Result<Vec<BatchInfo>, rusqlite::Error> {
+        Self::read_batches(&self.db_conn)
+    }
+
+    /// Reads the `batches` table of the given connection, which may also be a snapshot of the database
+    pub(crate) fn read_batches(conn: &Connection) -> Result<Vec<BatchInfo>, rusqlite::Error> {
+        let mut stmt = conn.prepare(
+            "SELECT source, name, hash_count, imported FROM batches ORDER BY source, id",
+        )?;
+        let batches = stmt
+            .query_map([], |row| {
+                Ok(BatchInfo {
+                    source: row.get(0)?,
+                    name: row.get(1)?,
+                    hash_count: row.get::<_, i64>(2)? as u64,
+                    imported: row.get(3)?,
+                })
+            })?
+            .collect();
+        batches
+    }
+
+    /// Writes a consistent, vacuumed copy of the database to the given path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// db_ops.snapshot(Path::new("/tmp/signatures-copy.db")).unwrap();
+    /// ```
+    pub fn snapshot(&self, target: &Path) -> Result<(), rusqlite::Error> {
+        info!("Writing database snapshot to {}", target.display());
+        self.db_conn
+            .execute("VACUUM INTO ?", params![target.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Adds all batches and signatures of another database to this one.
+    /// Batches that already exist are kept, hashes already present are skipped.
+    /// The retracted hashes of both databases stay out, including those only the other database knows about.
+    /// Returns the amount of hashes in the database afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let mut db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// db_ops.merge_from(Path::new("/tmp/signatures-copy.db")).unwrap();
+    /// ```
+    pub fn merge_from(&mut self, other: &Path) -> Result<u64, rusqlite::Error> {
+        info!("Merging signatures from {}", other.display());
+        let big_tic = time::Instant::now();
+        let hashes_before = self.count_hashes()?;
+        self.db_conn
+            .execute("ATTACH DATABASE ? AS other", params![other.to_string_lossy()])?;
+        let merged = (|| {
+            let transact = self.db_conn.transaction()?;
+            transact.execute(
+                "INSERT OR IGNORE INTO main.batches(source, name, hash_count, imported)
+                    SELECT source, name, 0, imported FROM other.batches",
+                [],
+            )?;
+            // Removals come first, so their hashes are removed from this database and not merged back in
+            transact.execute(
+                "INSERT OR IGNORE INTO main.removal_lists(source, name, applied)
+                    SELECT source, name, applied FROM other.removal_lists",
+                [],
+            )?;
+            transact.execute(
+                "INSERT OR IGNORE INTO main.removals(hash, list, reason, was_present)
+                    SELECT r.hash, m.id, r.reason, r.was_present OR r.hash IN (SELECT hash FROM main.signatures)
+                    FROM other.removals r
+                    JOIN other.removal_lists o ON r.list = o.id
+                    JOIN main.removal_lists m ON m.source = o.source AND m.name = o.name",
+                [],
+            )?;
+            // Keeps the batches in line with the hashes they hold, which the health check compares
+            transact.execute(
+                "UPDATE main.batches SET hash_count = hash_count -
+                    (SELECT COUNT(*) FROM main.signatures s JOIN main.removals r ON r.hash = s.hash
+                        WHERE s.file_nr = main.batches.id)
+                    WHERE id IN (SELECT s.file_nr FROM main.signatures s JOIN main.removals r ON r.hash = s.hash)",
+                [],
+            )?;
+            let retracted = transact.execute(
+                "DELETE FROM main.signatures WHERE hash IN (SELECT hash FROM main.removals)",
+                [],
+            )?;
+            transact.execute(
+                "DELETE FROM main.signature_meta WHERE hash IN (SELECT hash FROM main.removals)",
+                [],
+            )?;
+            if retracted > 0 {
+                info!("Retracted {} hashes removed in the merged database", retracted);
+            }
+            // Batch ids differ between databases, so every signature is moved to the local id of its batch
+            transact.execute(
+                "INSERT OR IGNORE INTO main.signatures(hash, file_nr)
+                    SELECT s.hash, m.id FROM other.signatures s
+                    JOIN other.batches o ON s.file_nr = o.id
+                    JOIN main.batches m ON m.source = o.source AND m.name = o.name
+                    WHERE s.hash NOT IN (SELECT hash FROM main.removals)",
+                [],
+            )?;
+            transact.execute(
+                "INSERT INTO main.signature_meta(hash, name, severity, first_seen)
+                    SELECT hash, name, severity, first_seen FROM other.signature_meta
+                    WHERE hash NOT IN (SELECT hash FROM main.removals)
+                    ON CONFLICT(hash) DO UPDATE SET
+                        name = COALESCE(name, excluded.name),
+                        severity = COALESCE(severity, excluded.severity),
+                        first_seen = COALESCE(first_seen, excluded.first_seen)",
+                [],
+            )?;
+            transact.execute(
+                "UPDATE main.batches SET hash_count =
+                    (SELECT COUNT(*) FROM main.signatures s WHERE s.file_nr = main.batches.id)
+                    WHERE id IN (SELECT m.id FROM main.batches m
+                        JOIN other.batches o ON m.source = o.source AND m.name = o.name)",
+                [],
+            )?;
+            transact.commit()
+        })();
+        self.db_conn.execute("DETACH DATABASE other", [])?;
+        merged?;
+
+        self.rebuild_bloom_filter()?;
+        let hashes_after = self.count_hashes()?;
+        let big_toc = time::Instant::now();
+        info!(
+            "=> Merged {} new hashes in {} seconds",
+            hashes_after.saturating_sub(hashes_before),
+            big_toc.duration_since(big_tic).as_secs_f64()
+        );
+        Ok(hashes_after)
+    }
+
+    /// Replaces the database with the given file and reopens it.
+    /// The bloom filter is removed, so that it gets rebuilt for the new signatures.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use virus_scanner::backend::db_ops::DBOps;
+    /// let db_ops = DBOps::new(&AppPaths::platform(), None).unwrap();
+    /// let db_ops = db_ops.replace_with(Path::new("/tmp/signatures-copy.db")).unwrap();
+    /// ```
+    pub fn replace_with(self, replacement: &Path) -> Result<DBOps, String> {
+        info!("Replacing database with {}", replacement.display());
+        let DBOps {
+            paths,
+            db_conn,
+            db_file,
+            bloom_file,
+            sender,
+            ..
+        } = self;
+        db_conn.close().map_err(|(_, err)| err.to_string())?;
+        // The log of the replaced database would otherwise be applied to the new one
+        for suffix in ["-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", db_file, suffix));
+        }
+
+        // Copied next to the database first, so a failed copy never leaves a half written database behind
+        let staged = format!("{}.restore", db_file);
+        fs::copy(replacement, &staged).map_err(|err| err.to_string())?;
+        fs::rename(&staged, &db_file).map_err(|err| err.to_string())?;
+        if let Err(err) = fs::remove_file(&bloom_file) {
+            debug!("No bloom filter removed: {err}");
+        }
+        DBOps::new(&paths, sender).map_err(|err| err.to_string())
     }
 
     fn calculate_progress(
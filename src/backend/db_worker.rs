@@ -0,0 +1,459 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc, Mutex, PoisonError, RwLock},
+    thread,
+};
+
+use flume::{Receiver, Sender};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    app_paths::AppPaths,
+    db_backup,
+    db_health::{self, HealthReport, RepairReport},
+    db_ops::{DBOps, SharedBloom, UpdateCheck},
+    file_log::UpdateLog,
+    signature::{RemovalInfo, Signature, SignatureInfo},
+    sources,
+};
+
+/// Amount of threads answering reads, so that a scan keeps going while e.g. the settings read the database
+const READERS: usize = 2;
+
+/// Where the answer to a request is sent to
+type Reply<T> = Sender<Result<T, String>>;
+
+/// Totals of the signature database
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DBStats {
+    /// Amount of hashes in the database
+    pub hash_count: u64,
+    /// Amount of imported batches, over all sources
+    pub batch_count: usize,
+}
+
+/// A request to the database worker. Reads are answered by any of the reader threads,
+/// writes are applied one after another by the writer thread.
+enum Request {
+    Lookup {
+        hashes: Vec<String>,
+        reply: Reply<HashMap<String, SignatureInfo>>,
+    },
+    Stats {
+        reply: Reply<DBStats>,
+    },
+    Removals {
+        reply: Reply<Vec<RemovalInfo>>,
+    },
+    CheckUpdates {
+        reply: Reply<UpdateCheck>,
+    },
+    CheckHealth {
+        thorough: bool,
+        reply: Reply<HealthReport>,
+    },
+    Export {
+        path: PathBuf,
+        reply: Reply<u64>,
+    },
+    InsertBatch {
+        source: String,
+        name: String,
+        signatures: Vec<Signature>,
+        reply: Reply<()>,
+    },
+    Update {
+        progress: Option<Sender<f32>>,
+        cancel: Option<Arc<AtomicBool>>,
+        update_log: Option<UpdateLog>,
+        reply: Reply<u64>,
+    },
+    Import {
+        path: PathBuf,
+        progress: Option<Sender<f32>>,
+        cancel: Option<Arc<AtomicBool>>,
+        reply: Reply<u64>,
+    },
+    Restore {
+        path: PathBuf,
+        merge: bool,
+        reply: Reply<u64>,
+    },
+    Repair {
+        reply: Reply<RepairReport>,
+    },
+}
+
+/// State shared by the writer and the reader threads
+struct Shared {
+    /// Where the database lives
+    paths: AppPaths,
+    /// Held for reading while a request uses the database file, and for writing while the file is replaced
+    gate: RwLock<()>,
+    /// The bloom filter of the open database, None until the database was opened or after it was replaced
+    bloom: Mutex<Option<SharedBloom>>,
+    /// The connection of each reader thread, kept open between requests and closed while the database is replaced
+    readers: Vec<Mutex<Option<DBOps>>>,
+}
+
+impl Shared {
+    /// Opens a connection to the database. The first one migrates the schema and loads the bloom filter,
+    /// all further ones share it.
+    fn connect(&self) -> Result<DBOps, String> {
+        let mut bloom = self.bloom.lock().unwrap_or_else(PoisonError::into_inner);
+        match bloom.as_ref() {
+            Some(shared) => DBOps::open_shared(&self.paths, shared.clone()).map_err(|err| err.to_string()),
+            None => {
+                let db_ops = DBOps::new(&self.paths, None).map_err(|err| err.to_string())?;
+                *bloom = Some(db_ops.shared_bloom());
+                Ok(db_ops)
+            }
+        }
+    }
+
+    /// Forgets the open database and closes the connections of the readers, so that the next request opens it anew.
+    /// Only called while holding the gate for writing, so no reader is using its connection.
+    fn reset(&self) {
+        *self.bloom.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        for reader in &self.readers {
+            *reader.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        }
+    }
+
+    /// Runs a read on the connection of the given reader thread, opening it if needed
+    fn read<T>(&self, reader: usize, read: impl FnOnce(&DBOps) -> Result<T, String>) -> Result<T, String> {
+        let mut connection = self.readers[reader].lock().unwrap_or_else(PoisonError::into_inner);
+        if connection.is_none() {
+            *connection = Some(self.connect()?);
+        }
+        read(connection.as_ref().expect("Connection was just opened"))
+    }
+}
+
+/// Handle to the database worker, which owns every connection to the signature database.
+/// Writes are serialized on a single thread, while reads run concurrently on their own threads,
+/// so a scan can safely run while an update is in progress. The worker stops once every handle is dropped.
+#[derive(Debug, Clone)]
+pub struct DBHandle {
+    paths: AppPaths,
+    reads: Sender<Request>,
+    writes: Sender<Request>,
+}
+
+impl DBHandle {
+    /// Starts the worker threads. The database is opened by the first request that needs it.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Where the database and everything belonging to it is kept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let db = DBHandle::spawn(AppPaths::platform());
+    /// let stats = db.stats().await.unwrap();
+    /// println!("{} hashes in database", stats.hash_count);
+    /// ```
+    pub fn spawn(paths: AppPaths) -> Self {
+        let shared = Arc::new(Shared {
+            paths: paths.clone(),
+            gate: RwLock::new(()),
+            bloom: Mutex::new(None),
+            readers: (0..READERS).map(|_| Mutex::new(None)).collect(),
+        });
+        let (writes, write_queue) = flume::unbounded();
+        let (reads, read_queue) = flume::unbounded();
+
+        let writer_shared = shared.clone();
+        thread::Builder::new()
+            .name(String::from("db-writer"))
+            .spawn(move || write_loop(&writer_shared, write_queue))
+            .expect("Failed to start database writer");
+        for nr in 0..READERS {
+            let reader_shared = shared.clone();
+            let read_queue = read_queue.clone();
+            thread::Builder::new()
+                .name(format!("db-reader-{}", nr))
+                .spawn(move || read_loop(&reader_shared, nr, read_queue))
+                .expect("Failed to start database reader");
+        }
+        DBHandle { paths, reads, writes }
+    }
+
+    /// Returns the locations the worker was started with, which the rest of the application uses as well
+    pub fn paths(&self) -> &AppPaths {
+        &self.paths
+    }
+
+    /// Looks up many hashes at once and returns the ones found in the database, see [`DBOps::hashes_exist`]
+    pub async fn lookup(&self, hashes: Vec<String>) -> Result<HashMap<String, SignatureInfo>, String> {
+        answer(self.send(&self.reads, |reply| Request::Lookup { hashes, reply })).await
+    }
+
+    /// Same as [`DBHandle::lookup`], for code that runs outside of an async context like the scanner
+    pub fn lookup_blocking(&self, hashes: Vec<String>) -> Result<HashMap<String, SignatureInfo>, String> {
+        self.send(&self.reads, |reply| Request::Lookup { hashes, reply })
+            .recv()
+            .unwrap_or_else(|_| Err(stopped()))
+    }
+
+    /// Returns the amount of hashes and batches in the database
+    pub async fn stats(&self) -> Result<DBStats, String> {
+        answer(self.send(&self.reads, |reply| Request::Stats { reply })).await
+    }
+
+    /// Returns every retracted hash, see [`DBOps::get_removals`]
+    pub async fn removals(&self) -> Result<Vec<RemovalInfo>, String> {
+        answer(self.send(&self.reads, |reply| Request::Removals { reply })).await
+    }
+
+    /// Checks how many signature files an update would download, see [`DBOps::check_updates`]
+    pub async fn check_updates(&self) -> Result<UpdateCheck, String> {
+        answer(self.send(&self.reads, |reply| Request::CheckUpdates { reply })).await
+    }
+
+    /// Checks the database for corruption, see [`db_health::check`]
+    pub async fn check_health(&self, thorough: bool) -> Result<HealthReport, String> {
+        answer(self.send(&self.reads, |reply| Request::CheckHealth { thorough, reply })).await
+    }
+
+    /// Exports the database to a backup at the given path and returns the amount of exported hashes
+    pub async fn export(&self, path: PathBuf) -> Result<u64, String> {
+        answer(self.send(&self.reads, |reply| Request::Export { path, reply })).await
+    }
+
+    /// Imports the signatures of a single batch, see [`DBOps::import_batch`]
+    pub async fn insert_batch(&self, source: &str, name: &str, signatures: Vec<Signature>) -> Result<(), String> {
+        answer(self.send(&self.writes, |reply| Request::InsertBatch {
+            source: source.to_owned(),
+            name: name.to_owned(),
+            signatures,
+            reply,
+        }))
+        .await
+    }
+
+    /// Updates the database from all configured sources and returns the amount of hashes afterwards.
+    /// A cancelled or failed update is reported as error, everything imported until then is kept.
+    pub async fn update(
+        &self,
+        progress: Option<Sender<f32>>,
+        cancel: Option<Arc<AtomicBool>>,
+        update_log: Option<UpdateLog>,
+    ) -> Result<u64, String> {
+        answer(self.send(&self.writes, |reply| Request::Update {
+            progress,
+            cancel,
+            update_log,
+            reply,
+        }))
+        .await
+    }
+
+    /// Imports the signatures at the given path, see [`sources::open_import`]
+    pub async fn import(
+        &self,
+        path: PathBuf,
+        progress: Option<Sender<f32>>,
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<u64, String> {
+        answer(self.send(&self.writes, |reply| Request::Import {
+            path,
+            progress,
+            cancel,
+            reply,
+        }))
+        .await
+    }
+
+    /// Verifies the backup at the given path and then replaces the database with it, or merges it into the database.
+    /// Returns the amount of hashes afterwards.
+    pub async fn restore(&self, path: PathBuf, merge: bool) -> Result<u64, String> {
+        answer(self.send(&self.writes, |reply| Request::Restore { path, merge, reply })).await
+    }
+
+    /// Rebuilds the database from its readable rows, see [`db_health::repair`]
+    pub async fn repair(&self) -> Result<RepairReport, String> {
+        answer(self.send(&self.writes, |reply| Request::Repair { reply })).await
+    }
+
+    /// Queues a request and returns where its answer will arrive
+    fn send<T>(&self, queue: &Sender<Request>, request: impl FnOnce(Reply<T>) -> Request) -> Receiver<Result<T, String>> {
+        let (reply, answer) = flume::bounded(1);
+        if queue.send(request(reply)).is_err() {
+            error!("{}", stopped());
+        }
+        answer
+    }
+}
+
+/// Waits for the answer to a request
+async fn answer<T>(answer: Receiver<Result<T, String>>) -> Result<T, String> {
+    answer.recv_async().await.unwrap_or_else(|_| Err(stopped()))
+}
+
+/// The error reported if the worker is gone, e.g. because it panicked
+fn stopped() -> String {
+    String::from("The database worker stopped")
+}
+
+/// Sends an answer, a requester that stopped waiting is no error
+fn reply<T>(reply: Reply<T>, result: Result<T, String>) {
+    if let Err(err) = &result {
+        error!("{err}");
+    }
+    let _ = reply.send(result);
+}
+
+/// Answers reads on the connection of this reader, which is kept open until the database is replaced
+fn read_loop(shared: &Shared, reader: usize, queue: Receiver<Request>) {
+    for request in queue.iter() {
+        let _gate = shared.gate.read().unwrap_or_else(PoisonError::into_inner);
+        match request {
+            Request::Lookup { hashes, reply: to } => reply(
+                to,
+                shared.read(reader, |db_ops| db_ops.hashes_exist(&hashes).map_err(|err| err.to_string())),
+            ),
+            Request::Stats { reply: to } => reply(
+                to,
+                shared.read(reader, |db_ops| {
+                    Ok(DBStats {
+                        hash_count: db_ops.count_hashes().map_err(|err| err.to_string())?,
+                        batch_count: db_ops.get_batches().map_err(|err| err.to_string())?.len(),
+                    })
+                }),
+            ),
+            Request::Removals { reply: to } => reply(
+                to,
+                shared.read(reader, |db_ops| db_ops.get_removals().map_err(|err| err.to_string())),
+            ),
+            Request::CheckUpdates { reply: to } => reply(
+                to,
+                shared.read(reader, |db_ops| db_ops.check_updates().map_err(|err| err.to_string())),
+            ),
+            Request::CheckHealth { thorough, reply: to } => {
+                reply(to, Ok(db_health::check(&shared.paths.database(), thorough)))
+            }
+            Request::Export { path, reply: to } => reply(
+                to,
+                shared
+                    .read(reader, |db_ops| db_backup::export(db_ops, &shared.paths, &path))
+                    .map(|manifest| manifest.hash_count),
+            ),
+            _ => unreachable!("Writes are never sent to the readers"),
+        }
+    }
+}
+
+/// Applies writes one after another, keeping its connection open between them
+fn write_loop(shared: &Shared, queue: Receiver<Request>) {
+    let mut writer: Option<DBOps> = None;
+    for request in queue.iter() {
+        match request {
+            Request::InsertBatch {
+                source,
+                name,
+                signatures,
+                reply: to,
+            } => reply(
+                to,
+                connection(shared, &mut writer).and_then(|db_ops| {
+                    db_ops
+                        .import_batch(&source, &name, signatures)
+                        .map_err(|err| err.to_string())
+                }),
+            ),
+            Request::Update {
+                progress,
+                cancel,
+                update_log,
+                reply: to,
+            } => reply(
+                to,
+                connection(shared, &mut writer).and_then(|db_ops| {
+                    db_ops.set_update_log(update_log);
+                    let result = run_job(db_ops, "Update", progress, cancel, |db_ops| {
+                        db_ops.update_db().map_err(|err| err.to_string())
+                    });
+                    db_ops.set_update_log(None);
+                    result
+                }),
+            ),
+            Request::Import {
+                path,
+                progress,
+                cancel,
+                reply: to,
+            } => reply(
+                to,
+                connection(shared, &mut writer).and_then(|db_ops| {
+                    run_job(db_ops, "Import", progress, cancel, |db_ops| {
+                        let source = sources::open_import(&path, &shared.paths).map_err(|err| err.to_string())?;
+                        db_ops.import_from(source.as_ref())
+                    })
+                }),
+            ),
+            Request::Restore { path, merge, reply: to } => reply(to, restore(shared, &mut writer, path, merge)),
+            Request::Repair { reply: to } => {
+                let _gate = shared.gate.write().unwrap_or_else(PoisonError::into_inner);
+                writer = None;
+                shared.reset();
+                reply(to, db_health::repair(&shared.paths.database()));
+            }
+            _ => unreachable!("Reads are never sent to the writer"),
+        }
+    }
+    info!("Database worker stopped");
+}
+
+/// Returns the connection of the writer, opening it if needed
+fn connection<'a>(shared: &Shared, writer: &'a mut Option<DBOps>) -> Result<&'a mut DBOps, String> {
+    if writer.is_none() {
+        *writer = Some(shared.connect()?);
+    }
+    Ok(writer.as_mut().expect("Connection was just opened"))
+}
+
+/// Runs an update or import with the given progress and cancel flag, and reports a cancelled
+/// or stopped run as error
+fn run_job(
+    db_ops: &mut DBOps,
+    name: &str,
+    progress: Option<Sender<f32>>,
+    cancel: Option<Arc<AtomicBool>>,
+    job: impl FnOnce(&mut DBOps) -> Result<u64, String>,
+) -> Result<u64, String> {
+    db_ops.set_sender(progress);
+    db_ops.set_cancel_flag(cancel.unwrap_or_default());
+    let result = job(db_ops).and_then(|hash_count| match (db_ops.cancelled(), db_ops.failure()) {
+        (Some((done, total)), _) => Err(format!("{} cancelled after {} of {} files", name, done, total)),
+        (None, Some(failure)) => Err(format!("{} stopped: {}", name, failure)),
+        (None, None) => Ok(hash_count),
+    });
+    db_ops.set_sender(None);
+    db_ops.set_cancel_flag(Arc::new(AtomicBool::new(false)));
+    result
+}
+
+/// Restores a verified backup. Replacing the database waits for running reads and closes every connection first.
+fn restore(shared: &Shared, writer: &mut Option<DBOps>, path: PathBuf, merge: bool) -> Result<u64, String> {
+    let backup = db_backup::verify(&shared.paths, &path)?;
+    if merge {
+        return connection(shared, writer)?
+            .merge_from(&backup.database())
+            .map_err(|err| err.to_string());
+    }
+    let _gate = shared.gate.write().unwrap_or_else(PoisonError::into_inner);
+    let current = match writer.take() {
+        Some(current) => current,
+        None => shared.connect()?,
+    };
+    shared.reset();
+    let replaced = current.replace_with(&backup.database())?;
+    let hash_count = replaced.count_hashes().map_err(|err| err.to_string())?;
+    *shared.bloom.lock().unwrap_or_else(PoisonError::into_inner) = Some(replaced.shared_bloom());
+    *writer = Some(replaced);
+    Ok(hash_count)
+}
@@ -0,0 +1,491 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Error, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use log::{debug, info, warn};
+
+/// Size of an ISO9660 logical sector, the volume descriptors always start at sector 16
+const ISO_SECTOR_SIZE: u64 = 2048;
+/// Directories nested deeper than this are not followed, protects against crafted images
+const MAX_DIR_DEPTH: usize = 64;
+
+/// File extensions that are treated as disk images while scanning
+pub const IMAGE_EXTENSIONS: [&str; 2] = ["iso", "img"];
+
+/// Returns true if the given path has a file extension of a supported disk image
+pub fn is_disk_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+        .unwrap_or(false)
+}
+
+/// Walks all files stored inside an ISO9660 or FAT12/16/32 image and passes each of them to the visitor.
+/// Images with an MBR partition table are supported as long as the partitions are FAT formatted.
+/// The image is only ever read, nothing gets mounted.
+///
+/// # Arguments
+///
+/// * `path` - The path to the image file
+/// * `visitor` - Called with the path of each file inside the image, e.g. `/EFI/boot.efi`, and a reader for its content
+///
+/// # Errors
+///
+/// Returns an `Error` with `ErrorKind::InvalidData` if the file is not a supported image.
+///
+/// # Example
+///
+/// ```
+/// disk_image::visit_files(Path::new("disk.img"), &mut |inner_path, reader| {
+///     println!("Found {}", inner_path);
+/// }).unwrap();
+/// ```
+pub fn visit_files(path: &Path, visitor: &mut dyn FnMut(&str, &mut dyn Read)) -> Result<(), io::Error> {
+    let mut file = File::open(path)?;
+    if let Some(iso) = IsoImage::open(&mut file)? {
+        info!("Reading ISO9660 image {}", path.display());
+        return iso.visit(&mut file, visitor);
+    }
+    if let Some(fat) = FatVolume::open(&mut file, 0)? {
+        info!("Reading FAT image {}", path.display());
+        return fat.visit(&mut file, "", visitor);
+    }
+
+    // Full disk images start with a partition table instead of a file system
+    let partitions = mbr_partitions(&mut file)?;
+    let mut found = false;
+    for (index, offset) in partitions.into_iter().enumerate() {
+        if let Some(fat) = FatVolume::open(&mut file, offset)? {
+            info!("Reading FAT partition {} of image {}", index + 1, path.display());
+            found = true;
+            fat.visit(&mut file, &format!("/partition{}", index + 1), visitor)?;
+        }
+    }
+    if found {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, "Not a supported disk image"))
+    }
+}
+
+/// Reads exactly `buf.len()` bytes at the given absolute offset
+fn read_at(file: &mut File, offset: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+fn le_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn le_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+/// Returns the byte offsets of all used primary partitions in the MBR, or nothing if there is no MBR
+fn mbr_partitions(file: &mut File) -> Result<Vec<u64>, io::Error> {
+    let mut sector = [0u8; 512];
+    if read_at(file, 0, &mut sector).is_err() || sector[510..512] != [0x55, 0xAA] {
+        return Ok(Vec::new());
+    }
+    Ok((0..4)
+        .map(|i| &sector[446 + i * 16..446 + (i + 1) * 16])
+        .filter(|entry| entry[4] != 0)
+        .map(|entry| le_u32(entry, 8) as u64 * 512)
+        .filter(|offset| *offset > 0)
+        .collect())
+}
+
+/// An ISO9660 file system, using the Joliet names if the image provides them
+struct IsoImage {
+    block_size: u64,
+    root_extent: u32,
+    root_size: u32,
+    joliet: bool,
+}
+
+impl IsoImage {
+    /// Reads the volume descriptors, returns None if the file is not an ISO9660 image
+    fn open(file: &mut File) -> Result<Option<Self>, io::Error> {
+        let mut primary = None;
+        let mut joliet = None;
+        let mut descriptor = [0u8; ISO_SECTOR_SIZE as usize];
+        for sector in 16..64 {
+            if read_at(file, sector * ISO_SECTOR_SIZE, &mut descriptor).is_err() || &descriptor[1..6] != b"CD001" {
+                break;
+            }
+            let root = &descriptor[156..190];
+            let image = IsoImage {
+                block_size: le_u16(&descriptor, 128).max(512) as u64,
+                root_extent: le_u32(root, 2),
+                root_size: le_u32(root, 10),
+                joliet: false,
+            };
+            match descriptor[0] {
+                1 if primary.is_none() => primary = Some(image),
+                // Supplementary descriptor with one of the Joliet UCS-2 escape sequences
+                2 if descriptor[88..90] == [0x25, 0x2F] && matches!(descriptor[90], 0x40 | 0x43 | 0x45) => {
+                    joliet = Some(IsoImage { joliet: true, ..image })
+                }
+                255 => break,
+                _ => {}
+            }
+        }
+        Ok(joliet.or(primary))
+    }
+
+    fn visit(&self, file: &mut File, visitor: &mut dyn FnMut(&str, &mut dyn Read)) -> Result<(), io::Error> {
+        let mut visited = HashSet::new();
+        self.visit_dir(file, self.root_extent, self.root_size, "", 0, &mut visited, visitor)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn visit_dir(
+        &self,
+        file: &mut File,
+        extent: u32,
+        size: u32,
+        prefix: &str,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        visitor: &mut dyn FnMut(&str, &mut dyn Read),
+    ) -> Result<(), io::Error> {
+        if depth > MAX_DIR_DEPTH || !visited.insert(extent) {
+            warn!("Skipping ISO directory at extent {} to avoid a loop", extent);
+            return Ok(());
+        }
+        if extent as u64 * self.block_size + size as u64 > file.metadata()?.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "ISO directory lies outside of the image"));
+        }
+        let mut dir = vec![0u8; size as usize];
+        read_at(file, extent as u64 * self.block_size, &mut dir)?;
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < dir.len() {
+            let len = dir[pos] as usize;
+            // Records never cross a sector boundary, a zero length means the rest of the sector is padding
+            if len == 0 {
+                pos = (pos / self.block_size as usize + 1) * self.block_size as usize;
+                continue;
+            }
+            if len < 34 || pos + len > dir.len() {
+                break;
+            }
+            let record = &dir[pos..pos + len];
+            let name_len = record[32] as usize;
+            if 33 + name_len <= len {
+                let raw_name = &record[33..33 + name_len];
+                // Single 0x00 and 0x01 bytes are the entries for the directory itself and its parent
+                if !(name_len == 1 && raw_name[0] <= 1) {
+                    let is_dir = record[25] & 0x02 != 0;
+                    entries.push((self.decode_name(raw_name), le_u32(record, 2), le_u32(record, 10), is_dir));
+                }
+            }
+            pos += len;
+        }
+
+        for (name, child_extent, child_size, is_dir) in entries {
+            let child_path = format!("{}/{}", prefix, name);
+            if is_dir {
+                self.visit_dir(file, child_extent, child_size, &child_path, depth + 1, visited, visitor)?;
+            } else {
+                debug!("Found {} in ISO image", child_path);
+                file.seek(SeekFrom::Start(child_extent as u64 * self.block_size))?;
+                let mut reader = (&mut *file).take(child_size as u64);
+                visitor(&child_path, &mut reader);
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns a raw directory record name into a readable file name, dropping the `;1` version suffix
+    fn decode_name(&self, raw: &[u8]) -> String {
+        let name = if self.joliet {
+            let units: Vec<u16> = raw.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            String::from_utf8_lossy(raw).to_string()
+        };
+        let name = name.split(';').next().unwrap_or_default();
+        name.strip_suffix('.').unwrap_or(name).to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// A FAT12, FAT16 or FAT32 file system starting at `offset` inside the image
+struct FatVolume {
+    offset: u64,
+    fat_type: FatType,
+    cluster_size: u64,
+    cluster_count: u32,
+    first_data_sector: u64,
+    bytes_per_sector: u64,
+    /// Location and size of the fixed root directory, used by FAT12 and FAT16
+    root_dir: (u64, u64),
+    /// First cluster of the root directory, used by FAT32
+    root_cluster: u32,
+    /// The first copy of the file allocation table
+    fat: Vec<u8>,
+}
+
+impl FatVolume {
+    /// Reads the boot sector at the given offset, returns None if it does not hold a FAT file system
+    fn open(file: &mut File, offset: u64) -> Result<Option<Self>, io::Error> {
+        let mut boot = [0u8; 512];
+        if read_at(file, offset, &mut boot).is_err() || boot[510..512] != [0x55, 0xAA] {
+            return Ok(None);
+        }
+        let bytes_per_sector = le_u16(&boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = le_u16(&boot, 14) as u64;
+        let num_fats = boot[16] as u64;
+        let root_entries = le_u16(&boot, 17) as u64;
+        let total_sectors = match le_u16(&boot, 19) {
+            0 => le_u32(&boot, 32) as u64,
+            sectors => sectors as u64,
+        };
+        let fat_size = match le_u16(&boot, 22) {
+            0 => le_u32(&boot, 36) as u64,
+            sectors => sectors as u64,
+        };
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || num_fats == 0
+            || fat_size == 0
+        {
+            return Ok(None);
+        }
+
+        let root_dir_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let first_data_sector = reserved_sectors + num_fats * fat_size + root_dir_sectors;
+        // Also rejects random data that happens to end in a boot signature, before allocating the FAT
+        if total_sectors <= first_data_sector
+            || offset + first_data_sector * bytes_per_sector > file.metadata()?.len()
+        {
+            return Ok(None);
+        }
+        let cluster_count = ((total_sectors - first_data_sector) / sectors_per_cluster) as u32;
+        let fat_type = if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let mut fat = vec![0u8; (fat_size * bytes_per_sector) as usize];
+        read_at(file, offset + reserved_sectors * bytes_per_sector, &mut fat)?;
+        debug!("Found {:?} volume with {} clusters", fat_type, cluster_count);
+
+        Ok(Some(FatVolume {
+            offset,
+            fat_type,
+            cluster_size: sectors_per_cluster * bytes_per_sector,
+            cluster_count,
+            first_data_sector,
+            bytes_per_sector,
+            root_dir: (
+                (reserved_sectors + num_fats * fat_size) * bytes_per_sector,
+                root_entries * 32,
+            ),
+            root_cluster: le_u32(&boot, 44),
+            fat,
+        }))
+    }
+
+    fn visit(&self, file: &mut File, prefix: &str, visitor: &mut dyn FnMut(&str, &mut dyn Read)) -> Result<(), io::Error> {
+        let root = if self.fat_type == FatType::Fat32 {
+            self.read_chain(file, self.root_cluster)?
+        } else {
+            let mut root = vec![0u8; self.root_dir.1 as usize];
+            read_at(file, self.offset + self.root_dir.0, &mut root)?;
+            root
+        };
+        let mut visited = HashSet::new();
+        self.visit_dir(file, &root, prefix, 0, &mut visited, visitor)
+    }
+
+    fn visit_dir(
+        &self,
+        file: &mut File,
+        dir: &[u8],
+        prefix: &str,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        visitor: &mut dyn FnMut(&str, &mut dyn Read),
+    ) -> Result<(), io::Error> {
+        if depth > MAX_DIR_DEPTH {
+            warn!("Skipping FAT directory {} because it is nested too deep", prefix);
+            return Ok(());
+        }
+        let mut long_name: Vec<(u8, Vec<u16>)> = Vec::new();
+        for entry in dir.chunks_exact(32) {
+            match entry[0] {
+                0x00 => break,
+                0xE5 => {
+                    long_name.clear();
+                    continue;
+                }
+                _ => {}
+            }
+            let attributes = entry[11];
+            // Long file name entries precede the short entry they belong to, in reverse order
+            if attributes == 0x0F {
+                let mut units = Vec::with_capacity(13);
+                for range in [1..11, 14..26, 28..32] {
+                    units.extend(entry[range].chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])));
+                }
+                long_name.push((entry[0] & 0x1F, units));
+                continue;
+            }
+            let name = if long_name.is_empty() {
+                Self::short_name(entry)
+            } else {
+                long_name.sort_by_key(|(order, _)| *order);
+                let units: Vec<u16> = long_name
+                    .drain(..)
+                    .flat_map(|(_, units)| units)
+                    .take_while(|unit| *unit != 0x0000 && *unit != 0xFFFF)
+                    .collect();
+                String::from_utf16_lossy(&units)
+            };
+            // Skip volume labels and the entries for the directory itself and its parent
+            if attributes & 0x08 != 0 || name == "." || name == ".." {
+                continue;
+            }
+
+            let cluster = ((le_u16(entry, 20) as u32) << 16) | le_u16(entry, 26) as u32;
+            let child_path = format!("{}/{}", prefix, name);
+            if attributes & 0x10 != 0 {
+                if cluster < 2 || !visited.insert(cluster) {
+                    continue;
+                }
+                let child_dir = self.read_chain(file, cluster)?;
+                self.visit_dir(file, &child_dir, &child_path, depth + 1, visited, visitor)?;
+            } else {
+                debug!("Found {} in FAT image", child_path);
+                let mut reader = ClusterReader {
+                    volume: self,
+                    file: &mut *file,
+                    cluster,
+                    cluster_pos: 0,
+                    remaining: le_u32(entry, 28) as u64,
+                    steps: 0,
+                };
+                visitor(&child_path, &mut reader);
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the 8.3 name of a directory entry, honoring the lowercase flags set by Windows
+    fn short_name(entry: &[u8]) -> String {
+        let mut base = String::from_utf8_lossy(&entry[0..8]).trim_end().to_string();
+        let mut ext = String::from_utf8_lossy(&entry[8..11]).trim_end().to_string();
+        if entry[12] & 0x08 != 0 {
+            base = base.to_lowercase();
+        }
+        if entry[12] & 0x10 != 0 {
+            ext = ext.to_lowercase();
+        }
+        if ext.is_empty() {
+            base
+        } else {
+            format!("{}.{}", base, ext)
+        }
+    }
+
+    /// Returns the cluster following the given one, or None at the end of the chain
+    fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let (next, end) = match self.fat_type {
+            FatType::Fat12 => {
+                let at = (cluster + cluster / 2) as usize;
+                let value = le_u16(self.fat.get(at..at + 2)?, 0) as u32;
+                let value = if cluster % 2 == 1 { value >> 4 } else { value & 0x0FFF };
+                (value, 0x0FF7)
+            }
+            FatType::Fat16 => (le_u16(self.fat.get(cluster as usize * 2..)?, 0) as u32, 0xFFF7),
+            FatType::Fat32 => (le_u32(self.fat.get(cluster as usize * 4..)?, 0) & 0x0FFFFFFF, 0x0FFFFFF7),
+        };
+        // Free, reserved, bad and end of chain markers all end the chain
+        if next < 2 || next >= end || next >= self.cluster_count + 2 {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    /// Returns the absolute offset of the given data cluster inside the image
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.offset + (self.first_data_sector * self.bytes_per_sector) + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    /// Reads a whole cluster chain into memory, only used for directories
+    fn read_chain(&self, file: &mut File, first: u32) -> Result<Vec<u8>, io::Error> {
+        let mut data = Vec::new();
+        let mut cluster = Some(first).filter(|cluster| *cluster >= 2);
+        let mut steps = 0;
+        while let Some(current) = cluster {
+            steps += 1;
+            if steps > self.cluster_count {
+                return Err(Error::new(ErrorKind::InvalidData, "Cluster chain contains a loop"));
+            }
+            let start = data.len();
+            data.resize(start + self.cluster_size as usize, 0);
+            read_at(file, self.cluster_offset(current), &mut data[start..])?;
+            cluster = self.next_cluster(current);
+        }
+        Ok(data)
+    }
+}
+
+/// Streams the content of a file stored in a FAT volume by following its cluster chain
+struct ClusterReader<'a> {
+    volume: &'a FatVolume,
+    file: &'a mut File,
+    cluster: u32,
+    cluster_pos: u64,
+    remaining: u64,
+    steps: u32,
+}
+
+impl Read for ClusterReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || self.cluster < 2 {
+            return Ok(0);
+        }
+        if self.cluster_pos == self.volume.cluster_size {
+            self.steps += 1;
+            match self.volume.next_cluster(self.cluster) {
+                Some(next) if self.steps <= self.volume.cluster_count => {
+                    self.cluster = next;
+                    self.cluster_pos = 0;
+                }
+                _ => return Err(Error::new(ErrorKind::UnexpectedEof, "Cluster chain ends before the file does")),
+            }
+        }
+        let len = (buf.len() as u64)
+            .min(self.volume.cluster_size - self.cluster_pos)
+            .min(self.remaining) as usize;
+        self.file
+            .seek(SeekFrom::Start(self.volume.cluster_offset(self.cluster) + self.cluster_pos))?;
+        let count = self.file.read(&mut buf[..len])?;
+        if count == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Image ends before the file does"));
+        }
+        self.cluster_pos += count as u64;
+        self.remaining -= count as u64;
+        Ok(count)
+    }
+}
@@ -1,10 +1,13 @@
 use std::{
-    fs::{self, File},
+    fs::{self, File, OpenOptions},
     io::Write,
+    path::PathBuf,
 };
 
+use chrono::{DateTime, Local, Utc};
 use log::{debug, error, info, warn};
-use directories_next::ProjectDirs;
+
+use super::app_paths::AppPaths;
 
 pub struct FileLog {
     pub file: Option<File>,
@@ -16,16 +19,17 @@ impl FileLog {
     ///
     /// # Arguments
     ///
+    /// * `paths` - The locations of the application, the file is created in its scan log directory.
     /// * `fname` - A string representing the name of the file to create.
     ///
     /// # Example
     ///
     /// ```
-    /// let log = FileLog::new("log.txt".to_owned());
+    /// let log = FileLog::new(&AppPaths::platform(), "log.txt".to_owned());
     /// ```
-    pub fn new(fname: String) -> Self {
+    pub fn new(paths: &AppPaths, fname: String) -> Self {
         let mut ret = FileLog { file: None };
-        ret.create_file(fname);
+        ret.create_file(paths, fname);
         ret
     }
 
@@ -39,7 +43,7 @@ impl FileLog {
     /// # Example
     ///
     /// ```
-    /// let log = FileLog::new("log.txt".to_owned());
+    /// let log = FileLog::new(&AppPaths::platform(), "log.txt".to_owned());
     /// log.log("abc123".to_owned(), "C:/Users/user/Desktop/file.txt".to_owned());
     /// ```
     pub fn log(&self, hash: String, fpath: String) {
@@ -60,21 +64,43 @@ impl FileLog {
         }
     }
 
+    /// Records a file that was abandoned because reading it stalled, so it was not checked.
+    /// The entry has no hash, a `-` takes its place, followed by the path and the reason.
+    ///
+    /// # Arguments
+    ///
+    /// * `fpath` - A string representing the file path to log.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let log = FileLog::new(&AppPaths::platform(), "log.txt".to_owned());
+    /// log.log_unreadable("C:/Users/user/Desktop/file.txt".to_owned());
+    /// ```
+    pub fn log_unreadable(&self, fpath: String) {
+        if let Some(mut file) = self.file.as_ref() {
+            if let Err(err) = file.write_all(format!("-\t{fpath}\tunreadable (timeout)\n").as_bytes()) {
+                error!("Failed loggin: {err}");
+            }
+        }
+    }
+
     /// Creates a new file with the specified name and attempts to create a logs folder if it doesn't already exist.
     ///
     /// # Arguments
     ///
+    /// * `paths` - The locations of the application, the file is created in its scan log directory.
     /// * `fname` - A string representing the name of the file to create.
     ///
     /// # Example
     ///
     /// ```
-    /// let mut log = FileLog::new("log.txt".to_owned());
-    /// log.create_file("new_log.txt".to_owned());
+    /// let paths = AppPaths::platform();
+    /// let mut log = FileLog::new(&paths, "log.txt".to_owned());
+    /// log.create_file(&paths, "new_log.txt".to_owned());
     /// ```
-    pub fn create_file(&mut self, fname: String) {
-        let project_dirs = ProjectDirs::from("com", "Raspirus", "Logs").expect("Failed to get project directories.");
-        let log_dir = project_dirs.data_local_dir().join("logs"); // Create a "logs" subdirectory
+    pub fn create_file(&mut self, paths: &AppPaths, fname: String) {
+        let log_dir = paths.scan_logs();
 
         match fs::create_dir_all(&log_dir) {
             Ok(_) => {
@@ -93,3 +119,56 @@ impl FileLog {
         }
     }
 }
+
+/// The log of a single database update, written to the `updates` folder next to the scan logs.
+/// Every entry is appended right away, so the log survives a crash during the update.
+#[derive(Debug, Clone)]
+pub struct UpdateLog {
+    pub path: Option<PathBuf>,
+}
+
+impl UpdateLog {
+    /// Creates a new update log named after the current time, in the update log directory of the given locations.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let log = UpdateLog::new(&AppPaths::platform());
+    /// log.log("DB update executed");
+    /// ```
+    pub fn new(paths: &AppPaths) -> Self {
+        let now: DateTime<Local> = Local::now();
+        let fname = format!("{}.log", now.format("%Y_%m_%d_%H_%M_%S"));
+        let log_dir = paths.update_logs();
+
+        match fs::create_dir_all(&log_dir) {
+            Ok(_) => {
+                info!("Created update log at DIR: {} NAME: {}", log_dir.display(), fname);
+                UpdateLog { path: Some(log_dir.join(fname)) }
+            }
+            Err(err) => {
+                error!("Failed creating updates folder: {err}");
+                UpdateLog { path: None }
+            }
+        }
+    }
+
+    /// Appends the given message to the log, prefixed with the current time.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The line to write, without trailing newline
+    pub fn log(&self, message: &str) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(format!("{} {}\n", Utc::now(), message).as_bytes()));
+        if let Err(err) = written {
+            error!("Failed writing update log: {err}");
+        }
+    }
+}
@@ -1,9 +1,8 @@
 use std::{
     fs::{File, self},
-    io::{BufReader, Error, ErrorKind, Read},
-    path::Path,
-    process::exit,
-    time,
+    io::{Error, ErrorKind, Read},
+    path::{Path, PathBuf},
+    time::{self, Duration},
 };
 
 use chrono::{DateTime, Local};
@@ -11,16 +10,75 @@ use log::{debug, error, info, warn};
 use terminal_size::terminal_size;
 use walkdir::WalkDir;
 use flume::Sender;
-use super::{db_ops::DBOps, file_log::FileLog};
+use super::{
+    db_worker::DBHandle,
+    disk_image,
+    file_log::FileLog,
+    mail_parser,
+    signature::SignatureInfo,
+    timed_read::{ReadOutcome, TimedReader},
+};
+
+/// Amount of hashed files collected before they get looked up in the database together
+const LOOKUP_BATCH_SIZE: usize = 256;
+/// Maximum time a single read may block before the file is considered stalled
+const READ_STALL_TIMEOUT: Duration = Duration::from_secs(15);
+/// Base time budget for reading a file, regardless of its size
+const FILE_TIME_BUDGET: Duration = Duration::from_secs(60);
+/// Slowest accepted read speed in bytes per second, used to extend the time budget of large files
+const MIN_READ_SPEED: u64 = 256 * 1024;
+/// Amount of stalled files in a row after which the device is considered failing
+const MAX_CONSECUTIVE_STALLS: u32 = 3;
+
+/// The result of trying to hash a single file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashOutcome {
+    /// The file was read completely, contains the MD5 hash
+    Hashed(String),
+    /// The file was empty, unreadable or a known false positive
+    Skipped,
+    /// Reading the file stalled or exceeded its time budget and was abandoned
+    TimedOut,
+}
+
+/// An infected file found during a scan, together with what is known about its signature
+#[derive(Debug, Clone)]
+pub struct Detection {
+    /// Path of the infected file, nested paths use `!` to separate the container
+    pub path: String,
+    /// MD5 hash of the file
+    pub hash: String,
+    /// Metadata of the matching signature
+    pub info: SignatureInfo,
+}
+
+/// The outcome of a scan, also returned if it was stopped early because the device is failing
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    /// The infected files found
+    pub detections: Vec<Detection>,
+    /// Files that were abandoned because reading them stalled, they were not checked
+    pub unreadable_files: Vec<String>,
+    /// Set if the scan stopped early because several files in a row stalled
+    pub device_failing: bool,
+}
 
+/// Walks the files inside a container, such as a disk image or a mailbox
+type ContainerVisit = fn(&Path, &mut dyn FnMut(&str, &mut dyn Read)) -> Result<(), Error>;
 
+/// Opens a file for hashing, on the reader thread
+pub(crate) type Opener = fn(&Path) -> Result<Box<dyn Read + Send>, Error>;
+
+fn open_file(path: &Path) -> Result<Box<dyn Read + Send>, Error> {
+    Ok(Box::new(File::open(path)?))
+}
 
 /// Struct representing a file scanner that is capable of searching through a specified directory and its subdirectories for malicious files.
 pub struct FileScanner {
-    /// A reference to a `DBOps` object that allows the `FileScanner` to access and manipulate the database.
-    pub db_conn: DBOps,
-    /// A vector of file paths for files that have been identified as malicious.
-    pub dirty_files: Vec<String>,
+    /// A handle to the database worker, used to look up the hashes of the scanned files.
+    pub db: DBHandle,
+    /// The files that have been identified as malicious.
+    pub dirty_files: Vec<Detection>,
     /// The file path of the directory that the `FileScanner` should search through.
     pub scanloc: String,
     /// A `FileLog` object that the `FileScanner` can use to log information about the search process.
@@ -32,7 +90,17 @@ pub struct FileScanner {
     /// Amount scanned in bytes
     scanned_size: u64,
     /// Sends messages
-    sender: Option<Sender<f32>>
+    sender: Option<Sender<f32>>,
+    /// Hashes and file paths waiting to be looked up in the database
+    pending: Vec<(String, String)>,
+    /// File paths that were abandoned because reading them stalled
+    unreadable_files: Vec<String>,
+    /// Set once several files in a row stalled, which usually means the device is failing
+    device_failing: bool,
+    /// Reads the files on a separate thread, so a stalled read can be abandoned
+    pub(crate) reader: TimedReader,
+    /// Opens the files to hash, can be replaced to simulate a failing device
+    pub(crate) opener: Opener,
 }
 
 impl FileScanner {
@@ -42,7 +110,8 @@ impl FileScanner {
     /// # Arguments
     ///
     /// * `scanloc` - The file path of the directory that the `FileScanner` should search through.
-    /// * `db_file` - The file path of the database that the `FileScanner` should use to store information about the files it has scanned.
+    /// * `sender` - Where the progress of the scan is sent to, in percent
+    /// * `db` - The database worker the hashes of the scanned files are looked up with, its paths also locate the scan log
     ///
     /// # Returns
     ///
@@ -51,17 +120,9 @@ impl FileScanner {
     /// # Errors
     ///
     /// This function will return an `Error` with an `ErrorKind` of `Other` if the `scanloc` file path does not exist.
-    pub fn new(scanloc: &str, sender: Option<Sender<f32>>) -> Result<Self, Error> {
+    pub fn new(scanloc: &str, sender: Option<Sender<f32>>, db: DBHandle) -> Result<Self, Error> {
         //check path
         if Path::new(&scanloc).exists() {
-            let tmpconf = match DBOps::new(None) {
-                Ok(db_conn) => db_conn,
-                Err(err) => {
-                    error!("{err}");
-                    exit(-1);
-                }
-            };
-
             let now: DateTime<Local> = Local::now();
             let now_str = now.format("%Y_%m_%d_%H_%M_%S").to_string();
             let log_str = format!("{}.log", now_str);
@@ -73,14 +134,19 @@ impl FileScanner {
                 ];
 
             Ok(FileScanner {
-                db_conn: tmpconf,
+                log: FileLog::new(db.paths(), log_str),
+                db,
                 dirty_files: Vec::new(),
                 scanloc: scanloc.to_owned(),
-                log: FileLog::new(log_str),
                 false_positive: false_pos,
                 folder_size: 0,
                 scanned_size: 0,
-                sender
+                sender,
+                pending: Vec::with_capacity(LOOKUP_BATCH_SIZE),
+                unreadable_files: Vec::new(),
+                device_failing: false,
+                reader: TimedReader::new(READ_STALL_TIMEOUT),
+                opener: open_file,
             })
         } else {
             Err(Error::new(ErrorKind::Other, "Invalid Path"))
@@ -88,6 +154,7 @@ impl FileScanner {
     }
 
     /// Searches the given file location for infected files.
+    /// Files that could not be read in time are listed in the result, as they were not checked.
     ///
     /// # Arguments
     ///
@@ -97,17 +164,19 @@ impl FileScanner {
     ///
     /// ```
     /// let mut scanner = FileScanner::new("/path/to/scan").unwrap();
-    /// scanner.search_files();
+    /// let result = scanner.search_files(false).unwrap();
     /// ```
-    pub fn search_files(&mut self, stop_early:bool) -> Result<Vec<String>, String> {
+    pub fn search_files(&mut self, stop_early:bool) -> Result<ScanResult, String> {
         let mut analysed: i128 = 0;
         let mut skipped: i128 = 0;
+        let mut consecutive_stalls: u32 = 0;
         let last_percentage: &mut f32 = &mut -1.0;
         let big_tic = time::Instant::now();
         if self.get_folder_size(Path::new(self.scanloc.as_str()).to_owned().as_ref()).is_err() {
             return Err("Can't get folder size".to_string());
         }
         for file in WalkDir::new(&self.scanloc)
+            .sort_by_file_name()
             .into_iter()
             .filter_map(|file| file.ok())
         {
@@ -120,42 +189,60 @@ impl FileScanner {
             })
             .is_file()
             {
-                let hash = match self.create_hash(&file.path().display().to_string()) {
-                    Some(hash) => {
+                let file_size = file.metadata().map(|md| md.len()).unwrap_or(0);
+                let mut stalled = false;
+                match self.create_hash(&file.path().display().to_string(), file_size) {
+                    HashOutcome::Hashed(hash) => {
                         analysed += 1;
-                        hash
+                        self.pending.push((hash, file.path().display().to_string()));
                     }
-                    None => {
+                    HashOutcome::Skipped => skipped += 1,
+                    HashOutcome::TimedOut => {
                         skipped += 1;
-                        "".to_owned()
+                        stalled = true;
                     }
                 };
-                if Self::calculate_progress(self, last_percentage, file.metadata().unwrap().len()).is_err() {
-                    error!("Progress calculation is broken");
-                    break;
+                // A container that already stalled would only stall again when looking inside
+                let visit: Option<ContainerVisit> = if stalled {
+                    None
+                } else if disk_image::is_disk_image(file.path()) {
+                    Some(disk_image::visit_files)
+                } else if mail_parser::is_mailbox(file.path()) {
+                    Some(mail_parser::visit_attachments)
+                } else {
+                    None
+                };
+                if let Some(visit) = visit {
+                    let (count, timed_out) = self.scan_nested(file.path(), file_size, visit);
+                    analysed += count;
+                    stalled = timed_out;
                 }
-                if let Ok(Some(exists)) = self.db_conn.hash_exists(hash.as_str()) {
-                    if exists {
-                        self.dirty_files.push(file.path().display().to_string());
-                        if stop_early {
-                            warn!("Stopping early at file: {:?}", file.path());
-                            break;
-                        }
-                        info!(
-                            "Found hash {} for file {}",
-                            hash,
-                            file.path().display().to_string()
+                if stalled {
+                    consecutive_stalls += 1;
+                    self.log.log_unreadable(file.path().display().to_string());
+                    self.unreadable_files.push(file.path().display().to_string());
+                    if consecutive_stalls >= MAX_CONSECUTIVE_STALLS {
+                        error!(
+                            "{} files in a row stalled, the device appears to be failing",
+                            consecutive_stalls
                         );
-                        self.log.log(hash, file.path().display().to_string());
+                        self.device_failing = true;
+                        break;
                     }
                 } else {
-                    // Handle the error case
-                    // For example, you can log the error or handle it based on your requirements
-                    error!("Error checking hash existence for file: {:?}", file.path());
+                    consecutive_stalls = 0;
+                }
+                if Self::calculate_progress(self, last_percentage, file_size).is_err() {
+                    error!("Progress calculation is broken");
+                    break;
+                }
+                if self.pending.len() >= LOOKUP_BATCH_SIZE && self.flush_pending(stop_early)? {
+                    break;
                 }
-                
             }
         }
+        // Look up whatever is left over from the last incomplete batch
+        self.flush_pending(stop_early)?;
         let big_toc = time::Instant::now();
         info!(
             "=> Analysed: {}, Skipped: {},  Infected: {}, Time: {} seconds",
@@ -164,54 +251,169 @@ impl FileScanner {
             self.dirty_files.len(),
             big_toc.duration_since(big_tic).as_secs_f64()
         );
-        Ok(self.dirty_files.clone())
+        if !self.unreadable_files.is_empty() {
+            warn!("{} file(s) were unreadable (timeout)", self.unreadable_files.len());
+        }
+        Ok(ScanResult {
+            detections: self.dirty_files.clone(),
+            unreadable_files: self.unreadable_files.clone(),
+            device_failing: self.device_failing,
+        })
+    }
+
+    /// Hashes every file stored inside a container, such as a disk image or a mailbox, and queues them for lookup.
+    /// The files are reported with nested paths such as `disk.img!/EFI/boot.efi`
+    /// or `mailbox.mbox!/message-12/attachment.docm`.
+    /// The container is read on the reader thread with the same time budget as hashing it as a whole.
+    /// Returns the amount of files hashed inside the container and whether reading it stalled,
+    /// the files hashed before the stall are still queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `&mut self` - the `FileScanner` instance
+    /// * `path` - the path to the container file
+    /// * `size` - the size of the container in bytes, used to calculate its time budget
+    /// * `visit` - the function walking the files inside the container
+    fn scan_nested(&mut self, path: &Path, size: u64, visit: ContainerVisit) -> (i128, bool) {
+        let container_path = path.display().to_string();
+        let job_path = path.to_owned();
+        let (entries, timed_out) = match self.reader.hash(
+            Box::new(move |visitor| visit(&job_path, visitor)),
+            Self::time_budget(size),
+        ) {
+            ReadOutcome::Finished(Ok(entries)) => (entries, false),
+            ReadOutcome::Finished(Err(err)) => {
+                // Plenty of .img files are not disk images, so this is expected
+                debug!("Not scanning inside {}: {}", container_path, err);
+                (Vec::new(), false)
+            }
+            ReadOutcome::TimedOut(entries) => {
+                warn!("Reading inside {} stalled, abandoning container", container_path);
+                (entries, true)
+            }
+        };
+
+        let mut found = Vec::new();
+        for (inner_path, hash) in entries {
+            match hash {
+                Ok(Some(hash)) => found.push((hash, format!("{}!{}", container_path, inner_path))),
+                Ok(None) => {}
+                Err(err) => warn!("Failed reading {}!{}: {}", container_path, inner_path, err),
+            }
+        }
+
+        let count = found.len() as i128;
+        info!("Hashed {} file(s) inside {}", count, container_path);
+        self.pending.extend(
+            found
+                .into_iter()
+                .filter(|(hash, _)| !self.false_positive.contains(hash)),
+        );
+        (count, timed_out)
+    }
+
+    /// The time reading a file of the given size may take, slow devices get `MIN_READ_SPEED` plus a base budget
+    fn time_budget(size: u64) -> Duration {
+        FILE_TIME_BUDGET + Duration::from_secs(size / MIN_READ_SPEED)
+    }
+
+    /// Looks up all pending hashes in the database at once and records the infected files.
+    /// Returns true if the scan should stop, because a virus was found and `stop_early` is set.
+    /// Fails if the database can not be read, as the files could otherwise wrongly be reported as clean.
+    ///
+    /// # Arguments
+    ///
+    /// * `&mut self` - the `FileScanner` instance
+    /// * `stop_early` - whether the scan ends at the first found virus
+    fn flush_pending(&mut self, stop_early: bool) -> Result<bool, String> {
+        let pending = std::mem::take(&mut self.pending);
+        let hashes: Vec<String> = pending.iter().map(|(hash, _)| hash.clone()).collect();
+        let found = match self.db.lookup_blocking(hashes) {
+            Ok(found) => found,
+            Err(err) => {
+                error!("Error checking hash existence for {} files: {}", pending.len(), err);
+                return Err(format!(
+                    "Scan aborted: the signature database could not be read ({}). Check and repair it in the settings.",
+                    err
+                ));
+            }
+        };
+
+        for (hash, path) in pending {
+            // The same file can be queued several times, e.g. when found in multiple containers
+            if let Some(info) = found.get(&hash).cloned() {
+                info!(
+                    "Found hash {} ({}) for file {}",
+                    hash,
+                    info.name.as_deref().unwrap_or("unknown malware"),
+                    path
+                );
+                self.log.log(hash.clone(), path.clone());
+                self.dirty_files.push(Detection {
+                    path: path.clone(),
+                    hash,
+                    info,
+                });
+                if stop_early {
+                    warn!("Stopping early at file: {}", path);
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
     }
 
     /// Creates the MD5 hash of a file.
+    /// The file is read on the reader thread of the scanner, so that a read blocking on a bad sector can be abandoned.
+    /// Reading is given up if a single read blocks for longer than `READ_STALL_TIMEOUT`
+    /// or if the whole file takes longer than its time budget, which grows with the file size.
     ///
     /// # Arguments
     ///
     /// * `&mut self` - the `FileScanner` instance
     /// * `path` - the path to the file to create the hash for
+    /// * `size` - the size of the file in bytes, used to calculate its time budget
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut scanner = FileScanner::new("/path/to/scan", "database.db").unwrap();
-    /// let hash = scanner.create_hash("/path/to/file.exe");
+    /// let mut scanner = FileScanner::new("/path/to/scan", None, DBHandle::spawn(AppPaths::platform())).unwrap();
+    /// let hash = scanner.create_hash("/path/to/file.exe", 1024);
     /// ```
-    pub fn create_hash(&mut self, path: &str) -> Option<String> {
-        let mut context = md5::Context::new();
-        let mut buffer = [0; 65536]; // 64KB
-
-        let file = match File::open(path) {
-            Ok(file) => file,
-            Err(err) => {
-                error!("Can't open file: {}", err);
-                return None;
-            }
-        };
-        let mut reader = BufReader::new(file);
-        let mut it = 0;
-        loop {
-            let count = match reader.read(&mut buffer) {
-                Ok(count) => count,
-                Err(err) => {error!("Error while reading: {}", err); return None},
-            };
+    pub fn create_hash(&mut self, path: &str, size: u64) -> HashOutcome {
+        let budget = Self::time_budget(size);
+        let open = self.opener;
+        let file_path = PathBuf::from(path);
+        let outcome = self.reader.hash(
+            Box::new(move |visitor| {
+                let mut file = open(&file_path)?;
+                visitor("", &mut file);
+                Ok(())
+            }),
+            budget,
+        );
 
-            if count == 0 {
-                if it == 0 {
-                    return None;
+        let ret = match outcome {
+            ReadOutcome::Finished(Ok(entries)) => match entries.into_iter().next() {
+                Some((_, Ok(Some(hash)))) => hash,
+                Some((_, Ok(None))) | None => return HashOutcome::Skipped,
+                Some((_, Err(err))) => {
+                    error!("Error while reading {}: {}", path, err);
+                    return HashOutcome::Skipped;
                 }
-                break;
+            },
+            ReadOutcome::Finished(Err(err)) => {
+                error!("Error while reading {}: {}", path, err);
+                return HashOutcome::Skipped;
             }
-            context.consume(&buffer[..count]);
-            it += 1;
-        }
-        let ret = format!("{:?}", context.compute());
+            ReadOutcome::TimedOut(_) => {
+                warn!("Reading {} stalled, abandoning file", path);
+                return HashOutcome::TimedOut;
+            }
+        };
 
         if self.false_positive.contains(&ret) {
-            return None;
+            return HashOutcome::Skipped;
         }
 
         match terminal_size() {
@@ -225,7 +427,7 @@ impl FileScanner {
             }
             None => {}
         };
-        Some(ret)
+        HashOutcome::Hashed(ret)
     }
 
     
@@ -0,0 +1,393 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Error, ErrorKind, Read},
+    path::Path,
+};
+
+use log::{debug, info};
+
+/// Multipart messages nested deeper than this are not followed, protects against crafted mails
+const MAX_PART_DEPTH: usize = 32;
+
+/// Returns true if the given path is an exported mail (`.eml`) or mailbox (`.mbox`)
+pub fn is_mailbox(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("eml") || ext.eq_ignore_ascii_case("mbox"))
+        .unwrap_or(false)
+}
+
+/// Decodes every attachment of an `.eml` file or of every message in an `.mbox` file and passes it to the visitor.
+/// Base64 and quoted-printable encoded parts are decoded, nested multiparts and attached messages are followed.
+///
+/// # Arguments
+///
+/// * `path` - The path to the `.eml` or `.mbox` file
+/// * `visitor` - Called with the path of each attachment, e.g. `/message-12/attachment.docm`, and a reader for its content
+///
+/// # Errors
+///
+/// Returns an `Error` with `ErrorKind::InvalidData` if an `.mbox` file contains no messages.
+///
+/// # Example
+///
+/// ```
+/// mail_parser::visit_attachments(Path::new("mailbox.mbox"), &mut |inner_path, reader| {
+///     println!("Found {}", inner_path);
+/// }).unwrap();
+/// ```
+pub fn visit_attachments(path: &Path, visitor: &mut dyn FnMut(&str, &mut dyn Read)) -> Result<(), io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let is_mbox = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("mbox"))
+        .unwrap_or(false);
+
+    if !is_mbox {
+        let mut message = Vec::new();
+        reader.read_to_end(&mut message)?;
+        info!("Reading mail {}", path.display());
+        visit_message(&message, "", 0, visitor);
+        return Ok(());
+    }
+
+    // Messages in a mailbox are separated by "From " lines, read them one by one to keep memory low
+    info!("Reading mailbox {}", path.display());
+    let mut message = Vec::new();
+    let mut index = 0;
+    let mut previous_blank = true;
+    for line in reader.split(b'\n') {
+        let line = line?;
+        if previous_blank && line.starts_with(b"From ") {
+            if index > 0 {
+                visit_message(&message, &format!("/message-{}", index), 0, visitor);
+            }
+            message.clear();
+            index += 1;
+            previous_blank = false;
+            continue;
+        }
+        previous_blank = line.is_empty() || line == b"\r";
+        // Undo the ">From " quoting applied to body lines by mboxrd writers
+        let quoted = line.iter().take_while(|byte| **byte == b'>').count();
+        let line = if quoted > 0 && line[quoted..].starts_with(b"From ") {
+            &line[1..]
+        } else {
+            &line[..]
+        };
+        message.extend_from_slice(line);
+        message.push(b'\n');
+    }
+    if index == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "Mailbox contains no messages"));
+    }
+    visit_message(&message, &format!("/message-{}", index), 0, visitor);
+    Ok(())
+}
+
+/// Splits a message into its headers and body, then walks its parts
+fn visit_message(message: &[u8], prefix: &str, depth: usize, visitor: &mut dyn FnMut(&str, &mut dyn Read)) {
+    let (headers, body) = split_headers(message);
+    let mut counter = 0;
+    visit_part(&headers, body, prefix, depth, &mut counter, visitor);
+}
+
+fn visit_part(
+    headers: &[(String, String)],
+    body: &[u8],
+    prefix: &str,
+    depth: usize,
+    counter: &mut usize,
+    visitor: &mut dyn FnMut(&str, &mut dyn Read),
+) {
+    if depth > MAX_PART_DEPTH {
+        debug!("Skipping MIME part nested too deep in {}", prefix);
+        return;
+    }
+    let content_type = header(headers, "content-type").unwrap_or("text/plain");
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    let encoding = header(headers, "content-transfer-encoding")
+        .unwrap_or("7bit")
+        .trim()
+        .to_lowercase();
+
+    if media_type.starts_with("multipart/") {
+        if let Some(boundary) = header_param(content_type, "boundary") {
+            for part in split_multipart(body, &boundary) {
+                let (part_headers, part_body) = split_headers(part);
+                visit_part(&part_headers, part_body, prefix, depth + 1, counter, visitor);
+            }
+        }
+        return;
+    }
+    if media_type == "message/rfc822" {
+        let decoded = decode_body(body, &encoding);
+        let (inner_headers, inner_body) = split_headers(&decoded);
+        visit_part(&inner_headers, inner_body, prefix, depth + 1, counter, visitor);
+        return;
+    }
+
+    let file_name = header(headers, "content-disposition")
+        .and_then(|disposition| header_param(disposition, "filename"))
+        .or_else(|| header_param(content_type, "name"));
+    // Plain text and HTML bodies are not attachments, everything else is scanned
+    if file_name.is_none() && media_type.starts_with("text/") {
+        return;
+    }
+    *counter += 1;
+    let name = match file_name {
+        Some(name) => sanitize_name(&decode_encoded_words(&name)),
+        None => format!("part-{}", counter),
+    };
+    let decoded = decode_body(body, &encoding);
+    let attachment_path = format!("{}/{}", prefix, name);
+    debug!("Found attachment {} with {} bytes", attachment_path, decoded.len());
+    visitor(&attachment_path, &mut decoded.as_slice());
+}
+
+/// Returns the unfolded headers with lowercase names and the body following the first empty line
+fn split_headers(message: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut pos = 0;
+    while pos < message.len() {
+        let end = message[pos..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map(|offset| pos + offset + 1)
+            .unwrap_or(message.len());
+        let line = String::from_utf8_lossy(&message[pos..end]);
+        let line = line.trim_end_matches(['\r', '\n']);
+        pos = end;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            // Folded header, continues the previous one
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, &message[pos.min(message.len())..])
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header_name, _)| header_name == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Returns the value of a header parameter such as `boundary` or `filename`,
+/// including RFC 2231 encoded and continued values like `filename*0*=utf-8''...`
+fn header_param(value: &str, name: &str) -> Option<String> {
+    let mut plain = None;
+    let mut sections: Vec<(usize, bool, String)> = Vec::new();
+    for param in value.split(';').skip(1) {
+        let Some((key, raw)) = param.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let raw = raw.trim().trim_matches('"').to_string();
+        if key == name {
+            plain = Some(raw);
+        } else if let Some(rest) = key.strip_prefix(name).and_then(|rest| rest.strip_prefix('*')) {
+            let encoded = rest.is_empty() || rest.ends_with('*');
+            let index = rest.trim_end_matches('*').parse::<usize>().unwrap_or(0);
+            sections.push((index, encoded, raw));
+        }
+    }
+    if sections.is_empty() {
+        return plain;
+    }
+    sections.sort_by_key(|(index, _, _)| *index);
+    let mut bytes = Vec::new();
+    for (index, encoded, raw) in sections {
+        if !encoded {
+            bytes.extend_from_slice(raw.as_bytes());
+            continue;
+        }
+        // The first encoded section starts with charset'language'
+        let raw = if index == 0 {
+            raw.splitn(3, '\'').nth(2).unwrap_or(&raw).to_string()
+        } else {
+            raw
+        };
+        bytes.extend(percent_decode(raw.as_bytes()));
+    }
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Splits a multipart body on its boundary lines, the preamble and epilogue are dropped
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut part_start: Option<usize> = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        let end = body[pos..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map(|offset| pos + offset + 1)
+            .unwrap_or(body.len());
+        let line = String::from_utf8_lossy(&body[pos..end]);
+        let line = line.trim_end();
+        if line.starts_with(&delimiter) {
+            if let Some(start) = part_start {
+                // The line break in front of the boundary belongs to the boundary, not the part
+                let mut part_end = pos;
+                if part_end > start && body[part_end - 1] == b'\n' {
+                    part_end -= 1;
+                    if part_end > start && body[part_end - 1] == b'\r' {
+                        part_end -= 1;
+                    }
+                }
+                parts.push(&body[start..part_end]);
+            }
+            if line[delimiter.len()..].starts_with("--") {
+                return parts;
+            }
+            part_start = Some(end);
+        }
+        pos = end;
+    }
+    // Tolerate a missing closing boundary
+    if let Some(start) = part_start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+fn decode_body(body: &[u8], encoding: &str) -> Vec<u8> {
+    match encoding {
+        "base64" => decode_base64(body),
+        "quoted-printable" => decode_quoted_printable(body),
+        _ => body.to_vec(),
+    }
+}
+
+/// Decodes base64 while skipping line breaks and any other characters outside of the alphabet
+fn decode_base64(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in input {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    output
+}
+
+fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut pos = 0;
+    while pos < input.len() {
+        if input[pos] != b'=' {
+            output.push(input[pos]);
+            pos += 1;
+            continue;
+        }
+        // Soft line breaks are removed, escaped bytes decoded, anything else kept as is
+        match (input.get(pos + 1), input.get(pos + 2)) {
+            (Some(b'\r'), Some(b'\n')) => pos += 3,
+            (Some(b'\n'), _) => pos += 2,
+            (Some(high), Some(low)) => match (hex_value(*high), hex_value(*low)) {
+                (Some(high), Some(low)) => {
+                    output.push(high << 4 | low);
+                    pos += 3;
+                }
+                _ => {
+                    output.push(b'=');
+                    pos += 1;
+                }
+            },
+            _ => {
+                output.push(b'=');
+                pos += 1;
+            }
+        }
+    }
+    output
+}
+
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut pos = 0;
+    while pos < input.len() {
+        if input[pos] == b'%' {
+            if let (Some(high), Some(low)) = (
+                input.get(pos + 1).and_then(|byte| hex_value(*byte)),
+                input.get(pos + 2).and_then(|byte| hex_value(*byte)),
+            ) {
+                output.push(high << 4 | low);
+                pos += 3;
+                continue;
+            }
+        }
+        output.push(input[pos]);
+        pos += 1;
+    }
+    output
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Decodes RFC 2047 encoded words like `=?utf-8?B?...?=` found in attachment names
+fn decode_encoded_words(value: &str) -> String {
+    let mut output = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("=?") {
+        let Some(end) = rest[start + 2..].find("?=").map(|end| start + 2 + end) else {
+            break;
+        };
+        let mut fields = rest[start + 2..end].splitn(3, '?');
+        let (_charset, encoding, text) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(charset), Some(encoding), Some(text)) => (charset, encoding, text),
+            _ => break,
+        };
+        let decoded = if encoding.eq_ignore_ascii_case("b") {
+            decode_base64(text.as_bytes())
+        } else {
+            decode_quoted_printable(text.replace('_', " ").as_bytes())
+        };
+        output.push_str(&rest[..start]);
+        output.push_str(&String::from_utf8_lossy(&decoded));
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Keeps attachment names from escaping the nested path
+fn sanitize_name(name: &str) -> String {
+    let name = name.trim().replace(['/', '\\'], "_");
+    if name.is_empty() || name == "." || name == ".." {
+        "attachment".to_string()
+    } else {
+        name
+    }
+}
@@ -1,5 +1,16 @@
+pub mod app_paths;
+pub mod bloom_filter;
+pub mod db_backup;
+pub mod db_health;
+pub mod db_migrations;
 pub mod db_ops;
+pub mod db_worker;
+pub mod disk_image;
 pub mod file_log;
 pub mod file_scanner;
+pub mod mail_parser;
+pub mod signature;
 pub mod config_file;
+pub mod sources;
+pub mod timed_read;
 pub mod utils;
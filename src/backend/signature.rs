@@ -0,0 +1,118 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// How dangerous a signature's malware is, as reported by its feed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// Parses the severity names and numbers (1 to 4) used by common feeds.
+    /// Returns None for anything else.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// assert_eq!(Severity::parse("HIGH"), Some(Severity::High));
+    /// assert_eq!(Severity::parse("4"), Some(Severity::Critical));
+    /// assert_eq!(Severity::parse("unknown"), None);
+    /// ```
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "1" | "low" => Some(Severity::Low),
+            "2" | "medium" | "moderate" => Some(Severity::Medium),
+            "3" | "high" => Some(Severity::High),
+            "4" | "critical" | "severe" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+
+    /// The value stored in the database
+    pub fn level(&self) -> i64 {
+        match self {
+            Severity::Low => 1,
+            Severity::Medium => 2,
+            Severity::High => 3,
+            Severity::Critical => 4,
+        }
+    }
+
+    /// Converts a value stored in the database back, None if it is out of range
+    pub fn from_level(level: i64) -> Option<Self> {
+        Self::parse(&level.to_string())
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single signature parsed from a feed, with whatever metadata the feed provides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Lowercase hexadecimal MD5 hash
+    pub hash: String,
+    /// Malware name or family
+    pub name: Option<String>,
+    pub severity: Option<Severity>,
+    /// Date the malware was first seen, as given by the feed
+    pub first_seen: Option<String>,
+}
+
+impl Signature {
+    /// Returns true if the feed provided any metadata besides the hash
+    pub fn has_metadata(&self) -> bool {
+        self.name.is_some() || self.severity.is_some() || self.first_seen.is_some()
+    }
+}
+
+/// Everything known about a signature found in the database
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignatureInfo {
+    /// Malware name or family
+    pub name: Option<String>,
+    pub severity: Option<Severity>,
+    /// Date the malware was first seen, as given by the feed
+    pub first_seen: Option<String>,
+    /// Name of the source the signature was imported from
+    pub source: Option<String>,
+    /// Date the signature was added to the database
+    pub added: Option<String>,
+}
+
+/// A hash retracted by a removal list, e.g. because it turned out to be a false positive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removal {
+    /// Lowercase hexadecimal MD5 hash
+    pub hash: String,
+    /// Why the hash was retracted, as given by the list
+    pub reason: Option<String>,
+}
+
+/// A retraction as recorded in the database, so the user can audit what was removed and why
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemovalInfo {
+    pub hash: String,
+    pub reason: Option<String>,
+    /// Name of the source that published the removal list
+    pub source: String,
+    /// Name of the removal list
+    pub list: String,
+    /// Date the removal list was applied
+    pub applied: Option<String>,
+    /// Whether the hash was in the database when it was retracted
+    pub was_present: bool,
+}
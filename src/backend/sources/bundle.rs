@@ -0,0 +1,119 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::read::GzDecoder;
+use log::{info, warn};
+
+use super::{local_dir::LocalDirSource, SignatureSource, SourceError};
+
+/// File extensions recognized as signature bundles
+pub const BUNDLE_EXTENSIONS: [&str; 3] = [".tar.gz", ".tgz", ".tar"];
+
+/// A tar archive, optionally gzip compressed, containing hash lists, e.g. copied over on a USB stick.
+/// The lists are extracted to a work directory, which is removed again once the source is dropped.
+/// Every list is one batch, identified by its file name.
+pub struct BundleSource {
+    extracted: PathBuf,
+    lists: LocalDirSource,
+}
+
+impl BundleSource {
+    /// Extracts all hash lists of the given bundle. Folders inside the bundle are flattened,
+    /// so entries can never be written outside of the work directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the source is tracked under in the database
+    /// * `path` - Path to the `.tar.gz`, `.tgz` or `.tar` file
+    /// * `cache_dir` - Where the work directory is created
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let bundle = BundleSource::extract("import", Path::new("/media/usb/signatures.tar.gz"), &AppPaths::platform().cache_dir).unwrap();
+    /// assert!(!bundle.list_batches().unwrap().is_empty());
+    /// ```
+    pub fn extract(name: &str, path: &Path, cache_dir: &Path) -> Result<Self, SourceError> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos())
+            .unwrap_or_default();
+        let extracted = cache_dir.join(format!("import-{}-{}", process::id(), started));
+        if extracted.exists() {
+            fs::remove_dir_all(&extracted)?;
+        }
+        fs::create_dir_all(&extracted)?;
+        // Created right away, so the directory gets cleaned up even if extracting fails
+        let bundle = BundleSource {
+            lists: LocalDirSource::new(name, &extracted.to_string_lossy()),
+            extracted,
+        };
+
+        info!("Extracting bundle {}", path.display());
+        let file = File::open(path)?;
+        let reader: Box<dyn Read> = if is_compressed(path) {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut archive = tar::Archive::new(reader);
+        let mut extracted_lists = 0;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let file_name = match entry.path()?.file_name() {
+                Some(file_name) => file_name.to_owned(),
+                None => continue,
+            };
+            let target = bundle.extracted.join(&file_name);
+            if target.exists() {
+                warn!("Skipping duplicate list {:?} in bundle", file_name);
+                continue;
+            }
+            io::copy(&mut entry, &mut File::create(target)?)?;
+            extracted_lists += 1;
+        }
+        info!("Extracted {} file(s) from bundle", extracted_lists);
+        Ok(bundle)
+    }
+}
+
+impl Drop for BundleSource {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.extracted) {
+            warn!("Failed removing {}: {}", self.extracted.display(), err);
+        }
+    }
+}
+
+impl SignatureSource for BundleSource {
+    fn name(&self) -> &str {
+        self.lists.name()
+    }
+
+    fn list_batches(&self) -> Result<Vec<String>, SourceError> {
+        self.lists.list_batches()
+    }
+
+    fn fetch_batch(&self, batch: &str) -> Result<Vec<u8>, SourceError> {
+        self.lists.fetch_batch(batch)
+    }
+}
+
+/// Returns true if the given path is a signature bundle, judging by its extension
+pub fn is_bundle(path: &Path) -> bool {
+    let file_name = path.to_string_lossy().to_lowercase();
+    BUNDLE_EXTENSIONS.iter().any(|ext| file_name.ends_with(ext))
+}
+
+fn is_compressed(path: &Path) -> bool {
+    let file_name = path.to_string_lossy().to_lowercase();
+    file_name.ends_with(".gz") || file_name.ends_with(".tgz")
+}
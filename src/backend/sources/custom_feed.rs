@@ -0,0 +1,85 @@
+use log::info;
+use reqwest::{blocking::Client, StatusCode};
+
+use super::{integrity, is_removal_list, SignatureSource, SourceError};
+
+/// A feed described by an index file that lists its hash lists, one per line.
+/// Entries are either absolute URLs or paths relative to the index file.
+/// Each entry is one batch, so new lists only need to be appended to the index.
+/// Entries ending in `.removed` are removal lists retracting hashes of earlier lists.
+pub struct CustomFeedSource {
+    name: String,
+    index_url: String,
+    client: Client,
+}
+
+impl CustomFeedSource {
+    pub fn new(name: &str, index_url: &str, client: Client) -> Self {
+        CustomFeedSource {
+            name: name.to_owned(),
+            index_url: index_url.to_owned(),
+            client,
+        }
+    }
+
+    /// Resolves an index entry to the URL of the hash list
+    fn batch_url(&self, batch: &str) -> String {
+        if batch.starts_with("http://") || batch.starts_with("https://") {
+            return batch.to_owned();
+        }
+        let base = match self.index_url.rfind('/') {
+            Some(pos) => &self.index_url[..=pos],
+            None => "",
+        };
+        format!("{}{}", base, batch.trim_start_matches('/'))
+    }
+
+    fn get(&self, url: &str) -> Result<Vec<u8>, SourceError> {
+        let response = self.client.get(url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(SourceError::NotFound(url.to_owned()));
+        }
+        integrity::read_body(response.error_for_status()?)
+    }
+
+    /// Returns all entries of the index, in the order they are listed
+    fn index_entries(&self) -> Result<Vec<String>, SourceError> {
+        let index = self.get(&self.index_url)?;
+        let index = String::from_utf8(index)
+            .map_err(|err| SourceError::Invalid(format!("Index is not a text file: {}", err)))?;
+        Ok(index
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect())
+    }
+}
+
+impl SignatureSource for CustomFeedSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list_batches(&self) -> Result<Vec<String>, SourceError> {
+        let mut entries = self.index_entries()?;
+        entries.retain(|entry| !is_removal_list(entry));
+        Ok(entries)
+    }
+
+    fn fetch_batch(&self, batch: &str) -> Result<Vec<u8>, SourceError> {
+        let url = self.batch_url(batch);
+        info!("Downloading {}", url);
+        self.get(&url)
+    }
+
+    fn list_removals(&self) -> Result<Vec<String>, SourceError> {
+        let mut entries = self.index_entries()?;
+        entries.retain(|entry| is_removal_list(entry));
+        Ok(entries)
+    }
+
+    fn fetch_removals(&self, list: &str) -> Result<Vec<u8>, SourceError> {
+        self.fetch_batch(list)
+    }
+}
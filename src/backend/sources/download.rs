@@ -0,0 +1,182 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    header::{self, HeaderMap},
+    StatusCode,
+};
+
+use super::SourceError;
+
+/// A download that is streamed into a `.partial` file and continued with a `Range` request
+/// after an interruption. The partial file is kept on disk, so downloads also continue after a restart.
+/// Next to it, the `ETag` or `Last-Modified` of the file is stored, so a file that changed on the
+/// server in the meantime is downloaded from the start instead of being stitched together.
+pub struct ResumableDownload {
+    url: String,
+    path: PathBuf,
+}
+
+impl ResumableDownload {
+    /// Creates a download of the given URL, keeping its partial file in the given directory.
+    /// The file name is derived from the URL, so switching mirrors never mixes up partial files.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let download = ResumableDownload::new(Path::new("/tmp/downloads"), "https://virusshare.com/hashfiles/VirusShare_00000.md5");
+    /// let content = download.fetch(reqwest::blocking::Client::new().get(download.url())).unwrap();
+    /// download.discard();
+    /// ```
+    pub fn new(dir: &Path, url: &str) -> Self {
+        ResumableDownload {
+            url: url.to_owned(),
+            path: dir.join(format!("{}.partial", file_name_for(url))),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the amount of bytes already downloaded
+    pub fn downloaded(&self) -> u64 {
+        fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0)
+    }
+
+    /// Sends the given request for the download's URL and streams the response into the partial file,
+    /// resuming where a previous attempt stopped. Returns the complete content.
+    /// An interrupted transfer is reported as `SourceError::Interrupted` and can simply be fetched again.
+    pub fn fetch(&self, request: RequestBuilder) -> Result<Vec<u8>, SourceError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let offset = self.downloaded();
+        let mut request = request;
+        if offset > 0 {
+            info!("Resuming download of {} at {} bytes", self.url, offset);
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+            if let Ok(validator) = fs::read_to_string(self.validator_path()) {
+                request = request.header(header::IF_RANGE, validator.trim());
+            }
+        }
+
+        let response = request.send()?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Err(SourceError::NotFound(self.url.clone())),
+            StatusCode::PARTIAL_CONTENT => match content_range(response.headers()) {
+                Some((Some(start), total)) if start == offset => {
+                    let file = OpenOptions::new().append(true).open(&self.path)?;
+                    self.stream(response, file, total)
+                }
+                _ => {
+                    self.discard();
+                    Err(SourceError::Interrupted(format!(
+                        "{} answered with an unexpected range, starting over",
+                        self.url
+                    )))
+                }
+            },
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                // The previous attempt already downloaded everything, but the batch was never imported
+                if offset > 0 && content_range(response.headers()).and_then(|(_, total)| total) == Some(offset) {
+                    return Ok(fs::read(&self.path)?);
+                }
+                self.discard();
+                Err(SourceError::Interrupted(format!(
+                    "Partial download of {} is stale, starting over",
+                    self.url
+                )))
+            }
+            _ => {
+                // The whole file is sent, either because nothing was downloaded yet or because it changed
+                let response = response.error_for_status()?;
+                if offset > 0 {
+                    warn!("{} changed since the download was interrupted, starting over", self.url);
+                }
+                self.save_validator(response.headers());
+                let expected = response.content_length();
+                self.stream(response, File::create(&self.path)?, expected)
+            }
+        }
+    }
+
+    /// Removes the partial file, e.g. once its content was imported
+    pub fn discard(&self) {
+        for path in [&self.path, &self.validator_path()] {
+            if let Err(err) = fs::remove_file(path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed removing {}: {}", path.display(), err);
+                }
+            }
+        }
+    }
+
+    /// Copies the response body to the end of the file and returns the complete content.
+    /// Whatever arrived before an interruption stays in the file for the next attempt.
+    fn stream(&self, mut response: Response, mut file: File, expected: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        let copied = io::copy(&mut response, &mut file);
+        file.sync_data()?;
+        if let Err(err) = copied {
+            return Err(SourceError::Interrupted(format!(
+                "Download of {} stopped at {} bytes: {}",
+                self.url,
+                self.downloaded(),
+                err
+            )));
+        }
+
+        let content = fs::read(&self.path)?;
+        match expected {
+            Some(expected) if content.len() as u64 != expected => Err(SourceError::Interrupted(format!(
+                "Download of {} stopped at {} of {} bytes",
+                self.url,
+                content.len(),
+                expected
+            ))),
+            _ => Ok(content),
+        }
+    }
+
+    fn validator_path(&self) -> PathBuf {
+        self.path.with_extension("partial.validator")
+    }
+
+    /// Stores the `ETag` or `Last-Modified` of a fresh download, used as `If-Range` when resuming
+    fn save_validator(&self, headers: &HeaderMap) {
+        let validator = [header::ETAG, header::LAST_MODIFIED]
+            .iter()
+            .find_map(|name| headers.get(name))
+            .and_then(|value| value.to_str().ok());
+        let saved = match validator {
+            Some(validator) => fs::write(self.validator_path(), validator),
+            None => fs::remove_file(self.validator_path()).or(Ok(())),
+        };
+        if let Err(err) = saved {
+            warn!("Failed saving download validator: {}", err);
+        }
+    }
+}
+
+/// Turns a URL into a file name that is valid on every platform, e.g. `virusshare.com_hashfiles`
+pub fn file_name_for(url: &str) -> String {
+    url.split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Parses a `Content-Range: bytes <start>-<end>/<total>` header into its start and total.
+/// The start is None for the `bytes */<total>` form sent with 416 responses.
+fn content_range(headers: &HeaderMap) -> Option<(Option<u64>, Option<u64>)> {
+    let value = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-').and_then(|(start, _)| start.trim().parse().ok());
+    Some((start, total.trim().parse().ok()))
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use log::info;
+use reqwest::{
+    blocking::{Client, ClientBuilder},
+    Certificate, NoProxy, Proxy,
+};
+use serde::{Deserialize, Serialize};
+
+use super::SourceError;
+
+/// Sent as `User-Agent` unless another one is configured
+pub const DEFAULT_USER_AGENT: &str = concat!("Raspirus/", env!("CARGO_PKG_VERSION"));
+
+/// The client shared by all network code, together with the settings it was built with
+static SHARED_CLIENT: Mutex<Option<(HttpConfig, Client)>> = Mutex::new(None);
+
+/// Settings of the HTTP client used for all requests, e.g. to get through a corporate proxy
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Proxy for HTTP and HTTPS requests, e.g. `http://proxy.example.com:3128`.
+    /// If empty, the proxy set in the `HTTP_PROXY` and `HTTPS_PROXY` environment variables is used.
+    pub proxy_url: String,
+    /// Username for the proxy, empty if it needs no authentication
+    pub proxy_username: String,
+    /// Password for the proxy
+    pub proxy_password: String,
+    /// Comma separated hosts, domains and IP ranges reached without the proxy, e.g. `localhost,.example.com,10.0.0.0/8`
+    pub no_proxy: String,
+    /// Seconds to wait for a connection to be established, 0 to wait indefinitely
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for the server to send data, 0 to wait indefinitely
+    pub read_timeout_secs: u64,
+    /// Sent as `User-Agent` header, empty for the default
+    pub user_agent: String,
+    /// Path of a PEM file with additional CA certificates to trust, e.g. a company's internal CA
+    pub ca_certificates: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            proxy_url: String::new(),
+            proxy_username: String::new(),
+            proxy_password: String::new(),
+            no_proxy: String::new(),
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            user_agent: String::new(),
+            ca_certificates: String::new(),
+        }
+    }
+}
+
+/// Returns the client shared by all network code. It is built once and only rebuilt
+/// when the settings change, so connections are reused across sources and updates.
+///
+/// # Examples
+///
+/// ```
+/// let config = Config::new().load(&AppPaths::platform()).unwrap();
+/// let client = http::shared_client(&config.http).unwrap();
+/// ```
+pub fn shared_client(config: &HttpConfig) -> Result<Client, SourceError> {
+    let mut shared = SHARED_CLIENT.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((built_with, client)) = shared.as_ref() {
+        if built_with == config {
+            return Ok(client.clone());
+        }
+    }
+    let client = build_client(config)?;
+    *shared = Some((config.clone(), client.clone()));
+    Ok(client)
+}
+
+/// Builds a new client with the given settings.
+/// Fails if the proxy URL is malformed or the CA certificates can not be read.
+pub fn build_client(config: &HttpConfig) -> Result<Client, SourceError> {
+    let mut builder = ClientBuilder::new()
+        .connect_timeout(seconds(config.connect_timeout_secs))
+        .timeout(seconds(config.read_timeout_secs))
+        .user_agent(match config.user_agent.trim() {
+            "" => DEFAULT_USER_AGENT,
+            user_agent => user_agent,
+        });
+
+    let proxy_url = config.proxy_url.trim();
+    if !proxy_url.is_empty() {
+        info!("Sending requests through proxy {}", proxy_url);
+        let mut proxy = Proxy::all(proxy_url)
+            .map_err(|err| SourceError::Config(format!("Invalid proxy URL {}: {}", proxy_url, err)))?;
+        if !config.proxy_username.is_empty() {
+            proxy = proxy.basic_auth(&config.proxy_username, &config.proxy_password);
+        }
+        builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&config.no_proxy)));
+    }
+
+    let ca_path = config.ca_certificates.trim();
+    if !ca_path.is_empty() {
+        let pem = fs::read(ca_path)
+            .map_err(|err| SourceError::Config(format!("Failed reading CA certificates {}: {}", ca_path, err)))?;
+        let certificates = parse_certificates(&pem)
+            .map_err(|err| SourceError::Config(format!("Invalid CA certificates in {}: {}", ca_path, err)))?;
+        info!("Trusting {} additional CA certificate(s) from {}", certificates.len(), ca_path);
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|err| SourceError::Config(format!("Failed creating HTTP client: {}", err)))
+}
+
+/// Splits a PEM bundle into its certificates, as only the first one would be read otherwise
+fn parse_certificates(pem: &[u8]) -> Result<Vec<Certificate>, String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = std::str::from_utf8(pem).map_err(|_| String::from("not a PEM file"))?;
+    let certificates = pem
+        .split_inclusive(END)
+        .filter(|block| block.contains(END))
+        .map(|block| Certificate::from_pem(block.trim().as_bytes()).map_err(|err| err.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(String::from("no certificate found"));
+    }
+    Ok(certificates)
+}
+
+/// Turns a timeout in seconds into a duration, where 0 means no timeout
+fn seconds(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
@@ -0,0 +1,55 @@
+use chrono::Local;
+use log::info;
+use reqwest::{blocking::Client, header, StatusCode};
+
+use super::{integrity, SignatureSource, SourceError};
+
+/// A single hash list served over HTTP.
+/// The list only ever has one batch, identified by its `ETag` or `Last-Modified` header,
+/// so it gets imported again whenever the server reports a change.
+pub struct HttpListSource {
+    name: String,
+    url: String,
+    client: Client,
+}
+
+impl HttpListSource {
+    pub fn new(name: &str, url: &str, client: Client) -> Self {
+        HttpListSource {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            client,
+        }
+    }
+}
+
+impl SignatureSource for HttpListSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list_batches(&self) -> Result<Vec<String>, SourceError> {
+        let response = self.client.head(&self.url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(SourceError::NotFound(self.url.clone()));
+        }
+        let response = response.error_for_status()?;
+        let version = [header::ETAG, header::LAST_MODIFIED]
+            .iter()
+            .find_map(|name| response.headers().get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim_matches('"').to_owned())
+            // Without any version information the list is imported at most once a day
+            .unwrap_or_else(|| Local::now().format("%Y-%m-%d").to_string());
+        Ok(vec![version])
+    }
+
+    fn fetch_batch(&self, _batch: &str) -> Result<Vec<u8>, SourceError> {
+        info!("Downloading {}", self.url);
+        let response = self.client.get(&self.url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(SourceError::NotFound(self.url.clone()));
+        }
+        integrity::read_body(response.error_for_status()?)
+    }
+}
@@ -0,0 +1,167 @@
+use std::{collections::HashMap, fmt};
+
+use ed25519_dalek::{Signature as Ed25519Signature, VerifyingKey};
+use reqwest::blocking::Response;
+use sha2::{Digest, Sha256};
+
+use super::SourceError;
+
+/// Checksum manifest a mirror can publish next to its hash files, in the format written by `sha256sum`
+pub const MANIFEST_FILE: &str = "SHA256SUMS";
+
+/// Detached Ed25519 signature of the manifest, either the raw 64 bytes or hex encoded
+pub const MANIFEST_SIGNATURE_FILE: &str = "SHA256SUMS.sig";
+
+/// How thoroughly a downloaded batch was verified before importing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// Only checked for error pages and truncation, the source publishes no manifest
+    Unverified,
+    /// The SHA-256 checksum matches an unsigned manifest
+    Checksum,
+    /// The SHA-256 checksum matches a manifest signed with the pinned public key
+    Signed,
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verification::Unverified => write!(f, "not verified, no manifest published"),
+            Verification::Checksum => write!(f, "SHA-256 matches unsigned manifest"),
+            Verification::Signed => write!(f, "SHA-256 matches signed manifest"),
+        }
+    }
+}
+
+/// Checksums of the files listed in a manifest, keyed by file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    checksums: HashMap<String, String>,
+}
+
+impl Manifest {
+    /// Parses a manifest in `sha256sum` format, one `<checksum>  <file name>` entry per line.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let manifest = Manifest::parse(b"9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  VirusShare_00000.md5\n").unwrap();
+    /// assert!(manifest.verify("VirusShare_00000.md5", b"test").is_ok());
+    /// ```
+    pub fn parse(content: &[u8]) -> Result<Self, SourceError> {
+        let content = std::str::from_utf8(content)
+            .map_err(|err| SourceError::Rejected(format!("Manifest is not a text file: {}", err)))?;
+        let mut checksums = HashMap::new();
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (checksum, name) = line
+                .split_once(char::is_whitespace)
+                .map(|(checksum, name)| (checksum, name.trim().trim_start_matches('*')))
+                .filter(|(checksum, name)| {
+                    checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()) && !name.is_empty()
+                })
+                .ok_or_else(|| SourceError::Rejected(format!("Malformed manifest entry: {}", line)))?;
+            checksums.insert(name.to_owned(), checksum.to_lowercase());
+        }
+        if checksums.is_empty() {
+            return Err(SourceError::Rejected(String::from("Manifest is empty")));
+        }
+        Ok(Manifest { checksums })
+    }
+
+    /// Returns the names of all files listed in the manifest
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.checksums.keys().map(String::as_str)
+    }
+
+    /// Checks that the given content matches the checksum listed for the file.
+    /// Files missing from the manifest are rejected as well.
+    pub fn verify(&self, file_name: &str, content: &[u8]) -> Result<(), SourceError> {
+        let expected = self
+            .checksums
+            .get(file_name)
+            .ok_or_else(|| SourceError::Rejected(format!("{} is not listed in the manifest", file_name)))?;
+        let actual = sha256_hex(content);
+        if *expected != actual {
+            return Err(SourceError::Rejected(format!(
+                "Checksum of {} does not match the manifest: expected {}, got {}",
+                file_name, expected, actual
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the detached Ed25519 signature of a manifest against the pinned public key.
+///
+/// # Arguments
+///
+/// * `content` - The raw manifest as downloaded
+/// * `signature` - The signature, either the raw 64 bytes or hex encoded
+/// * `public_key` - The pinned public key, hex encoded
+pub fn verify_signature(content: &[u8], signature: &[u8], public_key: &str) -> Result<(), SourceError> {
+    let key: [u8; 32] = decode_hex(public_key)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| SourceError::Rejected(String::from("Pinned public key is not a hex encoded Ed25519 key")))?;
+    let key = VerifyingKey::from_bytes(&key)
+        .map_err(|err| SourceError::Rejected(format!("Pinned public key is invalid: {}", err)))?;
+
+    let signature: [u8; 64] = match signature.len() {
+        64 => signature.try_into().ok(),
+        _ => std::str::from_utf8(signature)
+            .ok()
+            .and_then(decode_hex)
+            .and_then(|bytes| bytes.try_into().ok()),
+    }
+    .ok_or_else(|| SourceError::Rejected(String::from("Manifest signature is malformed")))?;
+
+    key.verify_strict(content, &Ed25519Signature::from_bytes(&signature))
+        .map_err(|_| SourceError::Rejected(String::from("Manifest signature does not match the pinned public key")))
+}
+
+/// Rejects content that is obviously not a hash list, such as the HTML error pages
+/// some servers and captive portals answer with instead of a 404.
+pub fn check_body(batch: &str, content: &[u8]) -> Result<(), SourceError> {
+    if content.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'<') {
+        return Err(SourceError::Rejected(format!("{} is an HTML page, not a hash list", batch)));
+    }
+    Ok(())
+}
+
+/// Reads the body of a response and rejects it if it is shorter than announced
+/// in its `Content-Length`, which happens when the connection drops mid-transfer.
+pub fn read_body(response: Response) -> Result<Vec<u8>, SourceError> {
+    let url = response.url().to_string();
+    let expected = response.content_length();
+    let body = response.bytes()?.to_vec();
+    if let Some(expected) = expected {
+        if body.len() as u64 != expected {
+            return Err(SourceError::Rejected(format!(
+                "Download of {} truncated at {} of {} bytes",
+                url,
+                body.len(),
+                expected
+            )));
+        }
+    }
+    Ok(body)
+}
+
+/// Returns the SHA-256 of the given content in hexadecimal representation
+pub fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    value
+        .trim()
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((char::from(*high).to_digit(16)? * 16 + char::from(*low).to_digit(16)?) as u8),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,82 @@
+use std::{fs, path::PathBuf};
+
+use log::info;
+
+use super::{SignatureSource, SourceError, REMOVAL_EXTENSION};
+
+/// File extensions of the hash lists picked up from a local directory
+const LIST_EXTENSIONS: [&str; 3] = ["md5", "txt", "csv"];
+
+/// A local directory, e.g. on a USB stick, containing hash lists.
+/// Every file is one batch, identified by its file name. Files ending in `.removed` are removal lists.
+pub struct LocalDirSource {
+    name: String,
+    path: PathBuf,
+}
+
+impl LocalDirSource {
+    pub fn new(name: &str, path: &str) -> Self {
+        LocalDirSource {
+            name: name.to_owned(),
+            path: PathBuf::from(path),
+        }
+    }
+
+    /// Returns the path of the given batch inside the directory
+    fn batch_path(&self, batch: &str) -> Result<PathBuf, SourceError> {
+        // Batches are plain file names, never paths leading out of the directory
+        if batch.contains(['/', '\\']) || batch == ".." {
+            return Err(SourceError::Invalid(format!("{} is not a file name", batch)));
+        }
+        Ok(self.path.join(batch))
+    }
+
+    /// Returns the names of all files in the directory with one of the given extensions, sorted by name
+    fn list_files(&self, extensions: &[&str]) -> Result<Vec<String>, SourceError> {
+        let mut files: Vec<String> = fs::read_dir(&self.path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(|ext| extensions.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+                    .unwrap_or(false)
+            })
+            .filter_map(|path| path.file_name().and_then(|name| name.to_str()).map(str::to_owned))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl SignatureSource for LocalDirSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list_batches(&self) -> Result<Vec<String>, SourceError> {
+        self.list_files(&LIST_EXTENSIONS)
+    }
+
+    fn fetch_batch(&self, batch: &str) -> Result<Vec<u8>, SourceError> {
+        let path = self.batch_path(batch)?;
+        if !path.is_file() {
+            return Err(SourceError::NotFound(batch.to_owned()));
+        }
+        info!("Reading {}", path.display());
+        Ok(fs::read(path)?)
+    }
+
+    fn batch_size(&self, batch: &str) -> Result<Option<u64>, SourceError> {
+        Ok(Some(fs::metadata(self.batch_path(batch)?)?.len()))
+    }
+
+    fn list_removals(&self) -> Result<Vec<String>, SourceError> {
+        self.list_files(&[REMOVAL_EXTENSION])
+    }
+
+    fn fetch_removals(&self, list: &str) -> Result<Vec<u8>, SourceError> {
+        self.fetch_batch(list)
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use log::info;
+
+use super::{SignatureSource, SourceError};
+
+/// A single hash list on disk, optionally gzip compressed.
+/// The list is the only batch, identified by its file name.
+pub struct LocalFileSource {
+    name: String,
+    path: PathBuf,
+}
+
+impl LocalFileSource {
+    pub fn new(name: &str, path: &Path) -> Self {
+        LocalFileSource {
+            name: name.to_owned(),
+            path: path.to_path_buf(),
+        }
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+impl SignatureSource for LocalFileSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list_batches(&self) -> Result<Vec<String>, SourceError> {
+        if !self.path.is_file() {
+            return Err(SourceError::NotFound(self.path.display().to_string()));
+        }
+        Ok(vec![self.file_name()])
+    }
+
+    fn fetch_batch(&self, batch: &str) -> Result<Vec<u8>, SourceError> {
+        if batch != self.file_name() {
+            return Err(SourceError::NotFound(batch.to_owned()));
+        }
+        info!("Reading {}", self.path.display());
+        let mut content = Vec::new();
+        let file = File::open(&self.path)?;
+        if batch.to_lowercase().ends_with(".gz") {
+            GzDecoder::new(file).read_to_end(&mut content)?;
+        } else {
+            let mut file = file;
+            file.read_to_end(&mut content)?;
+        }
+        Ok(content)
+    }
+}
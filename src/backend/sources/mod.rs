@@ -0,0 +1,286 @@
+use std::{
+    fmt, io,
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use serde::{Deserialize, Serialize};
+
+use self::{
+    bundle::BundleSource, custom_feed::CustomFeedSource, http_list::HttpListSource, integrity::Verification,
+    local_dir::LocalDirSource, local_file::LocalFileSource, virusshare::VirusShareSource,
+};
+use super::{
+    app_paths::AppPaths,
+    config_file::Config,
+    signature::{Removal, Severity, Signature},
+};
+
+pub mod bundle;
+pub mod custom_feed;
+pub mod download;
+pub mod http;
+pub mod http_list;
+pub mod integrity;
+pub mod local_dir;
+pub mod local_file;
+pub mod retry;
+pub mod virusshare;
+
+/// Name under which offline imports are tracked in the database
+pub const IMPORT_SOURCE: &str = "import";
+
+/// Extension of removal lists in directories, feed indexes and mirrors, e.g. `2023-false-positives.removed`
+pub const REMOVAL_EXTENSION: &str = "removed";
+
+/// Errors that can occur while listing, fetching or parsing signature batches
+#[derive(Debug)]
+pub enum SourceError {
+    /// The request failed, e.g. because there is no connection
+    Http(reqwest::Error),
+    /// Reading a local file failed
+    Io(io::Error),
+    /// The requested batch does not exist at the source
+    NotFound(String),
+    /// The batch was fetched but its content is unusable
+    Invalid(String),
+    /// The transfer broke off, fetching the batch again continues where it stopped
+    Interrupted(String),
+    /// The batch failed verification, e.g. it was truncated or does not match the manifest
+    Rejected(String),
+    /// The request kept failing until the retry policy was exhausted
+    GaveUp(String),
+    /// The configuration is unusable, e.g. the proxy URL is malformed
+    Config(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Http(err) => write!(f, "HTTP error: {}", err),
+            SourceError::Io(err) => write!(f, "IO error: {}", err),
+            SourceError::NotFound(batch) => write!(f, "Batch {} not found", batch),
+            SourceError::Invalid(reason) => write!(f, "Invalid batch: {}", reason),
+            SourceError::Interrupted(reason) => write!(f, "Download interrupted: {}", reason),
+            SourceError::Rejected(reason) => write!(f, "Rejected batch: {}", reason),
+            SourceError::GaveUp(reason) => write!(f, "Giving up {}", reason),
+            SourceError::Config(reason) => write!(f, "Invalid configuration: {}", reason),
+        }
+    }
+}
+
+impl From<reqwest::Error> for SourceError {
+    fn from(err: reqwest::Error) -> Self {
+        SourceError::Http(err)
+    }
+}
+
+impl From<io::Error> for SourceError {
+    fn from(err: io::Error) -> Self {
+        SourceError::Io(err)
+    }
+}
+
+/// A provider of signature batches, e.g. the numbered VirusShare hash files.
+/// Each source is tracked separately in the database by its name, so that only
+/// batches that have not been imported yet get fetched during an update.
+pub trait SignatureSource: Send + Sync {
+    /// Unique name of the source, used to track its batches in the database
+    fn name(&self) -> &str;
+
+    /// Returns the identifiers of all batches the source currently offers, oldest first
+    fn list_batches(&self) -> Result<Vec<String>, SourceError>;
+
+    /// Downloads or reads the raw content of a single batch
+    fn fetch_batch(&self, batch: &str) -> Result<Vec<u8>, SourceError>;
+
+    /// Checks the raw content of a batch before it gets parsed.
+    /// The default only rejects content that is obviously not a hash list, such as HTML error pages.
+    fn verify_batch(&self, batch: &str, content: &[u8]) -> Result<Verification, SourceError> {
+        integrity::check_body(batch, content)?;
+        Ok(Verification::Unverified)
+    }
+
+    /// Parses the raw content of a batch into signatures.
+    /// The default expects one hash per line with optional metadata, as described in [`parse_hash_list`].
+    fn parse_batch(&self, _batch: &str, content: &[u8]) -> Result<Vec<Signature>, SourceError> {
+        parse_hash_list(content)
+    }
+
+    /// Returns the size of a batch in bytes without fetching it, used to estimate the size of an update.
+    /// None if the source can not tell without downloading the batch, which is the default.
+    fn batch_size(&self, _batch: &str) -> Result<Option<u64>, SourceError> {
+        Ok(None)
+    }
+
+    /// Called once a batch was imported or rejected, so that temporary files such as
+    /// partial downloads can be removed. Does nothing by default.
+    fn finish_batch(&self, _batch: &str) {}
+
+    /// Returns the identifiers of all removal lists the source currently offers, oldest first.
+    /// Removal lists retract hashes published earlier, e.g. known false positives. Sources offer none by default.
+    fn list_removals(&self) -> Result<Vec<String>, SourceError> {
+        Ok(Vec::new())
+    }
+
+    /// Downloads or reads the raw content of a single removal list, as described in [`parse_removal_list`]
+    fn fetch_removals(&self, list: &str) -> Result<Vec<u8>, SourceError> {
+        Err(SourceError::NotFound(list.to_owned()))
+    }
+
+    /// Checks the raw content of a removal list before it gets applied.
+    /// A forged list could retract real malware, so sources that can verify their lists should do so.
+    fn verify_removals(&self, list: &str, content: &[u8]) -> Result<Verification, SourceError> {
+        integrity::check_body(list, content)?;
+        Ok(Verification::Unverified)
+    }
+}
+
+/// Returns true if the given batch or file name is a removal list, judged by its extension
+pub fn is_removal_list(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case(REMOVAL_EXTENSION))
+        .unwrap_or(false)
+}
+
+/// Configuration of a single signature source, stored in the config file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceConfig {
+    /// The numbered hash files published by VirusShare, fetched from the configured mirror URL
+    VirusShare,
+    /// A single hash list served over HTTP, re-imported whenever it changes
+    HttpList { name: String, url: String },
+    /// A local directory containing `.md5` or `.txt` hash lists
+    LocalDirectory { name: String, path: String },
+    /// An index file listing the URLs of hash lists, one per line
+    CustomFeed { name: String, index_url: String },
+}
+
+/// Creates all sources configured in the given config, in the order they are listed.
+/// All of them share the HTTP client configured in [`Config::http`], VirusShare keeps its downloads in the cache directory
+/// and stops searching for its newest file once the given cancel flag is raised.
+pub fn from_config(
+    config: &Config,
+    paths: &AppPaths,
+    cancel: &Arc<AtomicBool>,
+) -> Result<Vec<Box<dyn SignatureSource>>, SourceError> {
+    let client = http::shared_client(&config.http)?;
+    Ok(config
+        .signature_sources
+        .iter()
+        .map(|source| -> Box<dyn SignatureSource> {
+            match source {
+                SourceConfig::VirusShare => Box::new(
+                    VirusShareSource::new(
+                        client.clone(),
+                        &config.mirror_url,
+                        &config.mirror_username,
+                        &config.mirror_token,
+                        &paths.cache_dir,
+                    )
+                    .with_public_key(&config.update_public_key)
+                    .with_cancel_flag(cancel.clone()),
+                ),
+                SourceConfig::HttpList { name, url } => {
+                    Box::new(HttpListSource::new(name, url, client.clone()))
+                }
+                SourceConfig::LocalDirectory { name, path } => Box::new(LocalDirSource::new(name, path)),
+                SourceConfig::CustomFeed { name, index_url } => {
+                    Box::new(CustomFeedSource::new(name, index_url, client.clone()))
+                }
+            }
+        })
+        .collect())
+}
+
+/// Creates a source for an offline import from the given path, which can be
+/// a folder of hash lists, a single hash list or a bundle as described in [`bundle`].
+/// Bundles are extracted to the cache directory.
+///
+/// # Examples
+///
+/// ```
+/// let source = open_import(Path::new("/media/usb/hashes"), &AppPaths::platform()).unwrap();
+/// assert_eq!(source.name(), IMPORT_SOURCE);
+/// ```
+pub fn open_import(path: &Path, paths: &AppPaths) -> Result<Box<dyn SignatureSource>, SourceError> {
+    if path.is_dir() {
+        Ok(Box::new(LocalDirSource::new(IMPORT_SOURCE, &path.to_string_lossy())))
+    } else if !path.is_file() {
+        Err(SourceError::NotFound(path.display().to_string()))
+    } else if bundle::is_bundle(path) {
+        Ok(Box::new(BundleSource::extract(IMPORT_SOURCE, path, &paths.cache_dir)?))
+    } else {
+        Ok(Box::new(LocalFileSource::new(IMPORT_SOURCE, path)))
+    }
+}
+
+/// Parses a list with one MD5 hash per line. Lines starting with `#` are comments.
+/// Feeds can add metadata after the hash, separated by `,`, `;` or tabs, in the order
+/// malware name, severity and first seen date. Empty fields are skipped, anything after
+/// a hash separated by spaces, like the file name in `md5sum` output, is ignored.
+///
+/// # Example
+///
+/// ```
+/// let signatures = parse_hash_list(b"# comment\n93fe4fb85a682907137b0b1051991332,Emotet,high\n").unwrap();
+/// assert_eq!(signatures[0].name.as_deref(), Some("Emotet"));
+/// ```
+pub fn parse_hash_list(content: &[u8]) -> Result<Vec<Signature>, SourceError> {
+    let content = std::str::from_utf8(content)
+        .map_err(|err| SourceError::Invalid(format!("Not a text file: {}", err)))?;
+    Ok(content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split([',', ';', '\t']).map(|field| field.trim());
+            let hash = fields.next()?.split(' ').next()?;
+            if hash.len() != 32 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            let mut field = || fields.next().filter(|value| !value.is_empty()).map(str::to_owned);
+            Some(Signature {
+                hash: hash.to_lowercase(),
+                name: field(),
+                severity: field().and_then(|value| Severity::parse(&value)),
+                first_seen: field(),
+            })
+        })
+        .collect())
+}
+
+/// Parses a removal list with one MD5 hash per line. Lines starting with `#` are comments.
+/// The reason for the retraction can follow the hash, separated by `,`, `;` or a tab.
+///
+/// # Example
+///
+/// ```
+/// let removals = parse_removal_list(b"93fe4fb85a682907137b0b1051991332,Signed Windows driver\n").unwrap();
+/// assert_eq!(removals[0].reason.as_deref(), Some("Signed Windows driver"));
+/// ```
+pub fn parse_removal_list(content: &[u8]) -> Result<Vec<Removal>, SourceError> {
+    let content = std::str::from_utf8(content)
+        .map_err(|err| SourceError::Invalid(format!("Not a text file: {}", err)))?;
+    Ok(content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (hash, reason) = match line.split_once([',', ';', '\t']) {
+                Some((hash, reason)) => (hash.trim(), Some(reason.trim())),
+                None => (line, None),
+            };
+            if hash.len() != 32 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            Some(Removal {
+                hash: hash.to_lowercase(),
+                reason: reason.filter(|reason| !reason.is_empty()).map(str::to_owned),
+            })
+        })
+        .collect())
+}
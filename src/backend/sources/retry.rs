@@ -0,0 +1,229 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    io, thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use reqwest::StatusCode;
+
+use super::SourceError;
+
+/// What kind of failure a request ran into, which decides whether and how soon it is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The file does not exist, retrying does not change that
+    NotFound,
+    /// The server asks to slow down with a 429, or is temporarily unavailable with a 503
+    RateLimited,
+    /// The server did not answer in time
+    Timeout,
+    /// The host name could not be resolved, usually because there is no network at all
+    Dns,
+    /// The TLS handshake failed, e.g. because of an invalid or expired certificate
+    Tls,
+    /// The connection could not be established or broke off
+    Connection,
+    /// The server answered with another 5xx error
+    Server,
+    /// Anything retrying does not help with, such as a 401 or a rejected batch
+    Permanent,
+}
+
+impl Failure {
+    /// Classifies the given error
+    pub fn classify(err: &SourceError) -> Self {
+        match err {
+            SourceError::NotFound(_) => Failure::NotFound,
+            SourceError::Interrupted(_) => Failure::Connection,
+            SourceError::Io(err) if err.kind() == io::ErrorKind::TimedOut => Failure::Timeout,
+            SourceError::Http(err) => Self::classify_http(err),
+            _ => Failure::Permanent,
+        }
+    }
+
+    fn classify_http(err: &reqwest::Error) -> Self {
+        if let Some(status) = err.status() {
+            return match status {
+                StatusCode::NOT_FOUND => Failure::NotFound,
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => Failure::RateLimited,
+                status if status.is_server_error() => Failure::Server,
+                _ => Failure::Permanent,
+            };
+        }
+        if err.is_timeout() {
+            return Failure::Timeout;
+        }
+        // reqwest does not expose resolver and TLS errors, so the underlying errors are inspected
+        let mut causes = String::new();
+        let mut source = err.source();
+        while let Some(cause) = source {
+            causes.push_str(&cause.to_string().to_lowercase());
+            causes.push('\n');
+            source = cause.source();
+        }
+        if ["dns error", "failed to lookup address", "name or service not known", "no such host"]
+            .iter()
+            .any(|pattern| causes.contains(pattern))
+        {
+            Failure::Dns
+        } else if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|pattern| causes.contains(pattern))
+        {
+            Failure::Tls
+        } else if err.is_decode() || err.is_builder() || err.is_redirect() {
+            Failure::Permanent
+        } else {
+            Failure::Connection
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::NotFound => write!(f, "not found"),
+            Failure::RateLimited => write!(f, "the server is rate limiting or unavailable"),
+            Failure::Timeout => write!(f, "the server did not answer in time"),
+            Failure::Dns => write!(f, "the host name could not be resolved, check the network connection"),
+            Failure::Tls => write!(f, "the secure connection failed, check the mirror's certificate"),
+            Failure::Connection => write!(f, "the connection failed"),
+            Failure::Server => write!(f, "the server reported an error"),
+            Failure::Permanent => write!(f, "the request was refused"),
+        }
+    }
+}
+
+/// How often and how patiently failed requests are retried.
+/// Waits grow exponentially with every attempt and are randomized, so that several
+/// workers hitting the same error do not retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(5, Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy making at most `max_attempts` attempts per request.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - How often a request is sent at most, including the first attempt
+    /// * `base_delay` - The wait after the first failed attempt, doubled for every further one
+    /// * `max_delay` - The longest wait between two attempts
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let policy = RetryPolicy::new(3, Duration::from_millis(500), Duration::from_secs(10));
+    /// ```
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            base_delay,
+            max_delay,
+        }
+    }
+
+    /// Returns how long to wait before the next attempt, or None if the request should not be retried.
+    /// Missing files, TLS errors and refused requests are never retried, as retrying does not fix them.
+    /// Without DNS there is usually no network at all, so those are only retried once.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure` - What the last attempt failed with
+    /// * `attempt` - The number of the attempt that failed, starting at 1
+    pub fn delay(&self, failure: Failure, attempt: u32) -> Option<Duration> {
+        let max_attempts = match failure {
+            Failure::NotFound | Failure::Tls | Failure::Permanent => return None,
+            Failure::Dns => self.max_attempts.min(2),
+            _ => self.max_attempts,
+        };
+        if attempt >= max_attempts {
+            return None;
+        }
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        // Rate limited servers get more time to recover
+        let backoff = match failure {
+            Failure::RateLimited => backoff.saturating_mul(4).min(self.max_delay),
+            _ => backoff,
+        };
+        Some(backoff / 2 + jitter(backoff / 2))
+    }
+
+    /// Runs the given request until it succeeds or the policy is exhausted.
+    /// Errors that are not retried, such as `SourceError::NotFound`, are returned unchanged.
+    /// Once retrying was given up on, a `SourceError::GaveUp` describing the failure is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `what` - What is being requested, for the log and the error message
+    /// * `stopped` - Checked while waiting; once it returns true the last error is returned right away
+    /// * `request` - The request to run
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let content = RetryPolicy::default().run("00001", || false, || source.fetch_batch("00001"))?;
+    /// ```
+    pub fn run<T>(
+        &self,
+        what: &str,
+        stopped: impl Fn() -> bool,
+        mut request: impl FnMut() -> Result<T, SourceError>,
+    ) -> Result<T, SourceError> {
+        let mut attempt = 1;
+        loop {
+            let err = match request() {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let failure = Failure::classify(&err);
+            let Some(delay) = self.delay(failure, attempt) else {
+                return match failure {
+                    Failure::NotFound | Failure::Permanent => Err(err),
+                    _ => Err(SourceError::GaveUp(format!("after {} attempt(s), {}: {}", attempt, failure, err))),
+                };
+            };
+            warn!(
+                "Attempt {} for {} failed, {}: {}. Retrying in {:.1} seconds",
+                attempt,
+                what,
+                failure,
+                err,
+                delay.as_secs_f64()
+            );
+            // Waits in short steps, so that a cancelled update does not hang in a long backoff
+            let until = Instant::now() + delay;
+            while Instant::now() < until {
+                if stopped() {
+                    return Err(err);
+                }
+                thread::sleep(until.saturating_duration_since(Instant::now()).min(Duration::from_millis(100)));
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Returns a random duration up to the given maximum
+fn jitter(max: Duration) -> Duration {
+    // Every RandomState is seeded randomly, which is enough randomness for spreading retries
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(max.subsec_nanos());
+    max.mul_f64(hasher.finish() as f64 / u64::MAX as f64)
+}
@@ -0,0 +1,447 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time,
+};
+
+use log::{info, warn};
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header, StatusCode,
+};
+
+use super::{
+    download::{self, ResumableDownload},
+    integrity::{self, Manifest, Verification, MANIFEST_FILE, MANIFEST_SIGNATURE_FILE},
+    is_removal_list, parse_hash_list,
+    retry::RetryPolicy, SignatureSource, SourceError,
+};
+use crate::backend::signature::Signature;
+
+/// Where the hash files are published by VirusShare itself
+pub const DEFAULT_MIRROR_URL: &str = "https://virusshare.com/hashfiles";
+
+/// Highest file number searched for, VirusShare publishes a few hundred files.
+/// Keeps a mirror that answers every request with success from being probed forever.
+const MAX_FILE_NR: i32 = 100_000;
+
+/// Index of the removal lists, one file name per line, on mirrors that publish no manifest
+const REMOVALS_INDEX: &str = "removals.txt";
+
+/// The numbered hash files published at `https://virusshare.com/hashfiles/VirusShare_NNNNN.md5`,
+/// or at any mirror using the same layout.
+/// Mirrors can publish a checksum manifest, optionally signed, that every hash file is verified against.
+/// Removal lists retracting false positives are the `.removed` files listed in the manifest,
+/// or in a `removals.txt` index on mirrors without manifest.
+pub struct VirusShareSource {
+    client: Client,
+    base_url: String,
+    username: String,
+    token: String,
+    public_key: String,
+    /// Where interrupted downloads are kept until they are resumed
+    download_dir: PathBuf,
+    /// The manifest once it has been fetched, None inside if the mirror publishes none
+    manifest: Mutex<Option<Option<(Manifest, Verification)>>>,
+    /// How often the requests searching for the newest file are retried
+    retry: RetryPolicy,
+    /// Where the number of the newest file found on this mirror is kept, so the next search starts there
+    latest_cache: PathBuf,
+    /// Stops retrying the requests searching for the newest file once it is raised
+    cancel: Arc<AtomicBool>,
+}
+
+impl VirusShareSource {
+    /// Creates a source fetching from the given base URL.
+    /// If a token is set, it is sent as bearer token, or together with the username as basic auth.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The HTTP client used for all requests
+    /// * `base_url` - The URL of the directory containing the `VirusShare_NNNNN.md5` files
+    /// * `username` - Username for the mirror, may be empty
+    /// * `token` - Token or password for the mirror, may be empty
+    /// * `cache_dir` - Where partial downloads and the newest file of the mirror are kept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let paths = AppPaths::platform();
+    /// let source = VirusShareSource::new(reqwest::blocking::Client::new(), "http://10.0.0.2/hashes", "", "", &paths.cache_dir);
+    /// ```
+    pub fn new(client: Client, base_url: &str, username: &str, token: &str, cache_dir: &Path) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        VirusShareSource {
+            client,
+            base_url: base_url.to_owned(),
+            username: username.to_owned(),
+            token: token.to_owned(),
+            public_key: String::new(),
+            download_dir: cache_dir.join("downloads"),
+            manifest: Mutex::new(None),
+            retry: RetryPolicy::default(),
+            latest_cache: cache_dir
+                .join("mirrors")
+                .join(format!("{}.latest", download::file_name_for(base_url))),
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Pins the Ed25519 public key the mirror's manifest has to be signed with.
+    /// Once set, hash files are only accepted from mirrors publishing a correctly signed manifest.
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - The hex encoded public key, may be empty to accept unsigned mirrors
+    pub fn with_public_key(mut self, public_key: &str) -> Self {
+        self.public_key = public_key.trim().to_owned();
+        self
+    }
+
+    /// Sets the flag that cancels the update, so the search for the newest file does not keep retrying after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - The cancel flag of the running update
+    pub fn with_cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Fetches the mirror's manifest and checks its signature.
+    /// Returns None if the mirror publishes no manifest and no public key is pinned.
+    fn fetch_manifest(&self) -> Result<Option<(Manifest, Verification)>, SourceError> {
+        let url = format!("{}/{}", self.base_url, MANIFEST_FILE);
+        info!("Downloading {url}");
+        let response = self.request(&url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            if self.public_key.is_empty() {
+                return Ok(None);
+            }
+            return Err(SourceError::Rejected(String::from(
+                "A public key is pinned, but the mirror publishes no manifest",
+            )));
+        }
+        let content = integrity::read_body(response.error_for_status()?)?;
+        let manifest = Manifest::parse(&content)?;
+        if self.public_key.is_empty() {
+            return Ok(Some((manifest, Verification::Checksum)));
+        }
+
+        let url = format!("{}/{}", self.base_url, MANIFEST_SIGNATURE_FILE);
+        let response = self.request(&url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(SourceError::Rejected(String::from("The mirror's manifest is not signed")));
+        }
+        let signature = integrity::read_body(response.error_for_status()?)?;
+        integrity::verify_signature(&content, &signature, &self.public_key)?;
+        Ok(Some((manifest, Verification::Signed)))
+    }
+
+    /// Runs the given function with the mirror's manifest, fetching it on first use.
+    /// The manifest is only cached once fetched successfully, so a dropped connection is retried.
+    fn with_manifest<T>(
+        &self,
+        f: impl FnOnce(Option<&(Manifest, Verification)>) -> Result<T, SourceError>,
+    ) -> Result<T, SourceError> {
+        let mut cached = self.manifest.lock().expect("Manifest lock poisoned");
+        if cached.is_none() {
+            *cached = Some(self.fetch_manifest()?);
+        }
+        f(cached.as_ref().and_then(Option::as_ref))
+    }
+
+    /// Returns the URL of the hash file with the given number
+    fn file_url(&self, file_nr: i32) -> String {
+        format!("{}/{}", self.base_url, Self::file_name(file_nr))
+    }
+
+    /// Returns the name of the hash file with the given number, as listed in the manifest
+    fn file_name(file_nr: i32) -> String {
+        format!("VirusShare_{:0>5}.md5", file_nr)
+    }
+
+    /// Builds a GET request for the given URL, with the configured credentials
+    fn request(&self, url: &str) -> RequestBuilder {
+        self.with_credentials(self.client.get(url))
+    }
+
+    /// Adds the configured credentials to the given request
+    fn with_credentials(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.token.is_empty() {
+            request
+        } else if self.username.is_empty() {
+            request.bearer_auth(&self.token)
+        } else {
+            request.basic_auth(&self.username, Some(&self.token))
+        }
+    }
+
+    /// Returns the number of the newest file that is present online, or -1 if there is none.
+    /// If the mirror publishes a manifest, the newest file listed there is taken. Otherwise the mirror
+    /// is probed with HEAD requests, starting at the newest file found last time: an exponential search
+    /// finds a missing file, then a binary search the last existing one before it.
+    /// The search gives up on mirrors claiming to have more than 100000 files.
+    /// Failed requests are retried, once the retry policy is exhausted or the update is cancelled the error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let source = VirusShareSource::new(reqwest::blocking::Client::new(), DEFAULT_MIRROR_URL, "", "", &AppPaths::platform().cache_dir);
+    /// assert!(source.get_file_list().unwrap() > 0);
+    /// ```
+    pub fn get_file_list(&self) -> Result<i32, SourceError> {
+        let listed = self.with_manifest(|manifest| {
+            Ok(manifest.and_then(|(manifest, _)| {
+                manifest
+                    .files()
+                    .filter_map(|name| name.strip_prefix("VirusShare_")?.strip_suffix(".md5")?.parse::<i32>().ok())
+                    .max()
+            }))
+        });
+        match listed {
+            Ok(Some(latest)) => {
+                info!("Newest file listed in the manifest is {}", latest);
+                return Ok(latest);
+            }
+            Ok(None) => (),
+            Err(err) => warn!("Searching for the newest file, as the manifest is unusable: {}", err),
+        }
+
+        let exists = |file_nr| {
+            self.retry.run(
+                &Self::file_name(file_nr),
+                || self.cancel.load(Ordering::SeqCst),
+                || self.file_exists(file_nr),
+            )
+        };
+        let cached = self.cached_latest();
+        // Invariant of the search: `low` exists and `high` does not
+        let (mut low, mut high) = if exists(cached)? {
+            let (mut low, mut step) = (cached, 1);
+            while exists(low + step)? {
+                low += step;
+                if low >= MAX_FILE_NR {
+                    return Err(SourceError::Rejected(format!(
+                        "The mirror claims to have more than {} hash files",
+                        MAX_FILE_NR
+                    )));
+                }
+                step = (step * 2).min(MAX_FILE_NR - low);
+            }
+            (low, low + step)
+        } else if cached > 0 && exists(0)? {
+            // The mirror has fewer files than last time, e.g. because it was switched
+            (0, cached)
+        } else {
+            return Ok(-1);
+        };
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if exists(middle)? {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        info!("Newest file on the mirror is {}", low);
+        self.cache_latest(low);
+        Ok(low)
+    }
+
+    /// Returns the newest file found on this mirror during the last search, or 0 if it was never searched
+    fn cached_latest(&self) -> i32 {
+        fs::read_to_string(&self.latest_cache)
+            .ok()
+            .and_then(|cached| cached.trim().parse().ok())
+            .filter(|cached| (0..MAX_FILE_NR).contains(cached))
+            .unwrap_or(0)
+    }
+
+    /// Remembers the newest file found on this mirror for the next search
+    fn cache_latest(&self, latest: i32) {
+        let cached = match self.latest_cache.parent() {
+            Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(&self.latest_cache, latest.to_string())),
+            None => fs::write(&self.latest_cache, latest.to_string()),
+        };
+        if let Err(err) = cached {
+            warn!("Failed caching the newest file number: {}", err);
+        }
+    }
+
+    /// Returns whether the file with the specified file number exists online.
+    /// Answers other than success or 404, such as rate limiting, are returned as error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let source = VirusShareSource::new(reqwest::blocking::Client::new(), DEFAULT_MIRROR_URL, "", "", &AppPaths::platform().cache_dir);
+    /// assert!(source.file_exists(123).unwrap_or(false));
+    /// ```
+    pub fn file_exists(&self, file_nr: i32) -> Result<bool, SourceError> {
+        info!("Checking if file {file_nr} exists...");
+        let response = self.head(file_nr)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(false)
+        } else {
+            response.error_for_status()?;
+            Ok(true)
+        }
+    }
+
+    /// Sends a HEAD request for the file with the given number.
+    /// Falls back to a GET request for servers that do not support HEAD, without reading the body.
+    fn head(&self, file_nr: i32) -> Result<Response, SourceError> {
+        let url = self.file_url(file_nr);
+        let response = self.with_credentials(self.client.head(&url)).send()?;
+        match response.status() {
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => Ok(self.request(&url).send()?),
+            _ => Ok(response),
+        }
+    }
+}
+
+impl SignatureSource for VirusShareSource {
+    fn name(&self) -> &str {
+        "virusshare"
+    }
+
+    fn list_batches(&self) -> Result<Vec<String>, SourceError> {
+        // A pinned key has to cover every hash file, so the update is aborted before the first download
+        // instead of fetching each file only to reject it
+        if !self.public_key.is_empty() {
+            self.with_manifest(|_| Ok(()))?;
+        }
+        Ok((0..=self.get_file_list()?)
+            .map(|file_nr| format!("{:0>5}", file_nr))
+            .collect())
+    }
+
+    fn fetch_batch(&self, batch: &str) -> Result<Vec<u8>, SourceError> {
+        let file_nr = batch
+            .parse::<i32>()
+            .map_err(|_| SourceError::Invalid(format!("{} is not a file number", batch)))?;
+        let download = ResumableDownload::new(&self.download_dir, &self.file_url(file_nr));
+        info!("Downloading {}", download.url());
+        let big_tic = time::Instant::now();
+        let bytes = match download.fetch(self.request(download.url())) {
+            Err(SourceError::NotFound(_)) => return Err(SourceError::NotFound(batch.to_owned())),
+            result => result?,
+        };
+        let size = bytes.len();
+        let big_toc = time::Instant::now();
+        info!(
+            "=> Downloaded file of size {} mb in {} seconds",
+            size as f64 * 0.000001,
+            big_toc.duration_since(big_tic).as_secs_f64()
+        );
+        Ok(bytes)
+    }
+
+    fn batch_size(&self, batch: &str) -> Result<Option<u64>, SourceError> {
+        let file_nr = batch
+            .parse::<i32>()
+            .map_err(|_| SourceError::Invalid(format!("{} is not a file number", batch)))?;
+        let response = self.head(file_nr)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(SourceError::NotFound(batch.to_owned()));
+        }
+        // Read from the header, as the body of a HEAD response is always empty
+        Ok(response
+            .error_for_status()?
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse().ok()))
+    }
+
+    fn verify_batch(&self, batch: &str, content: &[u8]) -> Result<Verification, SourceError> {
+        integrity::check_body(batch, content)?;
+        // VirusShare answers with a short notice instead of a hash file when rate limiting
+        if content.split(|byte| *byte == b'\n').count() == 9 {
+            warn!("Notice instead of hash file: {}", String::from_utf8_lossy(content));
+            return Err(SourceError::Rejected(format!("File {} only contains a notice", batch)));
+        }
+
+        self.with_manifest(|manifest| match manifest {
+            Some((manifest, verification)) => {
+                let file_nr = batch
+                    .parse::<i32>()
+                    .map_err(|_| SourceError::Invalid(format!("{} is not a file number", batch)))?;
+                manifest.verify(&Self::file_name(file_nr), content)?;
+                Ok(*verification)
+            }
+            None => Ok(Verification::Unverified),
+        })
+    }
+
+    fn parse_batch(&self, _batch: &str, content: &[u8]) -> Result<Vec<Signature>, SourceError> {
+        parse_hash_list(content)
+    }
+
+    fn finish_batch(&self, batch: &str) {
+        if let Ok(file_nr) = batch.parse::<i32>() {
+            ResumableDownload::new(&self.download_dir, &self.file_url(file_nr)).discard();
+        }
+    }
+
+    fn list_removals(&self) -> Result<Vec<String>, SourceError> {
+        // Unlike the hash files, removal lists are never searched for if the manifest is unusable,
+        // as a pinned key has to cover every list that retracts hashes
+        let listed = self.with_manifest(|manifest| {
+            Ok(manifest.map(|(manifest, _)| {
+                manifest
+                    .files()
+                    .filter(|name| is_removal_list(name))
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            }))
+        })?;
+        let mut lists = match listed {
+            Some(lists) => lists,
+            None => {
+                let url = format!("{}/{}", self.base_url, REMOVALS_INDEX);
+                info!("Downloading {url}");
+                let response = self.request(&url).send()?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok(Vec::new());
+                }
+                let index = integrity::read_body(response.error_for_status()?)?;
+                String::from_utf8_lossy(&index)
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| is_removal_list(line) && !line.contains('/'))
+                    .map(str::to_owned)
+                    .collect()
+            }
+        };
+        lists.sort();
+        Ok(lists)
+    }
+
+    fn fetch_removals(&self, list: &str) -> Result<Vec<u8>, SourceError> {
+        let url = format!("{}/{}", self.base_url, list);
+        info!("Downloading {url}");
+        let response = self.request(&url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(SourceError::NotFound(list.to_owned()));
+        }
+        integrity::read_body(response.error_for_status()?)
+    }
+
+    fn verify_removals(&self, list: &str, content: &[u8]) -> Result<Verification, SourceError> {
+        integrity::check_body(list, content)?;
+        self.with_manifest(|manifest| match manifest {
+            Some((manifest, verification)) => {
+                manifest.verify(list, content)?;
+                Ok(*verification)
+            }
+            None => Ok(Verification::Unverified),
+        })
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    io::{Error, ErrorKind, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+use flume::{Receiver, RecvTimeoutError, Sender};
+use log::warn;
+
+/// Size of the chunks entries are read in
+const CHUNK_SIZE: usize = 65536; // 64KB
+/// Amount of buffers passed back and forth between the reader thread and the caller, reused for every job
+const CHUNK_COUNT: usize = 4;
+
+/// Called by a job once for every entry it contains, with the name of the entry and a reader for its content
+pub type Visitor<'a> = dyn FnMut(&str, &mut dyn Read) + 'a;
+
+/// Work for the reader thread: opens something and passes every entry in it to the visitor.
+/// A plain file is a single entry, a container such as a disk image has one entry per stored file.
+pub type ReadJob = Box<dyn FnOnce(&mut Visitor) -> Result<(), Error> + Send>;
+
+/// The MD5 hash of an entry, None if the entry was empty, or why it could not be read
+pub type EntryHash = (String, Result<Option<String>, Error>);
+
+/// What became of a job
+#[derive(Debug)]
+pub enum ReadOutcome {
+    /// The job ran to its end. Contains the hash of every entry, or why the job failed, e.g. because the file could not be opened
+    Finished(Result<Vec<EntryHash>, Error>),
+    /// A read stalled or the job exceeded its time budget, contains the entries hashed until then
+    TimedOut(Vec<EntryHash>),
+}
+
+/// Sent from the reader thread while it works on a job
+enum Event {
+    Start(String),
+    Chunk(Vec<u8>, usize),
+    End,
+    Failed(Error),
+    Done(Result<(), Error>),
+}
+
+/// Hashes files on a separate thread, so that a read blocking on a bad sector can be abandoned.
+/// The thread and its buffers are reused for every job, it is only replaced after it got stuck.
+/// A stuck thread stops on its own once its read returns, or never if the device hangs for good.
+pub struct TimedReader {
+    stall_timeout: Duration,
+    jobs: Sender<ReadJob>,
+    events: Receiver<Event>,
+    free: Sender<Vec<u8>>,
+}
+
+impl TimedReader {
+    /// Starts the reader thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `stall_timeout` - Maximum time a single read may block before the job is abandoned
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut reader = TimedReader::new(Duration::from_secs(15));
+    /// let outcome = reader.hash(Box::new(|visitor| {
+    ///     visitor("", &mut File::open("/path/to/file.exe")?);
+    ///     Ok(())
+    /// }), Duration::from_secs(60));
+    /// ```
+    pub fn new(stall_timeout: Duration) -> Self {
+        let (jobs, events, free) = Self::spawn();
+        TimedReader {
+            stall_timeout,
+            jobs,
+            events,
+            free,
+        }
+    }
+
+    /// Runs the job on the reader thread and returns the MD5 hash of every entry it visits.
+    /// The job is abandoned if a single read blocks for longer than the stall timeout,
+    /// or if the whole job takes longer than the given budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - Opens what should be read and passes its entries to the visitor
+    /// * `budget` - The time the whole job may take
+    pub fn hash(&mut self, job: ReadJob, budget: Duration) -> ReadOutcome {
+        let deadline = Instant::now() + budget;
+        if let Err(flume::SendError(job)) = self.jobs.send(job) {
+            warn!("Reader thread stopped, starting a new one");
+            self.replace();
+            let _ = self.jobs.send(job);
+        }
+
+        let mut entries = Vec::new();
+        let mut current: Option<(String, md5::Context, bool)> = None;
+        loop {
+            let wait = self.stall_timeout.min(deadline.saturating_duration_since(Instant::now()));
+            match self.events.recv_timeout(wait) {
+                Ok(Event::Start(name)) => current = Some((name, md5::Context::new(), false)),
+                Ok(Event::Chunk(buffer, count)) => {
+                    if let Some((_, context, read)) = current.as_mut() {
+                        context.consume(&buffer[..count]);
+                        *read = true;
+                    }
+                    let _ = self.free.send(buffer);
+                }
+                Ok(Event::End) => {
+                    if let Some((name, context, read)) = current.take() {
+                        let hash = read.then(|| format!("{:?}", context.compute()));
+                        entries.push((name, Ok(hash)));
+                    }
+                }
+                Ok(Event::Failed(err)) => {
+                    if let Some((name, _, _)) = current.take() {
+                        entries.push((name, Err(err)));
+                    }
+                }
+                Ok(Event::Done(result)) => return ReadOutcome::Finished(result.map(|()| entries)),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.replace();
+                    return ReadOutcome::TimedOut(entries);
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.replace();
+                    return ReadOutcome::Finished(Err(Error::other("Reader thread stopped")));
+                }
+            }
+        }
+    }
+
+    /// Leaves the current thread behind and starts a fresh one with new buffers
+    fn replace(&mut self) {
+        let (jobs, events, free) = Self::spawn();
+        self.jobs = jobs;
+        self.events = events;
+        self.free = free;
+    }
+
+    fn spawn() -> (Sender<ReadJob>, Receiver<Event>, Sender<Vec<u8>>) {
+        let (jobs, job_queue) = flume::unbounded::<ReadJob>();
+        let (event_sender, events) = flume::unbounded();
+        let (free, free_queue) = flume::unbounded();
+        thread::Builder::new()
+            .name(String::from("file-reader"))
+            .spawn(move || {
+                let mut pool: Vec<Vec<u8>> = (0..CHUNK_COUNT).map(|_| vec![0; CHUNK_SIZE]).collect();
+                // Set once the caller gave up on this thread, remaining entries are skipped
+                let mut abandoned = false;
+                for job in job_queue.iter() {
+                    let mut visitor = |name: &str, reader: &mut dyn Read| {
+                        if abandoned || event_sender.send(Event::Start(name.to_owned())).is_err() {
+                            abandoned = true;
+                            return;
+                        }
+                        let event = loop {
+                            let mut buffer = match pool.pop().map(Ok).unwrap_or_else(|| free_queue.recv()) {
+                                Ok(buffer) => buffer,
+                                Err(_) => {
+                                    abandoned = true;
+                                    return;
+                                }
+                            };
+                            match reader.read(&mut buffer) {
+                                Ok(0) => {
+                                    pool.push(buffer);
+                                    break Event::End;
+                                }
+                                Ok(count) => {
+                                    if event_sender.send(Event::Chunk(buffer, count)).is_err() {
+                                        abandoned = true;
+                                        return;
+                                    }
+                                }
+                                Err(err) if err.kind() == ErrorKind::Interrupted => pool.push(buffer),
+                                Err(err) => {
+                                    pool.push(buffer);
+                                    break Event::Failed(err);
+                                }
+                            }
+                        };
+                        abandoned = event_sender.send(event).is_err();
+                    };
+                    let result = job(&mut visitor);
+                    if abandoned || event_sender.send(Event::Done(result)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to start file reader");
+        (jobs, events, free)
+    }
+}
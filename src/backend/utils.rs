@@ -1,16 +1,13 @@
-use chrono::{Utc, DateTime, Local};
-use directories_next::ProjectDirs;
 use flume::Sender;
 use job_scheduler_ng::{Job, JobScheduler};
 use log::{error, info, warn};
 use std::{
     env,
     ffi::{OsStr, OsString},
-    fs::{self, File},
-    io::Write,
+    fs,
     iter::once,
-    path::Path,
-    process::exit,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
     time::{self, Duration}
 };
 use tokio::runtime::Runtime;
@@ -22,7 +19,15 @@ use winapi::um::fileapi::GetDriveTypeW;
 #[cfg(windows)]
 use winapi::um::winbase::DRIVE_REMOVABLE;
 
-use super::{config_file::Config, db_ops::DBOps, file_scanner};
+use super::{
+    config_file::Config,
+    db_health::{HealthReport, RepairReport},
+    db_ops::UpdateCheck,
+    db_worker::DBHandle,
+    file_log::UpdateLog,
+    file_scanner::{self, ScanResult},
+    signature::RemovalInfo,
+};
 
 pub struct Utils {}
 
@@ -34,11 +39,12 @@ pub struct UsbDevice {
 
 impl Utils {
     pub async fn start_scanner(
+        db: DBHandle,
         path: String,
         sender: Option<Sender<f32>>,
-    ) -> Result<Vec<String>, String> {
+    ) -> Result<ScanResult, String> {
         info!("Started Virus scanner on the backend");
-        let mut fs = match file_scanner::FileScanner::new(&path, sender) {
+        let mut fs = match file_scanner::FileScanner::new(&path, sender, db) {
             Ok(fs) => fs,
             Err(err) => {
                 error!("{}", err);
@@ -47,58 +53,113 @@ impl Utils {
         };
         let config = Config::new();
         let obfuscated = config.obfuscated_is_active;
-        let dirty_files = match fs.search_files(obfuscated) {
-            Ok(files) => files,
+        let result = match fs.search_files(obfuscated) {
+            Ok(result) => result,
             Err(e) => {
                 error!("{}", e);
                 return Err(e);
             }
         };
 
-        Ok(dirty_files)
+        Ok(result)
     }
 
-    pub async fn update_database(sender: Option<Sender<f32>>) -> Result<String, String> {
+    /// Updates the database from all configured sources.
+    /// Start, end and the verification result of every downloaded batch are written to a new update log.
+    /// Raising the cancel flag stops the update after the files in progress, which is reported as error.
+    pub async fn update_database(
+        db: DBHandle,
+        sender: Option<Sender<f32>>,
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<String, String> {
         info!("Started Database update on the backend");
-        let mut db_connection = match DBOps::new(sender) {
-            Ok(db_conn) => db_conn,
-            Err(err) => {
-                error!("{err}");
-                return Err(err.to_string());
-            }
-        };
+        let update_log = UpdateLog::new(db.paths());
+        update_log.log("DB update executed");
 
         let big_tic = time::Instant::now();
-        match tokio::task::spawn_blocking(move || {
-            let hash_count;
-            match db_connection.update_db() {
-                Ok(res) => {
-                    hash_count = res;
-                }
-                Err(err) => {
-                    error!("{err}");
-                    exit(-1)
-                }
-            }
-            hash_count
-        })
-        .await
-        {
+        match db.update(sender, cancel, Some(update_log.clone())).await {
             Ok(res) => {
                 let big_toc = time::Instant::now();
                 info!(
                     "Updated DB in {} seconds",
                     big_toc.duration_since(big_tic).as_secs_f64()
                 );
+                update_log.log(&format!("DB update finished with {} hashes", res));
                 Ok(serde_json::to_string(&res).unwrap_or_default())
             }
-            Err(err) => {
-                error!("{err}");
-                return Err(err.to_string());
+            Err(stopped) => {
+                info!("{}", stopped);
+                Err(stopped)
             }
         }
     }
 
+    /// Checks how many signature files an update would download, without downloading them.
+    pub async fn check_for_updates(db: DBHandle) -> Result<UpdateCheck, String> {
+        info!("Started update check on the backend");
+        db.check_updates().await
+    }
+
+    /// Imports signatures without network access, from a folder of hash lists,
+    /// a single hash list or a compressed bundle. Progress is reported through the sender.
+    /// Returns the total amount of hashes in the database afterwards.
+    pub async fn import_signatures(
+        db: DBHandle,
+        path: String,
+        sender: Option<Sender<f32>>,
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<String, String> {
+        info!("Started signature import from {} on the backend", path);
+        let big_tic = time::Instant::now();
+        let res = db.import(PathBuf::from(path), sender, cancel).await?;
+        let big_toc = time::Instant::now();
+        info!(
+            "Imported signatures in {} seconds",
+            big_toc.duration_since(big_tic).as_secs_f64()
+        );
+        Ok(serde_json::to_string(&res).unwrap_or_default())
+    }
+
+    /// Exports the signature database to a compressed, checksummed backup at the given path.
+    /// Returns the amount of exported hashes.
+    pub async fn export_database(db: DBHandle, path: String) -> Result<String, String> {
+        info!("Started database export to {} on the backend", path);
+        db.export(PathBuf::from(path)).await.map(|hash_count| hash_count.to_string())
+    }
+
+    /// Verifies the backup at the given path and then either replaces the signature database with it,
+    /// or merges its signatures into the current database. The current database is left untouched
+    /// if the backup fails verification. Returns the amount of hashes in the database afterwards.
+    pub async fn restore_database(db: DBHandle, path: String, merge: bool) -> Result<String, String> {
+        info!("Started database restore from {} on the backend", path);
+        db.restore(PathBuf::from(path), merge)
+            .await
+            .map(|hash_count| hash_count.to_string())
+    }
+
+    /// Checks the signature database for corruption and batches that lost hashes.
+    /// The thorough check runs SQLite's full integrity check, which can take minutes on large databases.
+    pub async fn check_database(db: DBHandle, thorough: bool) -> Result<HealthReport, String> {
+        info!("Started database health check on the backend");
+        let report = db.check_health(thorough).await?;
+        if !report.is_healthy() {
+            warn!("Database is unhealthy: {}", report.summary());
+        }
+        Ok(report)
+    }
+
+    /// Rebuilds the signature database from its readable rows.
+    /// Batches that lost hashes are removed, so that the next update downloads them again.
+    pub async fn repair_database(db: DBHandle) -> Result<RepairReport, String> {
+        info!("Started database repair on the backend");
+        db.repair().await
+    }
+
+    /// Returns every hash retracted by a removal list, with the list and reason, so the user can audit them.
+    pub async fn list_removals(db: DBHandle) -> Result<Vec<RemovalInfo>, String> {
+        db.removals().await
+    }
+
     pub fn list_usb_drives() -> Result<Vec<UsbDevice>, String> {
         let mut usb_drives = Vec::new();
 
@@ -199,7 +260,7 @@ impl Utils {
         Ok(usb_drives)
     }
 
-    pub async fn auto_update_scheduler(hour: i32, weekday: i32) {
+    pub async fn auto_update_scheduler(db: DBHandle, hour: i32, weekday: i32) {
         // ISSUE: Needs to restart app to apply new update schedule
 
         // In cron, the time is in 24h format, while the weekday starts at 0 = sunday and 6 = saturday
@@ -211,28 +272,11 @@ impl Utils {
         scheduler.add(Job::new(
             cron_schedule.parse().expect("Given CronSyntax is invalid"),
             move || {
-                // Check current time and use it for the name of the update logs file
-                let now: DateTime<Local> = Local::now();
-                let now_str = now.format("%Y_%m_%d_%H_%M_%S").to_string();
-                let log_str = format!("{}.log", now_str);
-                // Write to logs that the update function has started
-                let message = format!("{} DB update executed\n", Utc::now());
-                Self::log_update_res(&message, log_str.clone()).expect("Failed to write update logs to file.");
-                // Execute the async function using Tokio's Runtime
+                // Execute the async function using Tokio's Runtime, it writes the update log itself
                 let runtime = Runtime::new().expect("Unable to create AutoUpdate Runtime");
-                match runtime.block_on(Self::update_database(None)) {
-                    Ok(result) => {
-                        let message = format!("{} DB update finished\n", Utc::now());
-                        Self::log_update_res(&message, log_str.clone())
-                            .expect("Failed to write update logs to file.");
-                        info!("AutoUpdate finished with: {}", result);
-                    }
-                    Err(error) => {
-                        let message = format!("{} DB update error {}\n", Utc::now(), error);
-                        Self::log_update_res(&message, log_str.clone())
-                            .expect("Failed to write update logs to file.");
-                        error!("AutoUpdate failed with: {}", error)
-                    }
+                match runtime.block_on(Self::update_database(db.clone(), None, None)) {
+                    Ok(result) => info!("AutoUpdate finished with: {}", result),
+                    Err(error) => error!("AutoUpdate failed with: {}", error),
                 };
             },
         ));
@@ -243,16 +287,4 @@ impl Utils {
             std::thread::sleep(Duration::from_millis(500));
         }
     }
-
-    fn log_update_res(data: &str, fname: String) -> std::io::Result<()> {
-        let project_dirs = ProjectDirs::from("com", "Raspirus", "Logs").expect("Failed to get project directories.");
-        let log_dir = project_dirs.data_local_dir().join("updates"); 
-        // Open the file (creates if it doesn't exist)
-        let mut file = File::create(log_dir.join(fname)).unwrap();
-        // Write the data to the file
-        file.write_all(data.as_bytes())?;
-        // Flush the buffer to ensure all data is written
-        file.flush()?;
-        Ok(())
-    }
 }
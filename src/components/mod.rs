@@ -3,4 +3,5 @@ pub mod virus_card;
 pub mod languages;
 pub mod progress_sub;
 pub mod updating_sub;
-pub mod modal_widget;
\ No newline at end of file
+pub mod modal_widget;
+pub mod scan_warning;
\ No newline at end of file
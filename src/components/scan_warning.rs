@@ -0,0 +1,58 @@
+use iced::widget::{scrollable, text, Column, Container, Row, Space};
+use iced::{alignment, Element, Length};
+use iced_aw::{Icon, ICON_FONT};
+use rust_i18n::t;
+
+use crate::backend::file_scanner::ScanResult;
+use crate::Message;
+
+/// Lists the files a scan could not read in time, as they may be infected without being reported
+#[derive(Debug, Clone)]
+pub struct ScanWarning {
+    device_failing: bool,
+    unreadable_files: Vec<String>,
+}
+
+impl ScanWarning {
+    /// Returns None if every file of the scan could be read
+    pub fn new(result: &ScanResult) -> Option<Self> {
+        if result.unreadable_files.is_empty() && !result.device_failing {
+            return None;
+        }
+        Some(Self {
+            device_failing: result.device_failing,
+            unreadable_files: result.unreadable_files.clone(),
+        })
+    }
+
+    pub fn view(&self) -> Element<Message> {
+        let icon = text(Icon::ExclamationTriangle.to_string())
+            .font(ICON_FONT)
+            .size(32)
+            .vertical_alignment(alignment::Vertical::Center);
+
+        let title = if self.device_failing {
+            text(t!("scan_device_failing")).size(20)
+        } else {
+            text(t!("scan_unreadable")).size(20)
+        };
+
+        let mut files = Column::new();
+        for file in &self.unreadable_files {
+            files = files.push(text(file).size(14));
+        }
+
+        let content = Column::new()
+            .push(
+                Row::new()
+                    .push(icon)
+                    .push(Space::with_width(10))
+                    .push(title)
+                    .align_items(alignment::Alignment::Center),
+            )
+            .push(Space::with_height(5))
+            .push(scrollable(files).height(Length::Fixed(150.0)));
+
+        Container::new(content).padding(10).into()
+    }
+}
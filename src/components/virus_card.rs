@@ -9,6 +9,7 @@ pub struct VirusComp {
     title: String,
     value: String,
     icon: String,
+    details: Vec<String>,
 }
 
 impl VirusComp {
@@ -17,9 +18,16 @@ impl VirusComp {
             title,
             value,
             icon: icon.to_string(),
+            details: Vec::new(),
         }
     }
 
+    /// Adds lines shown below the value, such as the malware name and severity
+    pub fn with_details(mut self, details: Vec<String>) -> Self {
+        self.details = details;
+        self
+    }
+
     pub fn view(&self) -> Element<Message> {
         let icon = text(&self.icon)
             .font(ICON_FONT)
@@ -31,10 +39,13 @@ impl VirusComp {
         let title = text(&self.title).size(20);
         let value = text(&self.value).size(14);
 
-        let text_col = Column::new()
+        let mut text_col = Column::new()
             .push(title)
             .push(Space::with_height(5))
             .push(value);
+        for detail in &self.details {
+            text_col = text_col.push(text(detail).size(14));
+        }
 
         let content = Row::new()
             .push(icon)
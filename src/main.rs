@@ -13,7 +13,7 @@ back to the Home page. This is necessary because it allows us to have a centrali
 
 mod pages;
 use pages::{
-    clean::CleanPage,
+    clean::{CleanMessage, CleanPage},
     home::{HomeMessage, HomePage},
     infected::{InfectedMessage, InfectedPage},
     info::InfoPage,
@@ -24,6 +24,13 @@ use pages::{
 
 pub mod backend;
 pub mod components;
+#[cfg(test)]
+mod tests;
+
+use backend::{
+    app_paths::AppPaths, config_file::Config, db_health::HealthReport, db_worker::DBHandle,
+    file_scanner::ScanResult, utils::Utils,
+};
 
 /// Represents different pages of the application.
 /// Will be used to change from one page to another using Messages
@@ -41,7 +48,7 @@ pub enum Page {
 #[derive(Debug, Clone)]
 pub enum Param {
     String(String),
-    Vector(Vec<String>),
+    ScanResult(ScanResult),
 }
 
 /// The main structure of the application. It contains an instance of all the pages.
@@ -68,10 +75,12 @@ pub enum Message {
     Home(HomeMessage),
     Settings(SettingsMessage),
     Loading(LoadingMessage),
+    Clean(CleanMessage),
     Infected(InfectedMessage),
     Updating(UpdatingMessage),
-    ScanningFinished(Result<Vec<String>, String>),
+    ScanningFinished(Result<ScanResult, String>),
     UpdatingFinished(Result<String, String>),
+    DatabaseChecked(Result<HealthReport, String>),
 }
 
 /// The main implementation of the application
@@ -80,25 +89,29 @@ impl Application for Raspirus {
     type Message = Message;
     type Executor = executor::Default;
     type Theme = Theme;
-    type Flags = ();
+    type Flags = AppPaths;
 
     /// Creates a new Raspirus object that contains an instance of each page. This is necessary for the borrowing to function.
     /// It also allows us to change from one page to another trough this page.
     /// This may cause the application to start slowly, as it needs to load all pages first.
-    /// If one page fails, the application also fails
-    fn new(_flags: ()) -> (Self, Command<Message>) {
+    /// If one page fails, the application also fails.
+    /// The database is checked in the background, scans are blocked if it turns out to be damaged.
+    /// All pages share one database worker, so scans, updates and settings never open the database on their own.
+    /// The flags are the storage locations resolved at start.
+    fn new(paths: AppPaths) -> (Self, Command<Message>) {
+        let db = DBHandle::spawn(paths.clone());
         (
             Raspirus {
                 page: Page::Home,
-                home_page: HomePage::new(()).0,
+                home_page: HomePage::new(paths.clone()).0,
                 info_page: InfoPage::new(()).0,
-                settings_page: SettingsPage::new(()).0,
-                loading_page: LoadingPage::new(()).0,
+                settings_page: SettingsPage::new(db.clone()).0,
+                loading_page: LoadingPage::new(db.clone()).0,
                 clean_page: CleanPage::new(()).0,
-                infected_page: InfectedPage::new(()).0,
-                updating_page: UpdatingPage::new(()).0,
+                infected_page: InfectedPage::new(paths).0,
+                updating_page: UpdatingPage::new(db.clone()).0,
             },
-            Command::none(),
+            Command::perform(Utils::check_database(db, false), Message::DatabaseChecked),
         )
     }
 
@@ -131,10 +144,16 @@ impl Application for Raspirus {
                                 .loading_page
                                 .update(Message::Loading(LoadingMessage::SetPath(scan_path)));
                         }
-                        Param::Vector(scan_result) => {
-                            return self.infected_page.update(Message::Infected(
-                                InfectedMessage::SetScanResult(scan_result),
-                            ));
+                        // The clean page also shows the files that could not be checked
+                        Param::ScanResult(scan_result) => {
+                            return match page {
+                                Page::Clean => self.clean_page.update(Message::Clean(
+                                    CleanMessage::SetScanResult(scan_result),
+                                )),
+                                _ => self.infected_page.update(Message::Infected(
+                                    InfectedMessage::SetScanResult(scan_result),
+                                )),
+                            };
                         }
                     }
                 }
@@ -147,6 +166,7 @@ impl Application for Raspirus {
             Message::Loading(loading_message) => {
                 self.loading_page.update(Message::Loading(loading_message))
             }
+            Message::Clean(clean_message) => self.clean_page.update(Message::Clean(clean_message)),
             Message::Infected(infected_message) => self
                 .infected_page
                 .update(Message::Infected(infected_message)),
@@ -157,12 +177,12 @@ impl Application for Raspirus {
                 info!("Scanning successfull: {:?}", result);
                 // Assigning to an unused variable to avoid the warning: #[warn(unused_must_use)]
                 let _ = self.update(Message::Loading(LoadingMessage::ResetScan));
-                if result.is_empty() {
-                    self.update(Message::ChangePage(Page::Clean, None))
+                if result.detections.is_empty() {
+                    self.update(Message::ChangePage(Page::Clean, Some(Param::ScanResult(result))))
                 } else {
                     self.update(Message::ChangePage(
                         Page::Infected,
-                        Some(Param::Vector(result)),
+                        Some(Param::ScanResult(result)),
                     ))
                 }
             }
@@ -181,6 +201,14 @@ impl Application for Raspirus {
                 let _ = self.update(Message::Updating(UpdatingMessage::ResetUpdating));
                 self.update(Message::Updating(UpdatingMessage::UpdatingError(error)))
             }
+            // Both the home page, which blocks scans, and the settings page, which offers the repair, need the result
+            Message::DatabaseChecked(result) => {
+                let _ = self
+                    .home_page
+                    .update(Message::Home(HomeMessage::DatabaseChecked(result.clone())));
+                self.settings_page
+                    .update(Message::Settings(SettingsMessage::DatabaseChecked(result)))
+            }
         }
     }
 
@@ -207,6 +235,86 @@ impl Application for Raspirus {
     }
 }
 
+/// Handles the command line arguments, for usage without a display.
+/// Returns the exit code if a command was run, or None if the GUI should be started.
+fn run_cli(paths: &AppPaths, args: &[String]) -> Option<i32> {
+    match args.get(1).map(String::as_str) {
+        Some("--import") => {
+            let path = match args.get(2) {
+                Some(path) => path.clone(),
+                None => {
+                    eprintln!("Usage: raspirus --import <folder|hash list|bundle>");
+                    return Some(2);
+                }
+            };
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to start runtime");
+            match runtime.block_on(Utils::import_signatures(DBHandle::spawn(paths.clone()), path, None, None)) {
+                Ok(hash_count) => {
+                    println!("Import finished, {} hashes in database", hash_count);
+                    Some(0)
+                }
+                Err(err) => {
+                    eprintln!("Import failed: {}", err);
+                    Some(1)
+                }
+            }
+        }
+        Some("--check") => {
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to start runtime");
+            match runtime.block_on(Utils::check_for_updates(DBHandle::spawn(paths.clone()))) {
+                Ok(check) => {
+                    println!("{} new signature files available", check.new_files);
+                    println!(
+                        "About {:.1} MB to download, about {} new hashes",
+                        check.download_size as f64 * 0.000001,
+                        check.new_hashes
+                    );
+                    Some(0)
+                }
+                Err(err) => {
+                    eprintln!("Update check failed: {}", err);
+                    Some(1)
+                }
+            }
+        }
+        Some("--removals") => match tokio::runtime::Runtime::new()
+            .expect("Failed to start runtime")
+            .block_on(Utils::list_removals(DBHandle::spawn(paths.clone())))
+        {
+            Ok(removals) => {
+                for removal in &removals {
+                    println!(
+                        "{}\t{}/{}\t{}\t{}\t{}",
+                        removal.hash,
+                        removal.source,
+                        removal.list,
+                        removal.applied.as_deref().unwrap_or("-"),
+                        if removal.was_present { "removed" } else { "blocked" },
+                        removal.reason.as_deref().unwrap_or("no reason given")
+                    );
+                }
+                println!("{} retracted hashes", removals.len());
+                Some(0)
+            }
+            Err(err) => {
+                eprintln!("Reading retracted hashes failed: {}", err);
+                Some(1)
+            }
+        },
+        Some("--help") => {
+            println!("Usage: raspirus [--import <folder|hash list|bundle> | --check | --removals]");
+            println!("--check shows how many signature files an update would download, without downloading them.");
+            println!("--removals lists every retracted hash with the list that retracted it and why.");
+            println!("Without arguments the graphical interface is started.");
+            println!("--data-dir, --log-dir, --cache-dir and --config-dir <dir> change where files are kept,");
+            println!("as do RASPIRUS_DATA_DIR, RASPIRUS_LOG_DIR, RASPIRUS_CACHE_DIR and RASPIRUS_CONFIG_DIR.");
+            println!("--portable keeps all files in raspirus_data next to the executable, as does a raspirus.portable file there.");
+            Some(0)
+        }
+        _ => None,
+    }
+}
+
 fn main() {
     // Initializes the logger for the entire application. Useful when in dev-mode
     match pretty_env_logger::try_init() {
@@ -218,13 +326,28 @@ fn main() {
         }
     }
 
+    let args: Vec<String> = std::env::args().collect();
+    let (paths, args) = match AppPaths::resolve(&args) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = Config::create_default(&paths) {
+        warn!("Failed writing the default config: {err}");
+    }
+    if let Some(exit_code) = run_cli(&paths, &args) {
+        std::process::exit(exit_code);
+    }
+
     let icon = icon::from_file("assets/icons/icon.png");
     let settings = iced::settings::Settings {
         window: iced::window::Settings {
             icon: Some(icon.unwrap()),
             ..Default::default()
         },
-        ..Default::default()
+        ..iced::settings::Settings::with_flags(paths)
     };
 
     Raspirus::run(settings).expect("Init error");
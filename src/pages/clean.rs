@@ -1,13 +1,22 @@
 use iced::widget::{button, text, Column, Image, Row, Space};
-use iced::{Alignment, Element, Length, Sandbox};
+use iced::{executor, Alignment, Application, Command, Element, Length, Theme};
 use iced_aw::{Icon, ICON_FONT};
 use rust_i18n::t;
 
+use crate::backend::file_scanner::ScanResult;
+use crate::components::scan_warning::ScanWarning;
 use crate::{Message, Page};
 
-/// The struct is empty, as this page doesn't require any page-specific values or Messages
+/// Only keeps the files the scan could not read, no virus was found in the others
 #[derive(Debug, Clone)]
-pub struct CleanPage {}
+pub struct CleanPage {
+    warning: Option<ScanWarning>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CleanMessage {
+    SetScanResult(ScanResult),
+}
 
 /// # Clean page
 /// A page that displays a green check if the app found no viruses
@@ -16,21 +25,30 @@ pub struct CleanPage {}
 /// In the top left corner there is a title showing on which page the user currently is.
 /// In the center of the page there is an image showing a green tick
 /// Right below it there is a button that allows the user to return home
+/// If some files could not be read in time, they are listed below the button
 ///
 /// ## Actions
 /// User can return home by clicking a button
-impl Sandbox for CleanPage {
+impl Application for CleanPage {
     type Message = Message;
+    type Executor = executor::Default;
+    type Theme = Theme;
+    type Flags = ();
 
-    fn new() -> Self {
-        CleanPage {}
+    fn new(_flags: ()) -> (Self, Command<Message>) {
+        (CleanPage { warning: None }, Command::none())
     }
 
     fn title(&self) -> String {
         String::from("Raspirus | Clean")
     }
 
-    fn update(&mut self, _message: Self::Message) {}
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
+        if let Message::Clean(CleanMessage::SetScanResult(result)) = message {
+            self.warning = ScanWarning::new(&result);
+        }
+        Command::none()
+    }
 
     fn view(&self) -> Element<Self::Message> {
         let title = text(t!("clean_title")).size(50);
@@ -50,10 +68,12 @@ impl Sandbox for CleanPage {
         .on_press(Message::ChangePage(Page::Home, None))
         .padding(10);
 
-        Column::new()
-            .push(title)
-            .push(image)
-            .push(back_button)
+        let mut content = Column::new().push(title).push(image).push(back_button);
+        if let Some(warning) = &self.warning {
+            content = content.push(warning.view());
+        }
+
+        content
             .width(Length::Fill)
             .height(Length::Fill)
             .align_items(Alignment::Center)
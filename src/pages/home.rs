@@ -1,10 +1,12 @@
 use iced::widget::{button, pick_list, text, Column, Container, Row, Space};
-use iced::{alignment, Alignment, Element, Length, Sandbox};
+use iced::{alignment, executor, Alignment, Application, Command, Element, Length, Theme};
 use iced_aw::{Icon, Modal, ICON_FONT};
 use log::debug;
 use rfd::FileDialog;
 use rust_i18n::t;
 
+use crate::backend::app_paths::AppPaths;
+use crate::backend::db_health::HealthReport;
 use crate::backend::utils::{UsbDevice, Utils};
 use crate::components::languages::Language;
 use crate::components::modal_widget::HomeModal;
@@ -16,11 +18,15 @@ TODO:
 */
 
 pub struct HomePage {
+    /// Where files are kept, shown so that the user knows whether the portable mode is active
+    paths: AppPaths,
     is_raspberry_pi: bool,
     selected_language: Language,
     drive_path: Option<String>,
     drives_list: Option<Vec<UsbDevice>>,
     show_modal: bool,
+    /// Why the database may not be used for scanning, if it is unhealthy
+    db_problem: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -31,12 +37,16 @@ pub enum HomeMessage {
     FolderSelected,
     ShowModal,
     CloseModal,
+    DatabaseChecked(Result<HealthReport, String>),
 }
 
-impl Sandbox for HomePage {
+impl Application for HomePage {
     type Message = Message;
+    type Executor = executor::Default;
+    type Theme = Theme;
+    type Flags = AppPaths;
 
-    fn new() -> Self {
+    fn new(paths: AppPaths) -> (Self, Command<Message>) {
         let usbs = match Utils::list_usb_drives() {
             Ok(res) => Some(res),
             Err(_) => None,
@@ -44,20 +54,25 @@ impl Sandbox for HomePage {
 
         let arch = std::env::consts::ARCH;
 
-        HomePage {
-            is_raspberry_pi: arch == "arm",
-            selected_language: Language::English,
-            drive_path: None,
-            drives_list: usbs,
-            show_modal: false,
-        }
+        (
+            HomePage {
+                paths,
+                is_raspberry_pi: arch == "arm",
+                selected_language: Language::English,
+                drive_path: None,
+                drives_list: usbs,
+                show_modal: false,
+                db_problem: None,
+            },
+            Command::none(),
+        )
     }
 
     fn title(&self) -> String {
         String::from("Raspirus | Main")
     }
 
-    fn update(&mut self, message: Self::Message) {
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
         match message {
             Message::Home(home_message) => match home_message {
                 HomeMessage::DriveSelected(path) => {
@@ -90,9 +105,17 @@ impl Sandbox for HomePage {
                 HomeMessage::CloseModal => {
                     self.show_modal = false;
                 }
+                HomeMessage::DatabaseChecked(result) => {
+                    self.db_problem = match result {
+                        Ok(report) if report.is_healthy() => None,
+                        Ok(report) => Some(report.summary()),
+                        Err(err) => Some(err),
+                    };
+                }
             },
             _ => {}
         }
+        Command::none()
     }
 
     fn view(&self) -> Element<Self::Message> {
@@ -107,10 +130,13 @@ impl Sandbox for HomePage {
         )
         .on_press(Message::ChangePage(Page::Settings, None));
         let info_btn = button(text(t!("info"))).on_press(Message::ChangePage(Page::Info, None));
-        let start_btn = button(text(t!("start"))).on_press(match self.drive_path.clone() {
-            Some(path) => Message::ChangePage(Page::Loading, Some(Param::String(path))),
-            None => Message::Home(HomeMessage::ShowModal),
-        });
+        let start_btn = button(text(t!("start")));
+        // Scanning with a damaged database could report infected files as clean
+        let start_btn = match (&self.db_problem, self.drive_path.clone()) {
+            (Some(_), _) => start_btn,
+            (None, Some(path)) => start_btn.on_press(Message::ChangePage(Page::Loading, Some(Param::String(path)))),
+            (None, None) => start_btn.on_press(Message::Home(HomeMessage::ShowModal)),
+        };
         let refresh_btn = button(text(Icon::ArrowClockwise).font(ICON_FONT))
             .on_press(Message::Home(HomeMessage::RefreshPressed));
         let folder_btn = button(text(Icon::Folder.to_string()).font(ICON_FONT))
@@ -183,10 +209,23 @@ impl Sandbox for HomePage {
             },
         );
 
+        // `--data-dir` and `RASPIRUS_DATA_DIR` move the files without enabling the portable mode
+        let mode_label = if self.paths.portable {
+            text(format!("{} {}", t!("mode_portable"), self.paths.data_dir.display()))
+        } else if self.paths.data_dir != AppPaths::platform().data_dir {
+            text(format!("{} {}", t!("mode_custom"), self.paths.data_dir.display()))
+        } else {
+            text(t!("mode_installed"))
+        }
+        .size(14);
+
         let top_row = Row::new()
             .push(language_picker)
             .push(Space::with_width(Length::Fill))
+            .push(mode_label)
+            .push(Space::with_width(10))
             .push(settings_btn)
+            .align_items(Alignment::Center)
             .padding(10);
 
         let picker_row;
@@ -212,11 +251,16 @@ impl Sandbox for HomePage {
             .push(start_btn)
             .padding(5);
 
-        let central_col = Column::new()
+        let mut central_col = Column::new()
             .push(titel_label)
             .push(picker_row)
             .push(btn_row)
             .align_items(Alignment::Center);
+        if let Some(problem) = &self.db_problem {
+            central_col = central_col
+                .push(text(t!("db_unhealthy_scan")).size(20))
+                .push(text(problem).size(14));
+        }
 
         let content = Column::new()
             .push(top_row)
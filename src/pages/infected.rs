@@ -1,47 +1,89 @@
 use std::path::Path;
 use iced::widget::{button, scrollable, text, Column, Container, Image, Row, Space};
-use iced::{Alignment, Element, Length, Sandbox};
+use iced::{executor, Alignment, Application, Command, Element, Length, Theme};
 use iced_aw::{Icon, Modal, ICON_FONT};
 use log::{error, debug};
 use rust_i18n::t;
 
+use crate::backend::app_paths::AppPaths;
 use crate::backend::config_file::Config;
+use crate::backend::file_scanner::{Detection, ScanResult};
 use crate::components::modal_widget::DefaultModal;
+use crate::components::scan_warning::ScanWarning;
 use crate::components::virus_card::VirusComp;
 use crate::{Message, Page};
 
 #[derive(Debug, Clone)]
 pub struct InfectedPage {
+    paths: AppPaths,
     virus_list: Vec<VirusComp>,
+    warning: Option<ScanWarning>,
     show_modal: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum InfectedMessage {
-    SetScanResult(Vec<String>),
+    SetScanResult(ScanResult),
     CloseModal,
 }
 
-impl Sandbox for InfectedPage {
-    type Message = Message;
-
-    fn new() -> Self {
-        InfectedPage {
-            virus_list: Vec::new(),
-            show_modal: false,
+impl InfectedPage {
+    /// Describes the signature a file was detected with, one line per known field
+    fn detection_details(detection: &Detection) -> Vec<String> {
+        let info = &detection.info;
+        let mut details = vec![format!(
+            "{}: {}",
+            t!("infected_malware"),
+            info.name.clone().unwrap_or_else(|| t!("infected_unknown"))
+        )];
+        if let Some(severity) = info.severity {
+            details.push(format!("{}: {}", t!("infected_severity"), severity));
+        }
+        if let Some(source) = &info.source {
+            details.push(format!("{}: {}", t!("infected_source"), source));
         }
+        if let Some(first_seen) = &info.first_seen {
+            details.push(format!("{}: {}", t!("infected_first_seen"), first_seen));
+        }
+        if let Some(added) = &info.added {
+            // Stored as RFC 3339, only the date is of interest here
+            let added = added.split('T').next().unwrap_or(added);
+            details.push(format!("{}: {}", t!("infected_added"), added));
+        }
+        details
+    }
+}
+
+impl Application for InfectedPage {
+    type Message = Message;
+    type Executor = executor::Default;
+    type Theme = Theme;
+    type Flags = AppPaths;
+
+    fn new(paths: AppPaths) -> (Self, Command<Message>) {
+        (
+            InfectedPage {
+                paths,
+                virus_list: Vec::new(),
+                warning: None,
+                show_modal: false,
+            },
+            Command::none(),
+        )
     }
 
     fn title(&self) -> String {
         String::from("Raspirus | Infected")
     }
 
-    fn update(&mut self, message: Self::Message) {
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
         match message {
             Message::Infected(infected_message) => match infected_message {
                 InfectedMessage::SetScanResult(scan_res) => {
-                    if !scan_res.is_empty() {
-                        for entry in &scan_res {
+                    self.warning = ScanWarning::new(&scan_res);
+                    if !scan_res.detections.is_empty() {
+                        for detection in &scan_res.detections {
+                            let entry = &detection.path;
                             let title: &str;
                             if let Some(file_name) = Path::new(entry)
                                 .file_name()
@@ -59,7 +101,7 @@ impl Sandbox for InfectedPage {
                                 text.to_string(),
                                 Icon::ExclamationTriangle,
                             )
-                            .clone();
+                            .with_details(Self::detection_details(detection));
                             self.virus_list.push(virus_comp);
                         }
                     } else {
@@ -74,11 +116,11 @@ impl Sandbox for InfectedPage {
             },
             _ => {}
         }
+        Command::none()
     }
 
     fn view(&self) -> Element<Self::Message> {
-        let mut config = Config::new();
-        config = config.load().expect("Error loading config");
+        let config = Config::new().load(&self.paths).unwrap_or_else(|_| Config::new());
 
         /* OBFUSCATED MODE COMPONENTS */
         let title = text(t!("infected_title")).size(50);
@@ -123,10 +165,12 @@ impl Sandbox for InfectedPage {
         // Coonditional rendering
         if config.obfuscated_is_active {
             debug!("OBFUSCATED IS ACTIVE");
-            let content = Column::new()
-                .push(title)
-                .push(image)
-                .push(back_button)
+            // The unchecked files are listed even here, as they may be infected too
+            let mut content = Column::new().push(title).push(image).push(back_button);
+            if let Some(warning) = &self.warning {
+                content = content.push(warning.view());
+            }
+            let content = content
                 .align_items(Alignment::Center)
                 .padding(10);
 
@@ -143,13 +187,11 @@ impl Sandbox for InfectedPage {
                 .into()
         } else {
             debug!("OBFUSCATED NOT ACTIVE");
-            let content = scrollable(
-                Column::new()
-                    .spacing(20)
-                    .push(reg_title)
-                    .push(infected_comps)
-                    .width(Length::Fill),
-            );
+            let mut list = Column::new().spacing(20).push(reg_title);
+            if let Some(warning) = &self.warning {
+                list = list.push(warning.view());
+            }
+            let content = scrollable(list.push(infected_comps).width(Length::Fill));
 
             let container = Container::new(content)
                 .center_x()
@@ -7,6 +7,7 @@ use iced_aw::{Icon, Modal, ICON_FONT};
 use log::error;
 use rust_i18n::t;
 
+use crate::backend::db_worker::DBHandle;
 use crate::backend::utils::Utils;
 use crate::components::modal_widget::DefaultModal;
 use crate::components::progress_sub::ProgressSubscription;
@@ -14,6 +15,7 @@ use crate::{Message, Page};
 
 #[derive(Debug, Clone)]
 pub struct LoadingPage {
+    db: DBHandle,
     confirmed: bool,
     scan_path: String,
     progress: f32,
@@ -35,11 +37,12 @@ impl Application for LoadingPage {
     type Message = Message;
     type Executor = executor::Default;
     type Theme = Theme;
-    type Flags = ();
+    type Flags = DBHandle;
 
-    fn new(_flags: ()) -> (Self, Command<Message>) {
+    fn new(db: DBHandle) -> (Self, Command<Message>) {
         (
             LoadingPage {
+                db,
                 confirmed: false,
                 scan_path: String::from(""),
                 progress: 0.0,
@@ -65,7 +68,7 @@ impl Application for LoadingPage {
                     self.progress_receiver = Some(progress_receiver);
 
                     Command::perform(
-                        Utils::start_scanner(scan_path, Some(progress_sender)),
+                        Utils::start_scanner(self.db.clone(), scan_path, Some(progress_sender)),
                         Message::ScanningFinished,
                     )
                 }
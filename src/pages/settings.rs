@@ -1,13 +1,32 @@
-use iced::widget::{button, text, Column, Container, Row, Space};
-use iced::{alignment, Alignment, Element, Length, Sandbox};
-use iced_aw::{Icon, ICON_FONT};
+use iced::widget::{button, text, text_input, Column, Container, Row, Space};
+use chrono::Local;
+use iced::{alignment, executor, Alignment, Application, Command, Element, Length, Theme};
+use iced_aw::{Icon, NumberInput, ICON_FONT};
+use log::{error, info};
+use rfd::FileDialog;
 use rust_i18n::t;
 
 use crate::backend::config_file::Config;
+use crate::backend::db_health::{HealthReport, RepairReport};
+use crate::backend::db_ops::UpdateCheck;
+use crate::backend::db_worker::DBHandle;
+use crate::backend::utils::Utils;
+use crate::pages::updating::UpdatingMessage;
 use crate::{Message, Page};
 
 pub struct SettingsPage {
+    db: DBHandle,
     config: Config,
+    backup_running: bool,
+    backup_status: Option<String>,
+    update_check_running: bool,
+    update_check_status: Option<String>,
+    health_running: bool,
+    health_status: Option<String>,
+    repair_status: Option<String>,
+    database_unhealthy: bool,
+    config_error: Option<String>,
+    mirror_saved: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -16,58 +35,256 @@ pub enum SettingsMessage {
     AutoUpdateWeekSet(i32),
     LoggingToggle,
     ObfuscatedToggle,
+    MirrorUrlChanged(String),
+    MirrorUsernameChanged(String),
+    MirrorTokenChanged(String),
+    SaveMirror,
+    DownloadWorkersChanged(usize),
+    ExportDatabase,
+    RestoreDatabase,
+    MergeDatabase,
+    BackupFinished(Result<String, String>),
+    CheckUpdates,
+    UpdatesChecked(Result<UpdateCheck, String>),
+    CheckDatabase,
+    DatabaseChecked(Result<HealthReport, String>),
+    RepairDatabase,
+    DatabaseRepaired(Result<RepairReport, String>),
 }
 
-impl Sandbox for SettingsPage {
+impl SettingsPage {
+    /// Writes the config, a failure is shown on the page instead of ending the application
+    fn save_config(&mut self) -> bool {
+        match self.config.save(self.db.paths()) {
+            Ok(()) => {
+                self.config_error = None;
+                true
+            }
+            Err(err) => {
+                error!("Failed saving config: {}", err);
+                self.config_error = Some(format!("{}: {}", t!("config_save_failed"), err));
+                false
+            }
+        }
+    }
+
+    /// Asks for a backup file and restores or merges it in the background
+    fn restore_backup(&mut self, merge: bool) -> Command<Message> {
+        match FileDialog::new()
+            .add_filter(&t!("backup_filter"), &["gz"])
+            .pick_file()
+        {
+            Some(path) => {
+                self.backup_running = true;
+                self.backup_status = Some(t!("backup_running"));
+                Command::perform(
+                    Utils::restore_database(self.db.clone(), path.to_string_lossy().into_owned(), merge),
+                    |result| Message::Settings(SettingsMessage::BackupFinished(result)),
+                )
+            }
+            None => Command::none(),
+        }
+    }
+}
+
+impl Application for SettingsPage {
     type Message = Message;
+    type Executor = executor::Default;
+    type Theme = Theme;
+    type Flags = DBHandle;
 
-    fn new() -> Self {
+    fn new(db: DBHandle) -> (Self, Command<Message>) {
         let config = Config::new();
         config
-            .set_path()
+            .set_path(db.paths())
             .expect("Error while setting path of config");
 
-        SettingsPage {
-            config: config
-                .load()
-                .expect("Failed to load config in settings page"),
-        }
+        (
+            SettingsPage {
+                config: config.load(db.paths()).unwrap_or_else(|err| {
+                    error!("Failed to load config in settings page: {}", err);
+                    Config::new()
+                }),
+                db,
+                backup_running: false,
+                backup_status: None,
+                update_check_running: false,
+                update_check_status: None,
+                health_running: false,
+                health_status: None,
+                repair_status: None,
+                database_unhealthy: false,
+                config_error: None,
+                mirror_saved: false,
+            },
+            Command::none(),
+        )
     }
 
     fn title(&self) -> String {
         String::from("Raspirus | Settings")
     }
 
-    fn update(&mut self, message: Self::Message) {
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
         match message {
             Message::Settings(settings_message) => match settings_message {
                 SettingsMessage::AutoUpdateTimeSet(update_time) => {
                     self.config.db_update_time = update_time;
-                    self.config.save().expect("Error while saving config");
+                    self.save_config();
                 }
                 SettingsMessage::AutoUpdateWeekSet(update_week) => {
                     self.config.db_update_weekday = update_week;
-                    self.config.save().expect("Error while saving config");
+                    self.save_config();
                 }
                 SettingsMessage::LoggingToggle => {
                     self.config.logging_is_active = !self.config.logging_is_active;
-                    self.config.save().expect("Error while saving config");
+                    self.save_config();
                 }
                 SettingsMessage::ObfuscatedToggle => {
                     self.config.obfuscated_is_active = !self.config.obfuscated_is_active;
-                    self.config.save().expect("Error while saving config");
+                    self.save_config();
+                }
+                // The mirror fields are only saved once confirmed with enter, not on every keystroke
+                SettingsMessage::MirrorUrlChanged(mirror_url) => {
+                    self.config.mirror_url = mirror_url;
+                    self.mirror_saved = false;
+                }
+                SettingsMessage::MirrorUsernameChanged(mirror_username) => {
+                    self.config.mirror_username = mirror_username;
+                    self.mirror_saved = false;
+                }
+                SettingsMessage::MirrorTokenChanged(mirror_token) => {
+                    self.config.mirror_token = mirror_token;
+                    self.mirror_saved = false;
+                }
+                SettingsMessage::SaveMirror => {
+                    self.mirror_saved = self.save_config();
+                }
+                SettingsMessage::DownloadWorkersChanged(download_workers) => {
+                    self.config.download_workers = download_workers;
+                    self.save_config();
+                }
+                SettingsMessage::ExportDatabase => {
+                    let file_name = format!("raspirus-backup-{}.tar.gz", Local::now().format("%Y-%m-%d"));
+                    if let Some(path) = FileDialog::new()
+                        .add_filter(&t!("backup_filter"), &["gz"])
+                        .set_file_name(&file_name)
+                        .save_file()
+                    {
+                        self.backup_running = true;
+                        self.backup_status = Some(t!("backup_running"));
+                        return Command::perform(
+                            Utils::export_database(self.db.clone(), path.to_string_lossy().into_owned()),
+                            |result| Message::Settings(SettingsMessage::BackupFinished(result)),
+                        );
+                    }
+                }
+                SettingsMessage::RestoreDatabase => return self.restore_backup(false),
+                SettingsMessage::MergeDatabase => return self.restore_backup(true),
+                SettingsMessage::BackupFinished(result) => {
+                    self.backup_running = false;
+                    self.backup_status = Some(match result {
+                        Ok(hash_count) => {
+                            info!("Backup operation finished with {} hashes", hash_count);
+                            format!("{}: {}", t!("backup_done"), hash_count)
+                        }
+                        Err(err) => {
+                            error!("Backup operation failed: {}", err);
+                            format!("{}: {}", t!("backup_failed"), err)
+                        }
+                    });
+                    // A restored backup may have replaced a damaged database
+                    return Command::perform(Utils::check_database(self.db.clone(), false), Message::DatabaseChecked);
+                }
+                SettingsMessage::CheckUpdates => {
+                    self.update_check_running = true;
+                    self.update_check_status = Some(t!("update_check_running"));
+                    return Command::perform(Utils::check_for_updates(self.db.clone()), |result| {
+                        Message::Settings(SettingsMessage::UpdatesChecked(result))
+                    });
+                }
+                SettingsMessage::UpdatesChecked(result) => {
+                    self.update_check_running = false;
+                    self.update_check_status = Some(match result {
+                        Ok(check) if check.new_files == 0 => t!("update_db_completed_val"),
+                        Ok(check) => format!(
+                            "{} {} | {}: {:.1} MB | {}: {}",
+                            check.new_files,
+                            t!("update_check_available"),
+                            t!("update_check_size"),
+                            check.download_size as f64 * 0.000001,
+                            t!("update_check_hashes"),
+                            check.new_hashes
+                        ),
+                        Err(err) => {
+                            error!("Update check failed: {}", err);
+                            format!("{}: {}", t!("update_check_failed"), err)
+                        }
+                    });
+                }
+                SettingsMessage::CheckDatabase => {
+                    self.health_running = true;
+                    self.health_status = Some(t!("db_health_running"));
+                    return Command::perform(Utils::check_database(self.db.clone(), true), Message::DatabaseChecked);
+                }
+                SettingsMessage::DatabaseChecked(result) => {
+                    self.health_running = false;
+                    self.database_unhealthy = !matches!(&result, Ok(report) if report.is_healthy());
+                    self.health_status = Some(match result {
+                        Ok(report) if report.is_healthy() => format!("{}: {}", t!("db_health_ok"), report.hash_count),
+                        Ok(report) => format!("{}: {}", t!("db_health_bad"), report.summary()),
+                        Err(err) => format!("{}: {}", t!("db_health_failed"), err),
+                    });
+                }
+                SettingsMessage::RepairDatabase => {
+                    self.health_running = true;
+                    self.repair_status = Some(t!("db_repair_running"));
+                    return Command::perform(Utils::repair_database(self.db.clone()), |result| {
+                        Message::Settings(SettingsMessage::DatabaseRepaired(result))
+                    });
+                }
+                SettingsMessage::DatabaseRepaired(result) => {
+                    self.health_running = false;
+                    match result {
+                        Ok(report) => {
+                            info!(
+                                "Database repaired with {} hashes, {} batch(es) to download again",
+                                report.recovered_hashes,
+                                report.redownload.len()
+                            );
+                            self.repair_status = Some(format!(
+                                "{}: {} | {} {}",
+                                t!("db_repair_done"),
+                                report.recovered_hashes,
+                                report.redownload.len(),
+                                t!("db_repair_redownload")
+                            ));
+                            let mut commands =
+                                vec![Command::perform(Utils::check_database(self.db.clone(), false), Message::DatabaseChecked)];
+                            // Batches that lost hashes are downloaded again right away
+                            if !report.redownload.is_empty() {
+                                commands.push(Command::perform(async { Page::Updating }, |page| {
+                                    Message::ChangePage(page, None)
+                                }));
+                                commands.push(Command::perform(async {}, |_| {
+                                    Message::Updating(UpdatingMessage::StartUpdating)
+                                }));
+                            }
+                            return Command::batch(commands);
+                        }
+                        Err(err) => {
+                            error!("Database repair failed: {}", err);
+                            self.repair_status = Some(format!("{}: {}", t!("db_repair_failed"), err));
+                        }
+                    }
                 }
             },
             _ => {}
         }
+        Command::none()
     }
 
     fn view(&self) -> Element<Self::Message> {
-        self.config
-            .set_path()
-            .expect("Error while setting path of config");
-        self.config.load().expect("Error while loading config");
-
         let back_button = button(
             Row::new()
                 .push(text(Icon::House.to_string()).font(ICON_FONT))
@@ -88,6 +305,36 @@ impl Sandbox for SettingsPage {
             .align_items(Alignment::Start);
 
         // UPDATE COMPONENT
+        let mut update_info = Column::new()
+            .push(text(t!("update_db")).size(30))
+            .push(Space::with_height(5))
+            .push(text(t!("update_db_val")).size(20))
+            .push(Space::with_height(5))
+            .push(
+                text(format!(
+                    "{}: {} | {}: {}",
+                    t!("update_db_1"),
+                    self.config.hashes_in_db, 
+                    self.config.last_db_update,
+                    t!("update_db_2")
+                ))
+                .size(14),
+            );
+        if self.config.db_space_saved > 0 {
+            update_info = update_info.push(
+                text(format!(
+                    "{}: {:.1} MB",
+                    t!("db_space_saved"),
+                    self.config.db_space_saved as f64 * 0.000001
+                ))
+                .size(14),
+            );
+        }
+        if let Some(status) = &self.update_check_status {
+            update_info = update_info.push(text(status).size(14));
+        }
+        let check_button = button(text(t!("update_check_btn")));
+
         let update_comp = Row::new()
             .push(
                 text(Icon::Wrench.to_string())
@@ -98,25 +345,208 @@ impl Sandbox for SettingsPage {
                     .vertical_alignment(alignment::Vertical::Center),
             )
             .push(Space::with_width(Length::FillPortion(2)))
+            .push(update_info)
+            .push(Space::with_width(Length::FillPortion(2)))
+            .push(
+                Column::new()
+                    .push(button(text(t!("update_db_btn"))).on_press(Message::ChangePage(Page::Updating, None)))
+                    .push(Space::with_height(5))
+                    .push(if self.update_check_running {
+                        check_button
+                    } else {
+                        check_button.on_press(Message::Settings(SettingsMessage::CheckUpdates))
+                    }),
+            )
+            .align_items(alignment::Alignment::Center)
+            .padding([20, 200]);
+
+        // MIRROR COMPONENT
+        let mirror_comp = Row::new()
+            .push(
+                text(Icon::Globe.to_string())
+                    .font(ICON_FONT)
+                    .size(64)
+                    .height(72)
+                    .height(72)
+                    .vertical_alignment(alignment::Vertical::Center),
+            )
+            .push(Space::with_width(Length::FillPortion(2)))
             .push(
                 Column::new()
-                    .push(text(t!("update_db")).size(30))
+                    .push(text(t!("mirror")).size(30))
+                    .push(Space::with_height(5))
+                    .push(text(t!("mirror_val")).size(20))
+                    .push(Space::with_height(5))
+                    .push(
+                        text_input(&t!("mirror_url"), &self.config.mirror_url)
+                            .on_input(|url| Message::Settings(SettingsMessage::MirrorUrlChanged(url)))
+                            .on_submit(Message::Settings(SettingsMessage::SaveMirror))
+                            .padding(5),
+                    )
                     .push(Space::with_height(5))
-                    .push(text(t!("update_db_val")).size(20))
+                    .push(
+                        Row::new()
+                            .push(
+                                text_input(&t!("mirror_username"), &self.config.mirror_username)
+                                    .on_input(|username| {
+                                        Message::Settings(SettingsMessage::MirrorUsernameChanged(username))
+                                    })
+                                    .on_submit(Message::Settings(SettingsMessage::SaveMirror))
+                                    .padding(5),
+                            )
+                            .push(Space::with_width(5))
+                            .push(
+                                text_input(&t!("mirror_token"), &self.config.mirror_token)
+                                    .on_input(|token| {
+                                        Message::Settings(SettingsMessage::MirrorTokenChanged(token))
+                                    })
+                                    .on_submit(Message::Settings(SettingsMessage::SaveMirror))
+                                    .password()
+                                    .padding(5),
+                            ),
+                    )
                     .push(Space::with_height(5))
                     .push(
-                        text(format!(
-                            "{}: {} | {}: {}",
-                            t!("update_db_1"),
-                            self.config.hashes_in_db, 
-                            self.config.last_db_update,
-                            t!("update_db_2")
-                        ))
+                        text(if self.mirror_saved {
+                            t!("mirror_saved")
+                        } else {
+                            t!("mirror_submit_hint")
+                        })
                         .size(14),
+                    )
+                    .push(Space::with_height(5))
+                    .push(
+                        Row::new()
+                            .push(text(t!("download_workers")).size(14))
+                            .push(Space::with_width(5))
+                            .push(
+                                NumberInput::new(self.config.download_workers, 16, |workers| {
+                                    Message::Settings(SettingsMessage::DownloadWorkersChanged(workers))
+                                })
+                                .min(1),
+                            )
+                            .align_items(Alignment::Center),
+                    )
+                    .width(Length::FillPortion(8)),
+            )
+            .push(Space::with_width(Length::FillPortion(2)))
+            .align_items(alignment::Alignment::Center)
+            .padding([20, 200]);
+
+        // IMPORT COMPONENT
+        let import_comp = Row::new()
+            .push(
+                text(Icon::BoxArrowInDown.to_string())
+                    .font(ICON_FONT)
+                    .size(64)
+                    .height(72)
+                    .height(72)
+                    .vertical_alignment(alignment::Vertical::Center),
+            )
+            .push(Space::with_width(Length::FillPortion(2)))
+            .push(
+                Column::new()
+                    .push(text(t!("import_db")).size(30))
+                    .push(Space::with_height(5))
+                    .push(text(t!("import_db_val")).size(20)),
+            )
+            .push(Space::with_width(Length::FillPortion(2)))
+            .push(
+                Column::new()
+                    .push(
+                        button(text(t!("import_file_btn")))
+                            .on_press(Message::Updating(UpdatingMessage::ImportFile)),
+                    )
+                    .push(Space::with_height(5))
+                    .push(
+                        button(text(t!("import_folder_btn")))
+                            .on_press(Message::Updating(UpdatingMessage::ImportFolder)),
                     ),
             )
+            .align_items(alignment::Alignment::Center)
+            .padding([20, 200]);
+
+        // BACKUP COMPONENT
+        let mut backup_info = Column::new()
+            .push(text(t!("backup_db")).size(30))
+            .push(Space::with_height(5))
+            .push(text(t!("backup_db_val")).size(20));
+        if let Some(status) = &self.backup_status {
+            backup_info = backup_info
+                .push(Space::with_height(5))
+                .push(text(status).size(14));
+        }
+        let backup_button = |label: String, message: SettingsMessage| {
+            let backup_button = button(text(label));
+            if self.backup_running {
+                backup_button
+            } else {
+                backup_button.on_press(Message::Settings(message))
+            }
+        };
+
+        let backup_comp = Row::new()
+            .push(
+                text(Icon::Archive.to_string())
+                    .font(ICON_FONT)
+                    .size(64)
+                    .height(72)
+                    .height(72)
+                    .vertical_alignment(alignment::Vertical::Center),
+            )
+            .push(Space::with_width(Length::FillPortion(2)))
+            .push(backup_info)
+            .push(Space::with_width(Length::FillPortion(2)))
+            .push(
+                Column::new()
+                    .push(backup_button(t!("backup_export_btn"), SettingsMessage::ExportDatabase))
+                    .push(Space::with_height(5))
+                    .push(backup_button(t!("backup_restore_btn"), SettingsMessage::RestoreDatabase))
+                    .push(Space::with_height(5))
+                    .push(backup_button(t!("backup_merge_btn"), SettingsMessage::MergeDatabase)),
+            )
+            .align_items(alignment::Alignment::Center)
+            .padding([20, 200]);
+
+        // DATABASE HEALTH COMPONENT
+        let mut health_info = Column::new()
+            .push(text(t!("db_health")).size(30))
+            .push(Space::with_height(5))
+            .push(text(t!("db_health_val")).size(20));
+        for status in [&self.health_status, &self.repair_status].into_iter().flatten() {
+            health_info = health_info
+                .push(Space::with_height(5))
+                .push(text(status).size(14));
+        }
+        let check_db_button = button(text(t!("db_health_check_btn")));
+        let repair_button = button(text(t!("db_health_repair_btn")));
+
+        let health_comp = Row::new()
+            .push(
+                text(Icon::ShieldCheck.to_string())
+                    .font(ICON_FONT)
+                    .size(64)
+                    .height(72)
+                    .height(72)
+                    .vertical_alignment(alignment::Vertical::Center),
+            )
+            .push(Space::with_width(Length::FillPortion(2)))
+            .push(health_info)
             .push(Space::with_width(Length::FillPortion(2)))
-            .push(button(text(t!("update_db_btn"))).on_press(Message::ChangePage(Page::Updating, None)))
+            .push(
+                Column::new()
+                    .push(if self.health_running {
+                        check_db_button
+                    } else {
+                        check_db_button.on_press(Message::Settings(SettingsMessage::CheckDatabase))
+                    })
+                    .push(Space::with_height(5))
+                    .push(if self.health_running || !self.database_unhealthy {
+                        repair_button
+                    } else {
+                        repair_button.on_press(Message::Settings(SettingsMessage::RepairDatabase))
+                    }),
+            )
             .align_items(alignment::Alignment::Center)
             .padding([20, 200]);
 
@@ -205,17 +635,21 @@ impl Sandbox for SettingsPage {
 
         let setting_comps = Column::new()
             .push(update_comp)
+            .push(mirror_comp)
+            .push(import_comp)
+            .push(backup_comp)
+            .push(health_comp)
             .push(logging_comp)
             .push(obfuscation_comp)
             .push(scheduler_comp)
             .align_items(Alignment::Center)
             .height(Length::Fill);
 
-        let content = Column::new()
-            .spacing(20)
-            .push(title)
-            .push(setting_comps)
-            .width(Length::Fill);
+        let mut content = Column::new().spacing(20).push(title);
+        if let Some(config_error) = &self.config_error {
+            content = content.push(text(config_error).size(20));
+        }
+        let content = content.push(setting_comps).width(Length::Fill);
 
         Container::new(content)
             .center_x()
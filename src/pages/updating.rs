@@ -1,43 +1,95 @@
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
 use flume::{self, Receiver};
 use iced::widget::{progress_bar, text, Column, Container, Row, Space, button};
 use iced::{
     alignment, executor, Alignment, Application, Command, Element, Length, Subscription, Theme,
 };
 use iced_aw::{Modal, ICON_FONT, Icon};
-use log::error;
+use log::{error, info};
+use rfd::FileDialog;
+use rust_i18n::t;
 
+use crate::backend::db_worker::DBHandle;
 use crate::backend::utils::Utils;
 use crate::components::{modal_widget::DefaultModal, updating_sub::UpdatingSubscription};
 use crate::{Message, Page};
 
 #[derive(Debug, Clone)]
 pub struct UpdatingPage {
+    db: DBHandle,
     progress: f32,
     progress_receiver: Option<Receiver<f32>>,
     show_modal: bool,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    cancelling: bool,
+    status: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum UpdatingMessage {
     StartUpdating,
+    ImportFile,
+    ImportFolder,
     UpdatingProgress(f32),
     UpdatingError(String),
+    CancelUpdating,
     ResetUpdating,
     CloseModal,
 }
 
+impl UpdatingPage {
+    /// Switches to this page and imports the signatures at the given path, reporting progress like an update
+    fn start_import(&mut self, path: PathBuf) -> Command<Message> {
+        info!("Importing signatures from {}", path.display());
+        let (progress_sender, progress_receiver) = flume::unbounded();
+        self.progress_receiver = Some(progress_receiver);
+        let cancel_flag = self.new_cancel_flag();
+
+        Command::batch(vec![
+            Command::perform(async { Page::Updating }, |page| Message::ChangePage(page, None)),
+            Command::perform(
+                Utils::import_signatures(
+                    self.db.clone(),
+                    path.to_string_lossy().into_owned(),
+                    Some(progress_sender),
+                    Some(cancel_flag),
+                ),
+                Message::UpdatingFinished,
+            ),
+        ])
+    }
+
+    /// Creates the flag that cancels the update or import about to start
+    fn new_cancel_flag(&mut self) -> Arc<AtomicBool> {
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        self.cancel_flag = Some(cancel_flag.clone());
+        self.cancelling = false;
+        self.status = None;
+        cancel_flag
+    }
+}
+
 impl Application for UpdatingPage {
     type Message = Message;
     type Executor = executor::Default;
     type Theme = Theme;
-    type Flags = ();
+    type Flags = DBHandle;
 
-    fn new(_flags: ()) -> (Self, Command<Message>) {
+    fn new(db: DBHandle) -> (Self, Command<Message>) {
         (
             UpdatingPage {
+                db,
                 progress: 0.0,
                 progress_receiver: None,
                 show_modal: false,
+                cancel_flag: None,
+                cancelling: false,
+                status: None,
             },
             Command::none(),
         )
@@ -54,25 +106,56 @@ impl Application for UpdatingPage {
                     println!("UPDATE SZARTED");
                     let (progress_sender, progress_receiver) = flume::unbounded();
                     self.progress_receiver = Some(progress_receiver);
+                    let cancel_flag = self.new_cancel_flag();
 
                     Command::perform(
-                        Utils::update_database(Some(progress_sender)),
+                        Utils::update_database(self.db.clone(), Some(progress_sender), Some(cancel_flag)),
                         Message::UpdatingFinished,
                     )
                 }
+                UpdatingMessage::ImportFile => match FileDialog::new()
+                    .add_filter(&t!("import_filter"), &["md5", "txt", "csv", "gz", "tgz", "tar"])
+                    .pick_file()
+                {
+                    Some(path) => self.start_import(path),
+                    None => Command::none(),
+                },
+                UpdatingMessage::ImportFolder => match FileDialog::new().pick_folder() {
+                    Some(path) => self.start_import(path),
+                    None => Command::none(),
+                },
                 UpdatingMessage::UpdatingProgress(progress) => {
                     // Update the progress value and trigger a UI update
                     self.progress = progress;
                     Command::none()
                 }
                 UpdatingMessage::UpdatingError(error) => {
-                    error!("Error while updating: {}", error);
-                    self.show_modal = true;
+                    // A cancelled update is reported as error, but is no failure
+                    if self.cancelling {
+                        info!("{}", error);
+                        self.cancelling = false;
+                        self.status = Some(error);
+                    } else {
+                        error!("Error while updating: {}", error);
+                        self.show_modal = true;
+                        self.status = Some(error);
+                    }
                     Command::none()
                 }
+                UpdatingMessage::CancelUpdating => match &self.cancel_flag {
+                    // The update stops after the files currently downloading and reports back when done
+                    Some(cancel_flag) => {
+                        info!("Cancelling update...");
+                        cancel_flag.store(true, Ordering::SeqCst);
+                        self.cancelling = true;
+                        Command::none()
+                    }
+                    None => Command::perform(async { Page::Settings }, |page| Message::ChangePage(page, None)),
+                },
                 UpdatingMessage::ResetUpdating => {
                     self.progress = 0.0;
                     self.progress_receiver = None;
+                    self.cancel_flag = None;
                     Command::none()
                 }
                 UpdatingMessage::CloseModal => {
@@ -104,6 +187,12 @@ impl Application for UpdatingPage {
 
         let prog_bar = progress_bar(0.0..=100.0, self.progress);
 
+        let status = if self.cancelling {
+            text(t!("update_cancelling"))
+        } else {
+            text(self.status.clone().unwrap_or_default())
+        };
+
         let start_button = button(
             Row::new()
                 .push(text(Icon::PlayFill.to_string()).font(ICON_FONT))
@@ -112,8 +201,13 @@ impl Application for UpdatingPage {
                     text("START"),
                 ),
         )
-        .on_press(Message::Updating(UpdatingMessage::StartUpdating))
         .padding(10);
+        // A second update would run alongside the first and leave its cancel flag unreachable
+        let start_button = if self.cancel_flag.is_none() {
+            start_button.on_press(Message::Updating(UpdatingMessage::StartUpdating))
+        } else {
+            start_button
+        };
 
         let cancel_button = button(
             Row::new()
@@ -123,7 +217,7 @@ impl Application for UpdatingPage {
                     text("CANCEL"),
                 ),
         )
-        .on_press(Message::ChangePage(Page::Settings, None))
+        .on_press(Message::Updating(UpdatingMessage::CancelUpdating))
         .padding(10);
 
         let btn_row = Row::new()
@@ -137,6 +231,8 @@ impl Application for UpdatingPage {
             .push(update_title)
             .push(prog_bar)
             .push(Space::with_height(10))
+            .push(status)
+            .push(Space::with_height(10))
             .push(btn_row)
             .padding(10)
             .align_items(alignment::Horizontal::Center.into());
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::backend::app_paths::AppPaths;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_in_dir() {
+        let paths = AppPaths::in_dir(Path::new("test_app_paths"));
+        assert_eq!(paths.data_dir, PathBuf::from("test_app_paths/data"));
+        assert_eq!(paths.scan_logs(), PathBuf::from("test_app_paths/logs/logs"));
+        assert_eq!(paths.update_logs(), PathBuf::from("test_app_paths/logs/updates"));
+        assert_eq!(paths.cache_dir, PathBuf::from("test_app_paths/cache"));
+        assert_eq!(paths.config_dir, PathBuf::from("test_app_paths/config"));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let defaults = AppPaths::in_dir(Path::new("defaults"));
+        let (paths, remaining) = defaults
+            .clone()
+            .with_overrides(
+                &args(&["raspirus", "--data-dir", "cli_data", "--import", "hashes", "--log-dir", "cli_logs"]),
+                |name| match name {
+                    "RASPIRUS_DATA_DIR" => Some(PathBuf::from("env_data")),
+                    "RASPIRUS_CACHE_DIR" => Some(PathBuf::from("env_cache")),
+                    "RASPIRUS_CONFIG_DIR" => Some(PathBuf::new()),
+                    _ => None,
+                },
+            )
+            .unwrap();
+
+        // The command line wins over the environment, which wins over the defaults. Empty values are ignored.
+        assert_eq!(paths.data_dir, PathBuf::from("cli_data"));
+        assert_eq!(paths.log_dir, PathBuf::from("cli_logs"));
+        assert_eq!(paths.cache_dir, PathBuf::from("env_cache"));
+        assert_eq!(paths.config_dir, defaults.config_dir);
+        assert_eq!(remaining, args(&["raspirus", "--import", "hashes"]));
+    }
+
+    #[test]
+    fn test_portable() {
+        let exe_dir = Path::new("test_portable");
+        std::fs::create_dir_all(exe_dir).unwrap();
+
+        let installed = AppPaths::defaults(&args(&["raspirus"]), Some(exe_dir)).unwrap();
+        let flagged = AppPaths::defaults(&args(&["raspirus", "--portable"]), Some(exe_dir)).unwrap();
+        std::fs::write(exe_dir.join("raspirus.portable"), "").unwrap();
+        let marked = AppPaths::defaults(&args(&["raspirus"]), Some(exe_dir)).unwrap();
+        let unknown_dir = AppPaths::defaults(&args(&["raspirus", "--portable"]), None);
+        std::fs::remove_dir_all(exe_dir).unwrap();
+
+        assert!(!installed.portable);
+        assert_eq!(installed, AppPaths::platform());
+        assert!(flagged.portable);
+        assert_eq!(flagged.data_dir, PathBuf::from("test_portable/raspirus_data/data"));
+        assert_eq!(marked, flagged);
+        assert!(unknown_dir.is_err());
+
+        // The option only selects the defaults, it is not passed on to the command line handling
+        let (_, remaining) = flagged.with_overrides(&args(&["raspirus", "--portable", "--check"]), |_| None).unwrap();
+        assert_eq!(remaining, args(&["raspirus", "--check"]));
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        let defaults = AppPaths::in_dir(Path::new("defaults"));
+        assert!(defaults.clone().with_overrides(&args(&["raspirus", "--cache-dir"]), |_| None).is_err());
+        assert!(defaults.with_overrides(&args(&["raspirus", "--config-dir", ""]), |_| None).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rusqlite::Connection;
+
+    use crate::backend::app_paths::AppPaths;
+    use crate::backend::bloom_filter::BloomFilter;
+    use crate::backend::db_ops::DBOps;
+    const BLOOM_FILE_LOC: &str = "test_signatures.bloom";
+
+    #[test]
+    fn test_insert_and_contains() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        filter.insert("93fe4fb85a682907137b0b1051991332");
+
+        assert!(filter.might_contain("93fe4fb85a682907137b0b1051991332"));
+        assert!(!filter.might_contain("ec2112c9c243d837247217baf351ab79"));
+        assert_eq!(filter.items(), 1);
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = BloomFilter::new(10_000, 0.01);
+        for i in 0..10_000 {
+            filter.insert(&format!("{:x}", md5::compute(i.to_string())));
+        }
+
+        let false_positives = (10_000..20_000)
+            .filter(|i| filter.might_contain(&format!("{:x}", md5::compute(i.to_string()))))
+            .count();
+
+        // Allow some slack over the configured 1% rate
+        assert!(false_positives < 300, "Got {} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        filter.insert("93fe4fb85a682907137b0b1051991332");
+        filter.set_generation(42);
+        filter.save(std::path::Path::new(BLOOM_FILE_LOC)).unwrap();
+
+        let loaded = BloomFilter::load(std::path::Path::new(BLOOM_FILE_LOC)).unwrap();
+        std::fs::remove_file(BLOOM_FILE_LOC).unwrap();
+
+        assert_eq!(loaded.items(), 1);
+        assert_eq!(loaded.generation(), 42);
+        assert!(loaded.might_contain("93fe4fb85a682907137b0b1051991332"));
+    }
+
+    #[test]
+    fn test_load_invalid_file() {
+        let path = "test_invalid.bloom";
+        std::fs::write(path, b"not a filter").unwrap();
+        let loaded = BloomFilter::load(std::path::Path::new(path));
+        std::fs::remove_file(path).unwrap();
+
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn test_outdated_filter_rebuilt() {
+        let paths = AppPaths::in_dir(Path::new("test_bloom_generation"));
+        let removed = "93fe4fb85a682907137b0b1051991332";
+        let added = "ec2112c9c243d837247217baf351ab79";
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        db_ops.insert_hashes(vec![(removed.to_owned(), "1".to_owned())]).unwrap();
+        db_ops.rebuild_bloom_filter().unwrap();
+        drop(db_ops);
+
+        // Replace the hash behind the back of the persisted filter, keeping the amount of hashes
+        let conn = Connection::open(paths.database()).unwrap();
+        conn.execute("DELETE FROM signatures", []).unwrap();
+        conn.execute(&format!("INSERT INTO signatures VALUES (X'{}', 1)", added), []).unwrap();
+        drop(conn);
+
+        let db_ops = DBOps::new(&paths, None).unwrap();
+        let found = db_ops.hash_exists(added).unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all("test_bloom_generation").unwrap();
+
+        assert_eq!(found, Some(true));
+    }
+}
@@ -1,6 +1,10 @@
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+
+    use crate::backend::app_paths::AppPaths;
     use crate::backend::config_file::Config;
+    use crate::backend::sources::{http::HttpConfig, SourceConfig};
 
     #[test]
     fn test_new_config() {
@@ -12,6 +16,17 @@ mod tests {
         assert_eq!(config.obfuscated_is_active, true);
         assert_eq!(config.db_update_weekday, -1);
         assert_eq!(config.db_update_time, "22:00:00");
+        assert_eq!(config.db_space_saved, 0);
+        assert_eq!(config.signature_sources, vec![SourceConfig::VirusShare]);
+        assert_eq!(config.mirror_url, "https://virusshare.com/hashfiles");
+        assert_eq!(config.mirror_username, "");
+        assert_eq!(config.mirror_token, "");
+        assert_eq!(config.update_public_key, "");
+        assert_eq!(config.download_workers, 4);
+        assert_eq!(config.http, HttpConfig::default());
+        assert_eq!(config.http.proxy_url, "");
+        assert_eq!(config.http.connect_timeout_secs, 10);
+        assert_eq!(config.http.read_timeout_secs, 30);
     }
 
     #[test]
@@ -23,13 +38,37 @@ mod tests {
             obfuscated_is_active: false,
             db_update_weekday: 2,
             db_update_time: "08:00:00".to_string(),
+            db_space_saved: 1024,
+            signature_sources: vec![
+                SourceConfig::VirusShare,
+                SourceConfig::LocalDirectory {
+                    name: "usb".to_string(),
+                    path: "/media/usb/hashes".to_string(),
+                },
+            ],
+            mirror_url: "http://10.0.0.2/hashes".to_string(),
+            mirror_username: "pi".to_string(),
+            mirror_token: "secret".to_string(),
+            update_public_key: "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a".to_string(),
+            download_workers: 2,
+            http: HttpConfig {
+                proxy_url: "http://proxy.example.com:3128".to_string(),
+                proxy_username: "pi".to_string(),
+                proxy_password: "secret".to_string(),
+                no_proxy: "localhost,.example.com".to_string(),
+                connect_timeout_secs: 5,
+                read_timeout_secs: 0,
+                user_agent: "Raspirus-Test".to_string(),
+                ca_certificates: "/etc/ssl/corp-ca.pem".to_string(),
+            },
         };
 
-        let result_save = config.save();
+        let paths = AppPaths::in_dir(Path::new("test_config"));
+        let result_save = config.save(&paths);
         assert!(result_save.is_ok());
 
         let loaded_config = Config::new();
-        let result_load = loaded_config.load();
+        let result_load = loaded_config.load(&paths);
         assert!(result_load.is_ok());
 
         let loaded_config = result_load.unwrap();
@@ -39,5 +78,54 @@ mod tests {
         assert_eq!(loaded_config.obfuscated_is_active, config.obfuscated_is_active);
         assert_eq!(loaded_config.db_update_weekday, config.db_update_weekday);
         assert_eq!(loaded_config.db_update_time, config.db_update_time);
+        assert_eq!(loaded_config.db_space_saved, config.db_space_saved);
+        assert_eq!(loaded_config.signature_sources, config.signature_sources);
+        assert_eq!(loaded_config.mirror_url, config.mirror_url);
+        assert_eq!(loaded_config.mirror_username, config.mirror_username);
+        assert_eq!(loaded_config.mirror_token, config.mirror_token);
+        assert_eq!(loaded_config.update_public_key, config.update_public_key);
+        assert_eq!(loaded_config.download_workers, config.download_workers);
+        assert_eq!(loaded_config.http, config.http);
+        std::fs::remove_dir_all("test_config").unwrap();
+    }
+
+    #[test]
+    fn test_load_first_start() {
+        // A fresh stick, nothing was written next to the executable yet
+        let exe_dir = Path::new("test_config_first_start");
+        std::fs::create_dir_all(exe_dir).unwrap();
+        let paths = AppPaths::portable(exe_dir);
+
+        // Loading never writes, the default config is created once at startup
+        let missing = Config::new().load(&paths);
+        let written_by_load = paths.config_file().exists();
+        let created = Config::create_default(&paths).unwrap();
+        let loaded = Config::new().load(&paths);
+        let created_again = Config::create_default(&paths).unwrap();
+        std::fs::remove_dir_all(exe_dir).unwrap();
+
+        assert!(missing.is_err());
+        assert!(!written_by_load);
+        assert!(created);
+        assert_eq!(loaded.unwrap().mirror_url, Config::new().mirror_url);
+        assert!(!created_again);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_config_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let paths = AppPaths::in_dir(Path::new("test_config_permissions"));
+        std::fs::create_dir_all(&paths.config_dir).unwrap();
+        // A config written by an older version, readable by everyone
+        std::fs::write(paths.config_file(), "{}").unwrap();
+        std::fs::set_permissions(paths.config_file(), std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        Config::new().save(&paths).unwrap();
+        let mode = std::fs::metadata(paths.config_file()).unwrap().permissions().mode();
+        std::fs::remove_dir_all("test_config_permissions").unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
     }
 }
\ No newline at end of file
@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+    use crate::backend::db_backup::{self, BackupManifest};
+    use crate::backend::app_paths::AppPaths;
+    use crate::backend::db_ops::DBOps;
+    use crate::backend::sources::{parse_hash_list, parse_removal_list};
+
+    const BACKUP_LOC: &str = "test_backup.tar.gz";
+    const TAMPERED_LOC: &str = "test_backup_tampered.tar.gz";
+    const WRONG_COUNT_LOC: &str = "test_backup_wrong_count.tar.gz";
+
+    /// Rewrites the entries of a backup, letting `change` modify the manifest
+    fn rewrite_backup(source: &str, target: &str, change: impl Fn(&mut BackupManifest)) {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(source).unwrap()));
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(target).unwrap(), Compression::default()));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+            if name == "manifest.json" {
+                let mut manifest: BackupManifest = serde_json::from_slice(&content).unwrap();
+                change(&mut manifest);
+                content = serde_json::to_vec(&manifest).unwrap();
+            }
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_slice()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_export_and_verify() {
+        let db_dir = Path::new("test_backup_db");
+        let paths = AppPaths::in_dir(db_dir);
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        db_ops
+            .import_batch("test", "backup.md5", parse_hash_list(b"93fe4fb85a682907137b0b1051991332").unwrap())
+            .unwrap();
+        let manifest = db_backup::export(&db_ops, &paths, Path::new(BACKUP_LOC)).unwrap();
+
+        let backup = db_backup::verify(&paths, Path::new(BACKUP_LOC)).unwrap();
+        assert!(backup.database().starts_with(&paths.cache_dir));
+        assert_eq!(backup.manifest, manifest);
+        drop(backup);
+        // The work directories are gone once the export and the verified backup are done
+        assert_eq!(std::fs::read_dir(&paths.cache_dir).unwrap().count(), 0);
+        assert_eq!(manifest.hash_count, 1);
+        assert!(manifest
+            .batches
+            .iter()
+            .any(|batch| batch.source == "test" && batch.name == "backup.md5"));
+
+        rewrite_backup(BACKUP_LOC, TAMPERED_LOC, |manifest| manifest.sha256 = "0".repeat(64));
+        let tampered = db_backup::verify(&paths, Path::new(TAMPERED_LOC));
+
+        rewrite_backup(BACKUP_LOC, WRONG_COUNT_LOC, |manifest| manifest.hash_count += 1);
+        let wrong_count = db_backup::verify(&paths, Path::new(WRONG_COUNT_LOC));
+
+        for path in [BACKUP_LOC, TAMPERED_LOC, WRONG_COUNT_LOC] {
+            std::fs::remove_file(path).unwrap();
+        }
+        drop(db_ops);
+        std::fs::remove_dir_all(db_dir).unwrap();
+        assert!(tampered.unwrap_err().contains("checksum"));
+        assert!(wrong_count.is_err());
+    }
+
+    #[test]
+    fn test_export_counts_snapshot() {
+        let db_dir = Path::new("test_backup_snapshot_db");
+        let snapshot = Path::new("test_backup_snapshot.db");
+        let target = Path::new("test_backup_snapshot.tar.gz");
+        let paths = AppPaths::in_dir(db_dir);
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        db_ops
+            .import_batch("test", "first.md5", parse_hash_list(b"93fe4fb85a682907137b0b1051991332").unwrap())
+            .unwrap();
+        db_ops.snapshot(snapshot).unwrap();
+        // Written after the snapshot was taken, so it must not be part of the backup
+        db_ops
+            .import_batch("test", "second.md5", parse_hash_list(b"ec2112c9c243d837247217baf351ab79").unwrap())
+            .unwrap();
+
+        let manifest = db_backup::export_snapshot(snapshot, target).unwrap();
+        let verified = db_backup::verify(&paths, target).map(|backup| backup.manifest.clone());
+        drop(db_ops);
+        std::fs::remove_file(snapshot).unwrap();
+        std::fs::remove_file(target).unwrap();
+        std::fs::remove_dir_all(db_dir).unwrap();
+
+        assert_eq!(manifest.hash_count, 1);
+        assert!(manifest.batches.iter().all(|batch| batch.name != "second.md5"));
+        assert_eq!(verified.unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_merge_removals() {
+        let (kept, retracted, merged) = (
+            "93fe4fb85a682907137b0b1051991332",
+            "ec2112c9c243d837247217baf351ab79",
+            "0123456789abcdef0123456789abcdef",
+        );
+        let main_dir = Path::new("test_merge_main");
+        let other_dir = Path::new("test_merge_other");
+        let mut main = DBOps::new(&AppPaths::in_dir(main_dir), None).unwrap();
+        main.import_batch("test", "main.md5", parse_hash_list(format!("{}\n{}", kept, retracted).as_bytes()).unwrap())
+            .unwrap();
+        let mut other = DBOps::new(&AppPaths::in_dir(other_dir), None).unwrap();
+        other
+            .import_batch("test", "other.md5", parse_hash_list(format!("{}\n{}", merged, retracted).as_bytes()).unwrap())
+            .unwrap();
+        other
+            .apply_removals("test", "fp.removed", parse_removal_list(format!("{},Signed driver", retracted).as_bytes()).unwrap())
+            .unwrap();
+        drop(other);
+
+        let hash_count = main.merge_from(&AppPaths::in_dir(other_dir).database()).unwrap();
+        let found = main.hashes_exist(&[kept.to_owned(), retracted.to_owned(), merged.to_owned()]).unwrap();
+        let removals = main.get_removals().unwrap();
+        let batches = main.get_batches().unwrap();
+        drop(main);
+        std::fs::remove_dir_all(main_dir).unwrap();
+        std::fs::remove_dir_all(other_dir).unwrap();
+
+        assert_eq!(hash_count, 2);
+        assert!(found.contains_key(kept) && found.contains_key(merged));
+        assert!(!found.contains_key(retracted));
+        assert_eq!(removals.len(), 1);
+        assert_eq!(removals[0].reason.as_deref(), Some("Signed driver"));
+        assert!(removals[0].was_present);
+        // Both batches only count the hashes they still hold
+        assert!(batches.iter().all(|batch| batch.hash_count == 1));
+    }
+
+    #[test]
+    fn test_verify_invalid_file() {
+        let path = "test_backup_invalid.tar.gz";
+        let paths = AppPaths::in_dir(Path::new("test_backup_invalid"));
+        std::fs::write(path, b"not a backup").unwrap();
+        let result = db_backup::verify(&paths, Path::new(path));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_dir_all("test_backup_invalid").unwrap();
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    use rusqlite::{params, Connection};
+
+    use crate::backend::db_health;
+    use crate::backend::db_migrations;
+
+    /// Creates a database with a batch per entry, holding the given amount of hashes
+    /// while the batch claims to hold the recorded amount
+    fn create_database(path: &str, batches: &[(&str, u64, u64)]) {
+        let _ = fs::remove_file(path);
+        let conn = Connection::open(path).unwrap();
+        db_migrations::migrate(&conn).unwrap();
+        let mut hash_nr: u64 = 0;
+        for (id, (name, holds, recorded)) in batches.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO batches(id, source, name, hash_count) VALUES (?, 'test', ?, ?)",
+                params![id as i64, name, *recorded as i64],
+            )
+            .unwrap();
+            for _ in 0..*holds {
+                hash_nr += 1;
+                let hash = format!(
+                    "{:032x}",
+                    (hash_nr as u128).wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835)
+                );
+                let blob: Vec<u8> = (0..16)
+                    .map(|i| u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16).unwrap())
+                    .collect();
+                conn.execute(
+                    "INSERT INTO signatures(hash, file_nr) VALUES (?, ?)",
+                    params![blob, id as i64],
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    fn remove_database(path: &str) {
+        for file in [path.to_owned(), format!("{}-journal", path)] {
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn test_check_counts() {
+        let path = "test_health_counts.db";
+        create_database(path, &[("complete.md5", 20, 20), ("truncated.md5", 5, 30)]);
+        let report = db_health::check(Path::new(path), true);
+        let missing = db_health::check(Path::new("test_health_missing.db"), false);
+        remove_database(path);
+
+        assert!(!report.is_healthy());
+        assert_eq!(report.hash_count, 25);
+        assert_eq!(report.expected_hashes, 50);
+        assert_eq!(report.damaged_batches.len(), 1);
+        assert_eq!(report.damaged_batches[0].name, "truncated.md5");
+        assert!(report.summary().contains("truncated.md5"));
+        assert!(missing.is_healthy());
+    }
+
+    #[test]
+    fn test_repair_counts() {
+        let path = "test_health_repair_counts.db";
+        create_database(path, &[("complete.md5", 20, 20), ("truncated.md5", 5, 30)]);
+        let repaired = db_health::repair(Path::new(path)).unwrap();
+        let report = db_health::check(Path::new(path), true);
+        remove_database(path);
+
+        // The remaining hashes of the truncated batch are dropped along with it, to be downloaded again
+        assert_eq!(repaired.recovered_hashes, 20);
+        assert_eq!(repaired.redownload.len(), 1);
+        assert_eq!(repaired.redownload[0].name, "truncated.md5");
+        assert!(report.is_healthy());
+        assert_eq!(report.hash_count, 20);
+    }
+
+    #[test]
+    fn test_repair_corrupted() {
+        let path = "test_health_corrupted.db";
+        create_database(path, &[("first.md5", 3000, 3000), ("second.md5", 3000, 3000)]);
+        // Overwrite a page in the middle of the file, as a power loss during a write could
+        let size = fs::metadata(path).unwrap().len();
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(size / 2 / 4096 * 4096)).unwrap();
+        file.write_all(&[0x55; 4096]).unwrap();
+        drop(file);
+
+        let damaged = db_health::check(Path::new(path), false);
+        let repaired = db_health::repair(Path::new(path)).unwrap();
+        let report = db_health::check(Path::new(path), true);
+        remove_database(path);
+
+        // Hashes of every batch are spread over all pages, so which batches survive depends on the damaged page
+        assert!(!damaged.is_healthy());
+        assert!(repaired.recovered_hashes < 6000);
+        assert!(!repaired.redownload.is_empty());
+        assert!(report.is_healthy());
+        assert_eq!(report.hash_count, repaired.recovered_hashes);
+    }
+
+    #[test]
+    fn test_repair_unreadable() {
+        let path = "test_health_unreadable.db";
+        fs::write(path, vec![0x55; 8192]).unwrap();
+
+        let damaged = db_health::check(Path::new(path), false);
+        let repaired = db_health::repair(Path::new(path)).unwrap();
+        let report = db_health::check(Path::new(path), false);
+        let version = db_migrations::schema_version(&Connection::open(path).unwrap()).unwrap();
+        remove_database(path);
+
+        assert!(!damaged.is_healthy());
+        assert_eq!(repaired.recovered_hashes, 0);
+        assert!(report.is_healthy());
+        assert_eq!(version, db_migrations::SCHEMA_VERSION);
+    }
+}
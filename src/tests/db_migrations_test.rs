@@ -0,0 +1,134 @@
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use crate::backend::db_migrations::{self, MigrationError, SCHEMA_VERSION};
+
+    const HASH_1: &str = "93fe4fb85a682907137b0b1051991332";
+    const HASH_2: &str = "ec2112c9c243d837247217baf351ab79";
+
+    fn hash_type(conn: &Connection) -> String {
+        conn.query_row(
+            "SELECT type FROM pragma_table_info('signatures') WHERE name = 'hash'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// Fixture of a database created before versioning: text hashes and file numbers
+    fn legacy_fixture() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE signatures (hash varchar(32) PRIMARY KEY, file_nr varchar(5))",
+            [],
+        )
+        .unwrap();
+        for (hash, file_nr) in [(HASH_1, "00012"), (HASH_2, "00013"), ("invalid", "00013")] {
+            conn.execute("INSERT INTO signatures VALUES (?, ?)", [hash, file_nr])
+                .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_fresh_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        let applied = db_migrations::migrate(&conn).unwrap();
+
+        assert_eq!(applied, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(db_migrations::schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(hash_type(&conn), "BLOB");
+        assert_eq!(count(&conn, "batches"), 0);
+        // Running again does nothing
+        assert!(db_migrations::migrate(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_each_migration_step() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(db_migrations::migrate_to(&conn, 1).unwrap(), vec![1]);
+        assert_eq!(hash_type(&conn), "varchar(32)");
+        conn.execute("INSERT INTO signatures VALUES (?, '00001')", [HASH_1])
+            .unwrap();
+
+        assert_eq!(db_migrations::migrate_to(&conn, 2).unwrap(), vec![2]);
+        assert_eq!(hash_type(&conn), "BLOB");
+        assert_eq!(count(&conn, "signatures"), 1);
+
+        assert_eq!(db_migrations::migrate_to(&conn, 3).unwrap(), vec![3]);
+        let batch: (i64, String, String, i64) = conn
+            .query_row("SELECT id, source, name, hash_count FROM batches", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        assert_eq!(batch, (1, "virusshare".to_owned(), "00001".to_owned(), 1));
+
+        assert_eq!(db_migrations::migrate_to(&conn, 4).unwrap(), vec![4]);
+        assert_eq!(count(&conn, "signature_meta"), 0);
+
+        assert_eq!(db_migrations::migrate_to(&conn, 5).unwrap(), vec![5]);
+        assert_eq!(count(&conn, "removal_lists"), 0);
+        assert_eq!(count(&conn, "removals"), 0);
+
+        let generation = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT value FROM db_generation", [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(db_migrations::migrate_to(&conn, 6).unwrap(), vec![6]);
+        // Adding and removing a hash leaves the count unchanged, but not the generation
+        let before = (generation(&conn), count(&conn, "signatures"));
+        conn.execute("INSERT INTO signatures VALUES (zeroblob(16), 1)", []).unwrap();
+        conn.execute("DELETE FROM signatures WHERE hash = zeroblob(16)", []).unwrap();
+        assert_eq!(count(&conn, "signatures"), before.1);
+        assert_eq!(generation(&conn), before.0 + 2);
+    }
+
+    #[test]
+    fn test_legacy_database() {
+        let conn = legacy_fixture();
+        let applied = db_migrations::migrate(&conn).unwrap();
+
+        assert_eq!(applied, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(hash_type(&conn), "BLOB");
+        // The invalid hash is dropped, both files become batches
+        assert_eq!(count(&conn, "signatures"), 2);
+        assert_eq!(count(&conn, "batches"), 2);
+    }
+
+    #[test]
+    fn test_unversioned_compact_database() {
+        // Compact layout without the batches table and without a schema version
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE signatures (hash BLOB PRIMARY KEY, file_nr INTEGER NOT NULL) WITHOUT ROWID",
+            [],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO signatures VALUES (randomblob(16), 7)", [])
+            .unwrap();
+        db_migrations::migrate(&conn).unwrap();
+
+        assert_eq!(count(&conn, "signatures"), 1);
+        assert_eq!(count(&conn, "batches"), 1);
+    }
+
+    #[test]
+    fn test_newer_schema_refused() {
+        let conn = legacy_fixture();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+
+        assert!(matches!(
+            db_migrations::migrate(&conn),
+            Err(MigrationError::NewerSchema { .. })
+        ));
+        // Nothing was changed
+        assert_eq!(hash_type(&conn), "varchar(32)");
+    }
+}
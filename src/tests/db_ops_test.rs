@@ -1,29 +1,25 @@
 #[cfg(test)]
 mod tests {
-    use crate::backend::db_ops::DBOps;
-    const DB_FILE_LOC:&str = "signatures.db";
+    use std::path::Path;
 
-    #[test]
-    fn test_init_table() {
-        let db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
-        assert!(db_ops.init_table().is_ok());
-    }
+    use crate::backend::app_paths::AppPaths;
+    use crate::backend::db_ops::DBOps;
+    use crate::backend::sources::parse_hash_list;
+    const DB_DIR_LOC: &str = "test_db_ops";
 
-    #[test]
-    fn test_download_files() {
-        let mut db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
-        db_ops.download_files(vec![1, 2]);
+    fn test_paths() -> AppPaths {
+        AppPaths::in_dir(Path::new(DB_DIR_LOC))
     }
 
     #[test]
-    fn test_download_file() {
-        let file = DBOps::download_file(3).unwrap();
-        assert!(file.is_some());
+    fn test_init_table() {
+        let db_ops = DBOps::new(&test_paths(), None).unwrap();
+        assert!(db_ops.init_table().is_ok());
     }
 
     #[test]
     fn test_insert_hashes() {
-        let mut db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
+        let mut db_ops = DBOps::new(&test_paths(), None).unwrap();
         db_ops
             .insert_hashes(vec![("93fe4fb85a682907137b0b1051991332".to_owned(), "ec2112c9c243d837247217baf351ab79".to_owned())])
             .unwrap();
@@ -31,7 +27,7 @@ mod tests {
 
     #[test]
     fn test_hash_exists() {
-        let mut db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
+        let mut db_ops = DBOps::new(&test_paths(), None).unwrap();
         let hash_to_insert = "93fe4fb85a682907137b0b1051991332";
         let hash_not_in_db = "hello";
     
@@ -53,7 +49,7 @@ mod tests {
 
     #[test]
     fn test_count_hashes() {
-        let db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
+        let db_ops = DBOps::new(&test_paths(), None).unwrap();
         let hash_count = db_ops.count_hashes();
     
         match hash_count {
@@ -69,7 +65,7 @@ mod tests {
     
     #[test]
     fn test_remove_hash() {
-        let db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
+        let db_ops = DBOps::new(&test_paths(), None).unwrap();
         let hash_to_remove = "93fe4fb85a682907137b0b1051991332";
     
         // Remove the hash from the database
@@ -86,28 +82,117 @@ mod tests {
     }
 
     #[test]
-    fn test_get_file_list() {
-        let db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
-        assert!(db_ops.get_file_list() > 0);
+    fn test_remove_hash_from_batch() {
+        let paths = AppPaths::in_dir(Path::new("test_remove_hash"));
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        db_ops
+            .import_batch(
+                "test",
+                "list.md5",
+                parse_hash_list(b"93fe4fb85a682907137b0b1051991332\nec2112c9c243d837247217baf351ab79").unwrap(),
+            )
+            .unwrap();
+
+        db_ops._remove_hash("93fe4fb85a682907137b0b1051991332").unwrap();
+        let batches = db_ops.get_batches().unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all("test_remove_hash").unwrap();
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].hash_count, 1);
+    }
+
+    #[test]
+    fn test_hash_to_blob() {
+        let blob = DBOps::hash_to_blob("93FE4FB85A682907137B0B1051991332").unwrap();
+        assert_eq!(blob[0], 0x93);
+        assert_eq!(blob[15], 0x32);
+        assert!(DBOps::hash_to_blob("+3fe4fb85a682907137b0b105199133+").is_none());
+        assert!(DBOps::hash_to_blob("93fe4fb85a682907137b0b105199133g").is_none());
+        assert!(DBOps::hash_to_blob("93fe4fb85a682907137b0b10519913").is_none());
     }
 
     #[test]
-    fn test_file_exists() {
-        assert!(DBOps::file_exists(1).unwrap_or(false));
+    fn test_hashes_exist() {
+        let paths = AppPaths::in_dir(Path::new("test_hashes_exist"));
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        let hashes: Vec<String> = (0..1200u64).map(|nr| format!("{:032x}", 0x1000 + nr)).collect();
+        // Hits in two imported batches, spread over several lookup chunks
+        let first: Vec<String> = hashes.iter().step_by(100).cloned().collect();
+        let second: Vec<String> = hashes.iter().skip(550).step_by(300).cloned().collect();
+        db_ops.import_batch("test", "first.md5", parse_hash_list(first.join("\n").as_bytes()).unwrap()).unwrap();
+        db_ops.import_batch("test", "second.md5", parse_hash_list(second.join("\n").as_bytes()).unwrap()).unwrap();
+
+        let mut lookup = hashes.clone();
+        lookup.push("not a hash".to_owned());
+        let found = db_ops.hashes_exist(&lookup).unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all("test_hashes_exist").unwrap();
+
+        assert_eq!(found.len(), first.len() + second.len());
+        assert!(first.iter().chain(second.iter()).all(|hash| found.contains_key(hash)));
+        assert_eq!(found[&first[0]].source.as_deref(), Some("test"));
+        assert!(!found.contains_key(&hashes[1]));
+        assert!(!found.contains_key(&hashes[1199]));
+    }
+
+    #[test]
+    fn test_mixed_case_hashes() {
+        let paths = AppPaths::in_dir(Path::new("test_mixed_case_hashes"));
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        db_ops
+            .insert_hashes(vec![("93FE4FB85A682907137B0B1051991332".to_owned(), "ec2112c9c243d837247217baf351ab79".to_owned())])
+            .unwrap();
+
+        let exact = db_ops.hash_exists("93FE4FB85A682907137B0B1051991332").unwrap();
+        let lower = db_ops.hash_exists("93fe4fb85a682907137b0b1051991332").unwrap();
+        let found = db_ops
+            .hashes_exist(&["93fe4fb85a682907137b0b1051991332".to_owned(), "EC2112C9C243D837247217BAF351AB79".to_owned()])
+            .unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all("test_mixed_case_hashes").unwrap();
+
+        assert_eq!(exact, Some(true));
+        assert_eq!(lower, Some(true));
+        assert_eq!(found.len(), 1);
+        assert!(found.contains_key("93fe4fb85a682907137b0b1051991332"));
+    }
+
+    #[test]
+    fn test_insert_errors() {
+        let dir = Path::new("test_insert_errors");
+        let paths = AppPaths::in_dir(dir);
+        let mut db_ops = DBOps::new(&paths, None).unwrap();
+        let hash = "93fe4fb85a682907137b0b1051991333".to_owned();
+        let first = db_ops.insert_hashes(vec![(hash.clone(), "1".to_owned())]).unwrap();
+        let duplicate = db_ops.insert_hashes(vec![(hash, "1".to_owned())]).unwrap();
+
+        // Any other failure has to reach the caller instead of being counted as duplicate
+        rusqlite::Connection::open(paths.database())
+            .unwrap()
+            .execute("DROP TABLE removals", [])
+            .unwrap();
+        let failed = db_ops.insert_hashes(vec![("93fe4fb85a682907137b0b1051991334".to_owned(), "1".to_owned())]);
+        drop(db_ops);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(first, 1);
+        assert_eq!(duplicate, 0);
+        assert!(failed.is_err());
     }
 
     #[test]
     fn test_get_db_files() {
-        let db_ops = DBOps::new(DB_FILE_LOC, None).unwrap();
-        assert!(db_ops.get_db_files().is_some());
+        let db_ops = DBOps::new(&test_paths(), None).unwrap();
+        assert!(db_ops.get_db_files("virusshare").is_some());
     }
 
     #[cfg(test)]
     #[ctor::dtor]
     fn teardown() {
-        if std::path::Path::new(DB_FILE_LOC).exists() {
-            if let Err(err) = std::fs::remove_file(DB_FILE_LOC) {
-                eprintln!("Failed to delete the database file: {}", err);
+        if Path::new(DB_DIR_LOC).exists() {
+            if let Err(err) = std::fs::remove_dir_all(DB_DIR_LOC) {
+                eprintln!("Failed to delete the database directory: {}", err);
             } else {
                 println!("Database file deleted successfully");
             }
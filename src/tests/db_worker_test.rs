@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::thread;
+
+    use tokio::runtime::Runtime;
+
+    use crate::backend::app_paths::AppPaths;
+    use crate::backend::db_worker::DBHandle;
+    use crate::backend::sources::parse_hash_list;
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let hashes: Vec<String> = (0..3u64).map(|nr| format!("{:032x}", 0xdb_a070_0000 + nr)).collect();
+        let db = DBHandle::spawn(AppPaths::in_dir(Path::new("test_db_worker")));
+        let runtime = Runtime::new().unwrap();
+
+        let (before, found, stats) = runtime.block_on(async {
+            let before = db.stats().await.unwrap();
+            db.insert_batch("worker", "list.md5", parse_hash_list(hashes.join("\n").as_bytes()).unwrap())
+                .await
+                .unwrap();
+            // Answered by a reader connection, which has to see the hashes the writer just added
+            let found = db.lookup(hashes.clone()).await.unwrap();
+            (before, found, db.stats().await.unwrap())
+        });
+        drop(db);
+        std::fs::remove_dir_all("test_db_worker").unwrap();
+
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[&hashes[0]].source.as_deref(), Some("worker"));
+        assert_eq!(stats.hash_count, before.hash_count + 3);
+        assert_eq!(stats.batch_count, 1);
+    }
+
+    #[test]
+    fn test_concurrent_reads() {
+        let hash = format!("{:032x}", 0xdb_a070_1000u64);
+        let db = DBHandle::spawn(AppPaths::in_dir(Path::new("test_db_worker_reads")));
+        Runtime::new()
+            .unwrap()
+            .block_on(db.insert_batch("worker", "concurrent.md5", parse_hash_list(hash.as_bytes()).unwrap()))
+            .unwrap();
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                let hash = hash.clone();
+                thread::spawn(move || (0..20).all(|_| db.lookup_blocking(vec![hash.clone()]).unwrap().len() == 1))
+            })
+            .collect();
+        let all_found = readers.into_iter().all(|reader| reader.join().unwrap());
+        drop(db);
+        std::fs::remove_dir_all("test_db_worker_reads").unwrap();
+
+        assert!(all_found);
+    }
+
+    #[test]
+    fn test_restore_reopens_readers() {
+        let (kept, dropped) = (format!("{:032x}", 0xdb_a070_2000u64), format!("{:032x}", 0xdb_a070_2001u64));
+        let backup = Path::new("test_db_worker_restore.tar.gz");
+        let db = DBHandle::spawn(AppPaths::in_dir(Path::new("test_db_worker_restore")));
+        let runtime = Runtime::new().unwrap();
+
+        let (before, after) = runtime.block_on(async {
+            db.insert_batch("worker", "kept.md5", parse_hash_list(kept.as_bytes()).unwrap())
+                .await
+                .unwrap();
+            db.export(backup.to_path_buf()).await.unwrap();
+            db.insert_batch("worker", "dropped.md5", parse_hash_list(dropped.as_bytes()).unwrap())
+                .await
+                .unwrap();
+            // Keeps the reader connections open across the restore
+            let before = db.lookup(vec![kept.clone(), dropped.clone()]).await.unwrap();
+            db.restore(backup.to_path_buf(), false).await.unwrap();
+            (before, db.lookup(vec![kept.clone(), dropped.clone()]).await.unwrap())
+        });
+        drop(db);
+        std::fs::remove_file(backup).unwrap();
+        std::fs::remove_dir_all("test_db_worker_restore").unwrap();
+
+        assert_eq!(before.len(), 2);
+        assert!(after.contains_key(&kept));
+        assert!(!after.contains_key(&dropped));
+    }
+}
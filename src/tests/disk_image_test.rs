@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::backend::disk_image;
+
+    const FAT_IMAGE_LOC: &str = "test_disk.img";
+    const ISO_IMAGE_LOC: &str = "test_disk.iso";
+
+    /// Builds a tiny FAT12 image containing `/HELLO.TXT`, `/LongFileName.txt` and `/EFI/boot.efi`
+    fn build_fat12_image() -> Vec<u8> {
+        let mut image = vec![0u8; 64 * 512];
+        // Boot sector: 512 bytes per sector, 1 sector per cluster, 1 reserved sector, 2 FATs,
+        // 16 root entries, 64 sectors in total, 1 sector per FAT
+        image[11..13].copy_from_slice(&512u16.to_le_bytes());
+        image[13] = 1;
+        image[14..16].copy_from_slice(&1u16.to_le_bytes());
+        image[16] = 2;
+        image[17..19].copy_from_slice(&16u16.to_le_bytes());
+        image[19..21].copy_from_slice(&64u16.to_le_bytes());
+        image[21] = 0xF8;
+        image[22..24].copy_from_slice(&1u16.to_le_bytes());
+        image[510] = 0x55;
+        image[511] = 0xAA;
+
+        // FAT: cluster 2, 3 and 6 are single cluster chains, 4 continues in 5
+        let mut fat = vec![0u8; 512];
+        let entries: [(usize, u16); 7] = [(0, 0xFF8), (1, 0xFFF), (2, 0xFFF), (3, 0xFFF), (4, 5), (5, 0xFFF), (6, 0xFFF)];
+        for (cluster, value) in entries {
+            let at = cluster + cluster / 2;
+            if cluster % 2 == 0 {
+                fat[at] = (value & 0xFF) as u8;
+                fat[at + 1] = (fat[at + 1] & 0xF0) | ((value >> 8) as u8 & 0x0F);
+            } else {
+                fat[at] = (fat[at] & 0x0F) | (((value & 0x0F) as u8) << 4);
+                fat[at + 1] = (value >> 4) as u8;
+            }
+        }
+        image[512..1024].copy_from_slice(&fat);
+        image[1024..1536].copy_from_slice(&fat);
+
+        let short_entry = |name: &[u8; 11], attributes: u8, case: u8, cluster: u16, size: u32| {
+            let mut entry = [0u8; 32];
+            entry[..11].copy_from_slice(name);
+            entry[11] = attributes;
+            entry[12] = case;
+            entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+            entry[28..32].copy_from_slice(&size.to_le_bytes());
+            entry
+        };
+
+        // Long file name entry for "LongFileName.txt", split over two entries of 13 characters
+        let long_name: Vec<u16> = "LongFileName.txt".encode_utf16().collect();
+        let mut lfn_entries = Vec::new();
+        for (index, part) in long_name.chunks(13).enumerate() {
+            let mut units = part.to_vec();
+            if units.len() < 13 {
+                units.push(0x0000);
+            }
+            units.resize(13, 0xFFFF);
+            let mut entry = [0u8; 32];
+            entry[0] = index as u8 + 1;
+            entry[11] = 0x0F;
+            let bytes: Vec<u8> = units.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+            entry[1..11].copy_from_slice(&bytes[0..10]);
+            entry[14..26].copy_from_slice(&bytes[10..22]);
+            entry[28..32].copy_from_slice(&bytes[22..26]);
+            lfn_entries.push(entry);
+        }
+        lfn_entries.last_mut().unwrap()[0] |= 0x40;
+        lfn_entries.reverse();
+
+        // Root directory in sector 3
+        let mut root = Vec::new();
+        root.extend_from_slice(&short_entry(b"HELLO   TXT", 0x20, 0, 2, 11));
+        root.extend_from_slice(&short_entry(b"EFI        ", 0x10, 0, 3, 0));
+        for entry in &lfn_entries {
+            root.extend_from_slice(entry);
+        }
+        root.extend_from_slice(&short_entry(b"LONGFI~1TXT", 0x20, 0, 6, 4));
+        image[1536..1536 + root.len()].copy_from_slice(&root);
+
+        // Data area starts at sector 4 with cluster 2
+        let cluster_offset = |cluster: usize| (4 + cluster - 2) * 512;
+        image[cluster_offset(2)..cluster_offset(2) + 11].copy_from_slice(b"hello world");
+
+        let mut efi_dir = Vec::new();
+        efi_dir.extend_from_slice(&short_entry(b".          ", 0x10, 0, 3, 0));
+        efi_dir.extend_from_slice(&short_entry(b"..         ", 0x10, 0, 0, 0));
+        efi_dir.extend_from_slice(&short_entry(b"BOOT    EFI", 0x20, 0x18, 4, 600));
+        image[cluster_offset(3)..cluster_offset(3) + efi_dir.len()].copy_from_slice(&efi_dir);
+
+        for i in 0..600 {
+            image[cluster_offset(4) + i] = (i % 251) as u8;
+        }
+        image[cluster_offset(6)..cluster_offset(6) + 4].copy_from_slice(b"long");
+        image
+    }
+
+    /// Builds a tiny ISO9660 image containing `/README.TXT` and `/EFI/BOOT.EFI`
+    fn build_iso_image() -> Vec<u8> {
+        const SECTOR: usize = 2048;
+        let mut image = vec![0u8; 22 * SECTOR];
+
+        let record = |extent: u32, size: u32, flags: u8, name: &[u8]| {
+            let mut len = 33 + name.len();
+            len += len % 2;
+            let mut record = vec![0u8; len];
+            record[0] = len as u8;
+            record[2..6].copy_from_slice(&extent.to_le_bytes());
+            record[6..10].copy_from_slice(&extent.to_be_bytes());
+            record[10..14].copy_from_slice(&size.to_le_bytes());
+            record[14..18].copy_from_slice(&size.to_be_bytes());
+            record[25] = flags;
+            record[32] = name.len() as u8;
+            record[33..33 + name.len()].copy_from_slice(name);
+            record
+        };
+
+        // Primary volume descriptor in sector 16, terminator in sector 17
+        let pvd = 16 * SECTOR;
+        image[pvd] = 1;
+        image[pvd + 1..pvd + 6].copy_from_slice(b"CD001");
+        image[pvd + 6] = 1;
+        image[pvd + 128..pvd + 130].copy_from_slice(&(SECTOR as u16).to_le_bytes());
+        let root_record = record(18, SECTOR as u32, 2, &[0]);
+        image[pvd + 156..pvd + 156 + 34].copy_from_slice(&root_record);
+        let terminator = 17 * SECTOR;
+        image[terminator] = 255;
+        image[terminator + 1..terminator + 6].copy_from_slice(b"CD001");
+
+        let mut root = Vec::new();
+        root.extend(record(18, SECTOR as u32, 2, &[0]));
+        root.extend(record(18, SECTOR as u32, 2, &[1]));
+        root.extend(record(19, SECTOR as u32, 2, b"EFI"));
+        root.extend(record(21, 6, 0, b"README.TXT;1"));
+        image[18 * SECTOR..18 * SECTOR + root.len()].copy_from_slice(&root);
+
+        let mut efi = Vec::new();
+        efi.extend(record(19, SECTOR as u32, 2, &[0]));
+        efi.extend(record(18, SECTOR as u32, 2, &[1]));
+        efi.extend(record(20, 8, 0, b"BOOT.EFI;1"));
+        image[19 * SECTOR..19 * SECTOR + efi.len()].copy_from_slice(&efi);
+
+        image[20 * SECTOR..20 * SECTOR + 8].copy_from_slice(b"efi boot");
+        image[21 * SECTOR..21 * SECTOR + 6].copy_from_slice(b"readme");
+        image
+    }
+
+    fn collect_files(path: &str) -> Vec<(String, Vec<u8>)> {
+        let mut files = Vec::new();
+        disk_image::visit_files(Path::new(path), &mut |inner_path, reader| {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+            files.push((inner_path.to_owned(), content));
+        })
+        .unwrap();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_is_disk_image() {
+        assert!(disk_image::is_disk_image(Path::new("/media/usb/disk.IMG")));
+        assert!(disk_image::is_disk_image(Path::new("ubuntu.iso")));
+        assert!(!disk_image::is_disk_image(Path::new("notes.txt")));
+    }
+
+    #[test]
+    fn test_visit_fat12_image() {
+        std::fs::write(FAT_IMAGE_LOC, build_fat12_image()).unwrap();
+        let files = collect_files(FAT_IMAGE_LOC);
+        std::fs::remove_file(FAT_IMAGE_LOC).unwrap();
+
+        let expected_boot: Vec<u8> = (0..600).map(|i| (i % 251) as u8).collect();
+        assert_eq!(
+            files,
+            vec![
+                ("/EFI/boot.efi".to_owned(), expected_boot),
+                ("/HELLO.TXT".to_owned(), b"hello world".to_vec()),
+                ("/LongFileName.txt".to_owned(), b"long".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_iso_image() {
+        std::fs::write(ISO_IMAGE_LOC, build_iso_image()).unwrap();
+        let files = collect_files(ISO_IMAGE_LOC);
+        std::fs::remove_file(ISO_IMAGE_LOC).unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                ("/EFI/BOOT.EFI".to_owned(), b"efi boot".to_vec()),
+                ("/README.TXT".to_owned(), b"readme".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_invalid_image() {
+        let path = "test_invalid.img";
+        std::fs::write(path, vec![0xAB; 4096]).unwrap();
+        let result = disk_image::visit_files(Path::new(path), &mut |_, _| {});
+        std::fs::remove_file(path).unwrap();
+
+        assert!(result.is_err());
+    }
+}
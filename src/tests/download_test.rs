@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use reqwest::blocking::Client;
+
+    use crate::backend::sources::{download::ResumableDownload, SourceError};
+    use crate::tests::{self, MockResponse};
+
+    const DOWNLOAD_DIR: &str = "test_downloads";
+    const CONTENT: &str = "93fe4fb85a682907137b0b1051991332\nec2112c9c243d837247217baf351ab79\n";
+
+    /// Starts a stand-in server for a single file with the given `ETag`. Requests without a `Range`
+    /// header get the first half of the file and then the connection drops, ranged requests with a
+    /// matching `If-Range` get the rest. Returns the URL of the file.
+    fn start_server(etag: &'static str) -> String {
+        let address = tests::start_server(move |request| {
+            let range = request
+                .header("range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+            match range {
+                Some(start) if request.header("if-range") == Some(etag) => MockResponse::status("206 Partial Content")
+                    .header(&format!("Content-Range: bytes {}-{}/{}", start, CONTENT.len() - 1, CONTENT.len()))
+                    .body(&CONTENT[start..]),
+                _ => MockResponse::ok(&CONTENT[..CONTENT.len() / 2])
+                    .header(&format!("ETag: {}", etag))
+                    .truncated(CONTENT.len()),
+            }
+        });
+        format!("http://{}/hashfiles/VirusShare_00000.md5", address)
+    }
+
+    #[test]
+    fn test_resume_download() {
+        let client = Client::new();
+        let url = start_server("\"v1\"");
+        let download = ResumableDownload::new(Path::new(DOWNLOAD_DIR), &url);
+
+        let interrupted = download.fetch(client.get(&url));
+        let partial = download.downloaded();
+        // A new instance, as after a restart of the application
+        let resumed = ResumableDownload::new(Path::new(DOWNLOAD_DIR), &url).fetch(client.get(&url));
+        download.discard();
+        let discarded = download.downloaded();
+        std::fs::remove_dir_all(DOWNLOAD_DIR).ok();
+
+        assert!(matches!(interrupted, Err(SourceError::Interrupted(_))));
+        assert_eq!(partial, (CONTENT.len() / 2) as u64);
+        assert_eq!(resumed.unwrap(), CONTENT.as_bytes());
+        assert_eq!(discarded, 0);
+    }
+}
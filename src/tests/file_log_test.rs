@@ -1,13 +1,21 @@
 #[cfg(test)]
 mod tests {
     use std::io::Write;
+    use std::path::Path;
     use regex::Regex;
 
+    use crate::backend::app_paths::AppPaths;
     use crate::backend::file_log::FileLog;
 
+    const LOG_DIR_LOC: &str = "test_file_log";
+
+    fn test_paths() -> AppPaths {
+        AppPaths::in_dir(Path::new(LOG_DIR_LOC))
+    }
+
     #[test]
     fn test_create_file() {
-        let log = FileLog::new("log.txt".to_owned());
+        let log = FileLog::new(&test_paths(), "log.txt".to_owned());
 
         // Assert that the file is created
         assert!(log.file.is_some());
@@ -15,7 +23,7 @@ mod tests {
 
     #[test]
     fn test_log() {
-        let log = FileLog::new("log.txt".to_owned());
+        let log = FileLog::new(&test_paths(), "log.txt".to_owned());
     
         // Log a hash and file path
         log.log("abc123".to_owned(), "C:/Users/user/Desktop/file.txt".to_owned());
@@ -50,7 +58,7 @@ mod tests {
     #[cfg(test)]
     #[ctor::dtor]
     fn teardown() {
-        let log = FileLog::new("log.txt".to_owned());
+        let log = FileLog::new(&test_paths(), "log.txt".to_owned());
         let file = log.file.unwrap();
 
         let mut output = Vec::new();
@@ -80,6 +88,7 @@ mod tests {
         } else {
             println!("Teardown skipped, file does not exist");
         }
+        let _ = std::fs::remove_dir_all(LOG_DIR_LOC);
     }  
     
 }
@@ -1 +1,186 @@
-// Needs rework: Window is normally passed by Tauri
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Error, Read};
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    use tokio::runtime::Runtime;
+
+    use crate::backend::app_paths::AppPaths;
+    use crate::backend::db_worker::DBHandle;
+    use crate::backend::file_scanner::{FileScanner, ScanResult};
+    use crate::backend::sources::parse_hash_list;
+    use crate::backend::timed_read::{ReadOutcome, TimedReader};
+
+    const INFECTED_CONTENT: &[u8] = b"pretend this is malware";
+
+    /// A mail carrying the infected content as attachment
+    const INFECTED_MAIL: &[u8] = b"From: sender@example.com\r
+Subject: Invoice\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"outer\"\r
+\r
+--outer\r
+Content-Type: application/octet-stream; name=\"invoice.exe\"\r
+Content-Disposition: attachment; filename=\"invoice.exe\"\r
+Content-Transfer-Encoding: base64\r
+\r
+cHJldGVuZCB0aGlzIGlzIG1hbHdhcmU=\r
+--outer--\r
+";
+
+    /// Blocks every read for longer than the stall timeout of the tests, like a bad sector would
+    struct StallingReader;
+
+    impl Read for StallingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Error> {
+            thread::sleep(Duration::from_secs(2));
+            Ok(0)
+        }
+    }
+
+    /// Returns one byte at a time, slow enough to exceed a short time budget without ever stalling
+    struct TricklingReader;
+
+    impl Read for TricklingReader {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            thread::sleep(Duration::from_millis(20));
+            buf[0] = b'x';
+            Ok(1)
+        }
+    }
+
+    /// Opens files normally, except those with "stall" in their name
+    fn open_or_stall(path: &Path) -> Result<Box<dyn Read + Send>, Error> {
+        if path.file_name().unwrap().to_string_lossy().contains("stall") {
+            return Ok(Box::new(StallingReader));
+        }
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    /// Scans a folder with the given files in a fresh database that knows the infected content.
+    /// Reads are given up after 100ms instead of the usual 15 seconds.
+    fn scan(root: &str, files: &[(&str, &[u8])]) -> ScanResult {
+        let root = Path::new(root);
+        let scan_dir = root.join("files");
+        fs::create_dir_all(&scan_dir).unwrap();
+        for (name, content) in files {
+            fs::write(scan_dir.join(name), content).unwrap();
+        }
+        let db = DBHandle::spawn(AppPaths::in_dir(root));
+        let hash = format!("{:x}", md5::compute(INFECTED_CONTENT));
+        Runtime::new()
+            .unwrap()
+            .block_on(db.insert_batch("test", "infected.md5", parse_hash_list(hash.as_bytes()).unwrap()))
+            .unwrap();
+
+        let mut scanner = FileScanner::new(&scan_dir.display().to_string(), None, db).unwrap();
+        scanner.reader = TimedReader::new(Duration::from_millis(100));
+        scanner.opener = open_or_stall;
+        let result = scanner.search_files(false);
+        drop(scanner);
+        fs::remove_dir_all(root).unwrap();
+        result.unwrap()
+    }
+
+    #[test]
+    fn test_stalled_file_is_reported() {
+        let result = scan(
+            "test_scanner_stall",
+            &[("a_clean.txt", b"harmless"), ("b_stall.bin", b"unknown"), ("c_infected.exe", INFECTED_CONTENT)],
+        );
+
+        assert_eq!(result.detections.len(), 1);
+        assert!(result.detections[0].path.ends_with("c_infected.exe"));
+        assert_eq!(result.unreadable_files.len(), 1);
+        assert!(result.unreadable_files[0].ends_with("b_stall.bin"));
+        assert!(!result.device_failing);
+    }
+
+    #[test]
+    fn test_failing_device_keeps_detections() {
+        let result = scan(
+            "test_scanner_failing",
+            &[
+                ("a_infected.exe", INFECTED_CONTENT),
+                ("b_stall.bin", b"unknown"),
+                ("c_stall.bin", b"unknown"),
+                ("d_stall.bin", b"unknown"),
+                ("e_infected.exe", INFECTED_CONTENT),
+            ],
+        );
+
+        // The scan stops after the third stall in a row, but keeps what it found before
+        assert!(result.device_failing);
+        assert_eq!(result.unreadable_files.len(), 3);
+        assert_eq!(result.detections.len(), 1);
+        assert!(result.detections[0].path.ends_with("a_infected.exe"));
+    }
+
+    #[test]
+    fn test_infected_eml_attachment() {
+        let result = scan("test_scanner_eml", &[("invoice.eml", INFECTED_MAIL)]);
+
+        assert_eq!(result.detections.len(), 1);
+        assert!(result.detections[0].path.ends_with("invoice.eml!/invoice.exe"));
+        assert!(result.unreadable_files.is_empty());
+    }
+
+    #[test]
+    fn test_infected_mbox_attachment() {
+        let mut mbox = b"From a@example.com Mon Jan  1 00:00:00 2024\nFrom: a@example.com\nSubject: Hi\n\nHello\n\n".to_vec();
+        mbox.extend_from_slice(b"From b@example.com Mon Jan  1 00:00:00 2024\n");
+        mbox.extend(INFECTED_MAIL.iter().filter(|byte| **byte != b'\r'));
+        let result = scan("test_scanner_mbox", &[("export.mbox", &mbox)]);
+
+        assert_eq!(result.detections.len(), 1);
+        assert!(result.detections[0].path.ends_with("export.mbox!/message-2/invoice.exe"));
+        assert!(result.unreadable_files.is_empty());
+    }
+
+    #[test]
+    fn test_stalled_container_not_opened() {
+        let result = scan("test_scanner_container", &[("a_stall.eml", INFECTED_MAIL), ("b_clean.txt", b"harmless")]);
+
+        // Looking inside would read the stalled file a second time, without its time budget
+        assert!(result.detections.is_empty());
+        assert_eq!(result.unreadable_files.len(), 1);
+        assert!(result.unreadable_files[0].ends_with("a_stall.eml"));
+        assert!(!result.device_failing);
+    }
+
+    #[test]
+    fn test_time_budget_exceeded() {
+        let mut reader = TimedReader::new(Duration::from_secs(1));
+        let outcome = reader.hash(
+            Box::new(|visitor| {
+                visitor("", &mut TricklingReader);
+                Ok(())
+            }),
+            Duration::from_millis(200),
+        );
+        assert!(matches!(outcome, ReadOutcome::TimedOut(_)));
+
+        // The stuck thread has been replaced, so the next job is read normally
+        let outcome = reader.hash(
+            Box::new(|visitor| {
+                visitor("first", &mut &INFECTED_CONTENT[..]);
+                visitor("empty", &mut &b""[..]);
+                Ok(())
+            }),
+            Duration::from_secs(1),
+        );
+        let ReadOutcome::Finished(Ok(entries)) = outcome else {
+            panic!("Job did not finish: {:?}", outcome);
+        };
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "first");
+        assert_eq!(
+            entries[0].1.as_ref().unwrap().as_deref(),
+            Some(format!("{:x}", md5::compute(INFECTED_CONTENT)).as_str())
+        );
+        assert!(matches!(entries[1].1, Ok(None)));
+    }
+}
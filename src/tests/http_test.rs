@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+
+    use crate::backend::sources::{
+        http::{build_client, HttpConfig, DEFAULT_USER_AGENT},
+        SourceError,
+    };
+    use crate::tests::{self, MockRequest, MockResponse};
+
+    /// Starts a stand-in server answering every request with an empty 200 response.
+    /// Returns its address and every request it received.
+    fn start_server() -> (String, Receiver<MockRequest>) {
+        let (sender, received) = mpsc::channel();
+        let address = tests::start_server(move |request| {
+            let _ = sender.send(request.clone());
+            MockResponse::ok("")
+        });
+        (address, received)
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let (address, received) = start_server();
+        let custom = HttpConfig {
+            user_agent: String::from("Raspirus-Test"),
+            ..HttpConfig::default()
+        };
+
+        build_client(&HttpConfig::default()).unwrap().get(format!("http://{}/", address)).send().unwrap();
+        build_client(&custom).unwrap().get(format!("http://{}/", address)).send().unwrap();
+
+        let default_request = received.recv().unwrap();
+        let custom_request = received.recv().unwrap();
+        assert_eq!(default_request.header("user-agent"), Some(DEFAULT_USER_AGENT));
+        assert_eq!(custom_request.header("user-agent"), Some("Raspirus-Test"));
+    }
+
+    #[test]
+    fn test_proxy() {
+        let (proxy_address, proxied) = start_server();
+        let config = HttpConfig {
+            proxy_url: format!("http://{}", proxy_address),
+            proxy_username: String::from("pi"),
+            proxy_password: String::from("secret"),
+            no_proxy: String::from("127.0.0.1"),
+            ..HttpConfig::default()
+        };
+        let client = build_client(&config).unwrap();
+        let (direct_address, direct) = start_server();
+
+        client.get("http://hashes.example.com/VirusShare_00000.md5").send().unwrap();
+        client.get(format!("http://{}/", direct_address)).send().unwrap();
+
+        let request = proxied.recv().unwrap();
+        assert_eq!(request.line, "GET http://hashes.example.com/VirusShare_00000.md5 HTTP/1.1");
+        // "pi:secret" in base64
+        assert_eq!(request.header("proxy-authorization"), Some("Basic cGk6c2VjcmV0"));
+        // Hosts in the no proxy list are reached directly
+        assert_eq!(direct.recv().unwrap().line, "GET / HTTP/1.1");
+        assert!(proxied.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let invalid_proxy = HttpConfig {
+            proxy_url: String::from("not a proxy"),
+            ..HttpConfig::default()
+        };
+        let missing_ca = HttpConfig {
+            ca_certificates: String::from("test_missing_ca.pem"),
+            ..HttpConfig::default()
+        };
+        std::fs::write("test_invalid_ca.pem", "no certificate in here").unwrap();
+        let invalid_ca = HttpConfig {
+            ca_certificates: String::from("test_invalid_ca.pem"),
+            ..HttpConfig::default()
+        };
+
+        let results = [invalid_proxy, missing_ca, invalid_ca].map(|config| build_client(&config));
+        std::fs::remove_file("test_invalid_ca.pem").unwrap();
+
+        for result in results {
+            assert!(matches!(result, Err(SourceError::Config(_))));
+        }
+    }
+}
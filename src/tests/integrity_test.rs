@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use ed25519_dalek::{Signer, SigningKey};
+    use reqwest::blocking::Client;
+
+    use crate::backend::sources::{
+        integrity::{self, Manifest, Verification},
+        virusshare::VirusShareSource,
+        SignatureSource, SourceError,
+    };
+    use crate::tests::{start_server, MockResponse};
+
+    const HASH_FILE: &str = "93fe4fb85a682907137b0b1051991332\n";
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn public_key_hex(key: &SigningKey) -> String {
+        key.verifying_key()
+            .as_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn manifest_for(content: &str) -> String {
+        format!("{}  VirusShare_00000.md5\n", integrity::sha256_hex(content.as_bytes()))
+    }
+
+    /// Starts a stand-in mirror serving the given files below `/hashfiles/` and returns its base URL.
+    /// Files whose content is None are announced with a longer `Content-Length` than sent.
+    fn start_mirror(files: Vec<(&'static str, Option<Vec<u8>>)>) -> String {
+        let address = start_server(move |request| {
+            let file = files
+                .iter()
+                .find(|(name, _)| request.path() == format!("/hashfiles/{}", name));
+            match file {
+                None => MockResponse::status("404 Not Found"),
+                Some((_, Some(body))) => MockResponse::ok(body.clone()),
+                Some((_, None)) => MockResponse::ok(HASH_FILE).truncated(HASH_FILE.len() * 2),
+            }
+        });
+        format!("http://{}/hashfiles", address)
+    }
+
+    #[test]
+    fn test_check_body() {
+        assert!(integrity::check_body("00001", HASH_FILE.as_bytes()).is_ok());
+        assert!(matches!(
+            integrity::check_body("00001", b"\n  <!DOCTYPE html><html><body>Captive portal</body></html>"),
+            Err(SourceError::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse(manifest_for(HASH_FILE).as_bytes()).unwrap();
+
+        assert!(manifest.verify("VirusShare_00000.md5", HASH_FILE.as_bytes()).is_ok());
+        assert!(matches!(
+            manifest.verify("VirusShare_00000.md5", b"ec2112c9c243d837247217baf351ab79\n"),
+            Err(SourceError::Rejected(_))
+        ));
+        assert!(matches!(
+            manifest.verify("VirusShare_00001.md5", HASH_FILE.as_bytes()),
+            Err(SourceError::Rejected(_))
+        ));
+        assert!(Manifest::parse(b"<html></html>").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = signing_key();
+        let manifest = manifest_for(HASH_FILE);
+        let signature = key.sign(manifest.as_bytes()).to_bytes();
+        let hex_signature: String = signature.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let other_key = public_key_hex(&SigningKey::from_bytes(&[8; 32]));
+
+        assert!(integrity::verify_signature(manifest.as_bytes(), &signature, &public_key_hex(&key)).is_ok());
+        assert!(integrity::verify_signature(manifest.as_bytes(), hex_signature.as_bytes(), &public_key_hex(&key)).is_ok());
+        assert!(integrity::verify_signature(b"tampered", &signature, &public_key_hex(&key)).is_err());
+        assert!(integrity::verify_signature(manifest.as_bytes(), &signature, &other_key).is_err());
+        assert!(integrity::verify_signature(manifest.as_bytes(), &signature, "not a key").is_err());
+    }
+
+    #[test]
+    fn test_signed_mirror() {
+        let key = signing_key();
+        let manifest = manifest_for(HASH_FILE);
+        let signature = key.sign(manifest.as_bytes()).to_bytes().to_vec();
+        let base_url = start_mirror(vec![
+            ("VirusShare_00000.md5", Some(HASH_FILE.as_bytes().to_vec())),
+            ("SHA256SUMS", Some(manifest.into_bytes())),
+            ("SHA256SUMS.sig", Some(signature)),
+        ]);
+        let cache_dir = Path::new("test_signed_mirror");
+        let source = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir).with_public_key(&public_key_hex(&key));
+        let wrong_key = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir)
+            .with_public_key(&public_key_hex(&SigningKey::from_bytes(&[8; 32])));
+
+        let content = source.fetch_batch("00000");
+        std::fs::remove_dir_all(cache_dir).ok();
+        let content = content.unwrap();
+        assert_eq!(source.verify_batch("00000", &content).unwrap(), Verification::Signed);
+        assert!(matches!(
+            source.verify_batch("00000", b"ec2112c9c243d837247217baf351ab79\n"),
+            Err(SourceError::Rejected(_))
+        ));
+        assert!(matches!(wrong_key.verify_batch("00000", &content), Err(SourceError::Rejected(_))));
+    }
+
+    #[test]
+    fn test_bad_manifest_stops_listing() {
+        let manifest = manifest_for(HASH_FILE);
+        let signature = SigningKey::from_bytes(&[8; 32]).sign(manifest.as_bytes()).to_bytes().to_vec();
+        let requested = Arc::new(AtomicUsize::new(0));
+        let counter = requested.clone();
+        let address = start_server(move |request| match request.path() {
+            "/hashfiles/SHA256SUMS" => MockResponse::ok(manifest.clone()),
+            "/hashfiles/SHA256SUMS.sig" => MockResponse::ok(signature.clone()),
+            _ => {
+                counter.fetch_add(1, Ordering::SeqCst);
+                MockResponse::ok(HASH_FILE)
+            }
+        });
+        let source = VirusShareSource::new(Client::new(), &format!("http://{}/hashfiles", address), "", "", Path::new("test_bad_manifest"))
+            .with_public_key(&public_key_hex(&signing_key()));
+
+        assert!(matches!(source.list_batches(), Err(SourceError::Rejected(_))));
+        // Neither probed nor downloaded
+        assert_eq!(requested.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_manifest_lists_files() {
+        let manifest = format!(
+            "{}\n{}  VirusShare_00007.md5\n",
+            manifest_for(HASH_FILE).trim(),
+            integrity::sha256_hex(b"later")
+        );
+        let base_url = start_mirror(vec![("SHA256SUMS", Some(manifest.into_bytes()))]);
+        let source = VirusShareSource::new(Client::new(), &base_url, "", "", Path::new("test_manifest_lists_files"));
+
+        // Taken from the manifest, without probing the mirror for the files
+        assert_eq!(source.get_file_list().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_unsigned_mirror() {
+        let base_url = start_mirror(vec![
+            ("VirusShare_00000.md5", Some(HASH_FILE.as_bytes().to_vec())),
+            ("VirusShare_00001.md5", None),
+        ]);
+        let cache_dir = Path::new("test_unsigned_mirror");
+        let source = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir);
+        let pinned = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir).with_public_key(&public_key_hex(&signing_key()));
+
+        let content = source.fetch_batch("00000");
+        let truncated = source.fetch_batch("00001");
+        std::fs::remove_dir_all(cache_dir).ok();
+
+        let content = content.unwrap();
+        assert_eq!(source.verify_batch("00000", &content).unwrap(), Verification::Unverified);
+        assert!(matches!(pinned.verify_batch("00000", &content), Err(SourceError::Rejected(_))));
+        assert!(truncated.is_err());
+    }
+}
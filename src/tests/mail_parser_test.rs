@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::backend::mail_parser;
+
+    const EML_FILE_LOC: &str = "test_mail.eml";
+    const MBOX_FILE_LOC: &str = "test_mailbox.mbox";
+
+    const MULTIPART_MAIL: &str = "From: sender@example.com\r
+To: receiver@example.com\r
+Subject: Invoice\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed;\r
+ boundary=\"outer\"\r
+\r
+This is a multi-part message in MIME format.\r
+--outer\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+Please see the attached invoice.\r
+--outer\r
+Content-Type: application/vnd.ms-word.document.macroEnabled.12; name=\"invoice.docm\"\r
+Content-Disposition: attachment; filename=\"invoice.docm\"\r
+Content-Transfer-Encoding: base64\r
+\r
+aGVsbG8g\r
+d29ybGQ=\r
+--outer\r
+Content-Type: text/plain\r
+Content-Disposition: attachment; filename*=utf-8''r%C3%A9sum%C3%A9.txt\r
+Content-Transfer-Encoding: quoted-printable\r
+\r
+caf=C3=A9 =\r
+au lait\r
+--outer--\r
+";
+
+    fn collect_attachments(path: &str) -> Vec<(String, Vec<u8>)> {
+        let mut attachments = Vec::new();
+        mail_parser::visit_attachments(Path::new(path), &mut |inner_path, reader| {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+            attachments.push((inner_path.to_owned(), content));
+        })
+        .unwrap();
+        attachments
+    }
+
+    #[test]
+    fn test_is_mailbox() {
+        assert!(mail_parser::is_mailbox(Path::new("export/mailbox.MBOX")));
+        assert!(mail_parser::is_mailbox(Path::new("message.eml")));
+        assert!(!mail_parser::is_mailbox(Path::new("message.txt")));
+    }
+
+    #[test]
+    fn test_eml_attachments() {
+        std::fs::write(EML_FILE_LOC, MULTIPART_MAIL).unwrap();
+        let attachments = collect_attachments(EML_FILE_LOC);
+        std::fs::remove_file(EML_FILE_LOC).unwrap();
+
+        assert_eq!(
+            attachments,
+            vec![
+                ("/invoice.docm".to_owned(), b"hello world".to_vec()),
+                ("/résumé.txt".to_owned(), "café au lait".as_bytes().to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mbox_attachments() {
+        let single = "From: a@example.com\nSubject: Hi\nContent-Type: application/octet-stream\nContent-Transfer-Encoding: base64\n\nYWJj\n";
+        let mbox = format!(
+            "From a@example.com Mon Jan  1 00:00:00 2024\n{}\nFrom b@example.com Mon Jan  1 00:00:00 2024\n{}",
+            single,
+            MULTIPART_MAIL.replace('\r', "")
+        );
+        std::fs::write(MBOX_FILE_LOC, mbox).unwrap();
+        let attachments = collect_attachments(MBOX_FILE_LOC);
+        std::fs::remove_file(MBOX_FILE_LOC).unwrap();
+
+        let paths: Vec<&str> = attachments.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["/message-1/part-1", "/message-2/invoice.docm", "/message-2/résumé.txt"]
+        );
+        assert_eq!(attachments[0].1, b"abc".to_vec());
+        assert_eq!(attachments[1].1, b"hello world".to_vec());
+    }
+
+    #[test]
+    fn test_empty_mbox() {
+        let path = "test_empty.mbox";
+        std::fs::write(path, "just some text\n").unwrap();
+        let result = mail_parser::visit_attachments(Path::new(path), &mut |_, _| {});
+        std::fs::remove_file(path).unwrap();
+
+        assert!(result.is_err());
+    }
+}
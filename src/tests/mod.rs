@@ -1,4 +1,134 @@
+pub mod app_paths_test;
 pub mod db_ops_test;
 pub mod file_log_test;
 pub mod file_scanner_test;
-pub mod config_file_test;
\ No newline at end of file
+pub mod config_file_test;
+pub mod bloom_filter_test;
+pub mod disk_image_test;
+pub mod mail_parser_test;
+pub mod sources_test;
+pub mod db_backup_test;
+pub mod db_health_test;
+pub mod db_worker_test;
+pub mod db_migrations_test;
+pub mod integrity_test;
+pub mod download_test;
+pub mod retry_test;
+pub mod http_test;
+
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+/// A request received by the stand-in server started with `start_server`
+#[derive(Clone)]
+pub struct MockRequest {
+    /// The request line, such as `GET /hashfiles/VirusShare_00000.md5 HTTP/1.1`
+    pub line: String,
+    /// The headers as sent, without line breaks
+    pub headers: Vec<String>,
+}
+
+impl MockRequest {
+    /// The requested path, or the full URL for requests sent to a proxy
+    pub fn path(&self) -> &str {
+        self.line.split(' ').nth(1).unwrap_or_default()
+    }
+
+    /// The value of the first header with the given name, which is compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find_map(|header| {
+            let (key, value) = header.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// The answer of the stand-in server to a single request, the connection is closed afterwards
+pub struct MockResponse {
+    status: String,
+    headers: Vec<String>,
+    body: Vec<u8>,
+    content_length: Option<usize>,
+}
+
+impl MockResponse {
+    /// An empty response with the given status, such as `404 Not Found`
+    pub fn status(status: &str) -> Self {
+        MockResponse {
+            status: status.to_owned(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            content_length: None,
+        }
+    }
+
+    /// A `200 OK` response with the given body
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::status("200 OK").body(body)
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Adds a header line, such as `ETag: "v1"`
+    pub fn header(mut self, header: &str) -> Self {
+        self.headers.push(header.to_owned());
+        self
+    }
+
+    /// Announces a longer body than is sent, so the download ends early like on a dropped connection
+    pub fn truncated(mut self, content_length: usize) -> Self {
+        self.content_length = Some(content_length);
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\n", self.status);
+        for header in &self.headers {
+            response.push_str(&format!("{}\r\n", header));
+        }
+        response.push_str(&format!(
+            "Connection: close\r\nContent-Length: {}\r\n\r\n",
+            self.content_length.unwrap_or(self.body.len())
+        ));
+        let mut response = response.into_bytes();
+        response.extend_from_slice(&self.body);
+        response
+    }
+}
+
+/// Starts a stand-in HTTP server on a free local port, which answers every request with the given handler.
+/// Returns its address, such as `127.0.0.1:41234`. The server runs until the tests end.
+pub fn start_server(handler: impl Fn(&MockRequest) -> MockResponse + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = MockRequest {
+                line: String::new(),
+                headers: Vec::new(),
+            };
+            if reader.read_line(&mut request.line).is_err() {
+                continue;
+            }
+            request.line = request.line.trim().to_owned();
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).is_err() || header.trim().is_empty() {
+                    break;
+                }
+                request.headers.push(header.trim().to_owned());
+            }
+            let _ = stream.write_all(&handler(&request).to_bytes());
+        }
+    });
+    address
+}
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use reqwest::blocking::Client;
+
+    use crate::backend::sources::{
+        retry::{Failure, RetryPolicy},
+        SourceError,
+    };
+    use crate::tests::{self, MockResponse};
+
+    /// Starts a stand-in server answering every request with the given status line and returns its URL
+    fn start_server(status: &'static str) -> String {
+        let address = tests::start_server(move |_| MockResponse::status(status));
+        format!("http://{}/hashfiles/VirusShare_00000.md5", address)
+    }
+
+    fn classify_status(status: &'static str) -> Failure {
+        let err = Client::new()
+            .get(start_server(status))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .unwrap_err();
+        Failure::classify(&SourceError::Http(err))
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify_status("404 Not Found"), Failure::NotFound);
+        assert_eq!(classify_status("429 Too Many Requests"), Failure::RateLimited);
+        assert_eq!(classify_status("503 Service Unavailable"), Failure::RateLimited);
+        assert_eq!(classify_status("500 Internal Server Error"), Failure::Server);
+        assert_eq!(classify_status("401 Unauthorized"), Failure::Permanent);
+
+        // A server that accepts the connection but never answers
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::builder().timeout(Duration::from_millis(200)).build().unwrap();
+        let timeout = client.get(format!("http://{}/", silent.local_addr().unwrap())).send().unwrap_err();
+        assert_eq!(Failure::classify(&SourceError::Http(timeout)), Failure::Timeout);
+
+        // Nothing is listening on the port anymore
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let refused = Client::new().get(format!("http://{}/", closed)).send().unwrap_err();
+        assert_eq!(Failure::classify(&SourceError::Http(refused)), Failure::Connection);
+
+        assert_eq!(
+            Failure::classify(&SourceError::Interrupted(String::from("connection reset"))),
+            Failure::Connection
+        );
+        assert_eq!(
+            Failure::classify(&SourceError::Rejected(String::from("checksum mismatch"))),
+            Failure::Permanent
+        );
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(100), Duration::from_millis(300));
+
+        for failure in [Failure::NotFound, Failure::Tls, Failure::Permanent] {
+            assert_eq!(policy.delay(failure, 1), None);
+        }
+        assert!(policy.delay(Failure::Dns, 1).is_some());
+        assert_eq!(policy.delay(Failure::Dns, 2), None);
+        assert_eq!(policy.delay(Failure::Connection, 4), None);
+
+        let first = policy.delay(Failure::Timeout, 1).unwrap();
+        let second = policy.delay(Failure::Timeout, 2).unwrap();
+        let capped = policy.delay(Failure::Timeout, 3).unwrap();
+        let rate_limited = policy.delay(Failure::RateLimited, 1).unwrap();
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+        assert!(capped >= Duration::from_millis(150) && capped <= Duration::from_millis(300));
+        assert!(rate_limited >= Duration::from_millis(150) && rate_limited <= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_run() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1), Duration::from_millis(5));
+
+        let mut attempts = 0;
+        let exhausted: Result<(), _> = policy.run("00001", || false, || {
+            attempts += 1;
+            Err(SourceError::Interrupted(String::from("connection reset")))
+        });
+        assert_eq!(attempts, 3);
+        assert!(matches!(exhausted, Err(SourceError::GaveUp(_))));
+
+        let mut attempts = 0;
+        let not_found: Result<(), _> = policy.run("00002", || false, || {
+            attempts += 1;
+            Err(SourceError::NotFound(String::from("00002")))
+        });
+        assert_eq!(attempts, 1);
+        assert!(matches!(not_found, Err(SourceError::NotFound(_))));
+
+        let mut attempts = 0;
+        let recovered = policy.run("00003", || false, || {
+            attempts += 1;
+            match attempts {
+                1 => Err(SourceError::Interrupted(String::from("connection reset"))),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(recovered.unwrap(), 2);
+
+        // A stopped update does not wait for the next attempt
+        let mut attempts = 0;
+        let stopped: Result<(), _> = policy.run("00004", || true, || {
+            attempts += 1;
+            Err(SourceError::Interrupted(String::from("connection reset")))
+        });
+        assert_eq!(attempts, 1);
+        assert!(matches!(stopped, Err(SourceError::Interrupted(_))));
+    }
+}
@@ -0,0 +1,422 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use reqwest::blocking::Client;
+
+    use crate::backend::app_paths::AppPaths;
+    use crate::backend::db_ops::DBOps;
+    use crate::backend::signature::{Severity, Signature};
+    use crate::backend::sources::{
+        local_dir::LocalDirSource, open_import, parse_hash_list, parse_removal_list, virusshare::{VirusShareSource, DEFAULT_MIRROR_URL}, SignatureSource,
+        SourceError,
+    };
+    use crate::tests::{start_server, MockResponse};
+
+    const LOCAL_DIR_LOC: &str = "test_hash_lists";
+    const BUNDLE_LOC: &str = "test_bundle.tar.gz";
+
+    #[test]
+    fn test_parse_hash_list() {
+        let content = b"# VirusShare.com - Hash 00000\n93FE4FB85A682907137B0B1051991332\nec2112c9c243d837247217baf351ab79,Emotet,3,2023-01-05\n0123456789abcdef0123456789abcdef  sample.exe\nnot a hash\n\n";
+        let signatures = parse_hash_list(content).unwrap();
+        let hashes: Vec<&str> = signatures.iter().map(|signature| signature.hash.as_str()).collect();
+        assert_eq!(
+            hashes,
+            vec![
+                "93fe4fb85a682907137b0b1051991332",
+                "ec2112c9c243d837247217baf351ab79",
+                "0123456789abcdef0123456789abcdef",
+            ]
+        );
+        assert!(!signatures[0].has_metadata());
+        assert_eq!(signatures[1].name.as_deref(), Some("Emotet"));
+        assert_eq!(signatures[1].severity, Some(Severity::High));
+        assert_eq!(signatures[1].first_seen.as_deref(), Some("2023-01-05"));
+        assert!(!signatures[2].has_metadata());
+    }
+
+    #[test]
+    fn test_import_metadata() {
+        let hash = "5d41402abc4b2a76b9719d911017c592";
+        let db_dir = Path::new("test_import_metadata");
+        let mut db_ops = DBOps::new(&AppPaths::in_dir(db_dir), None).unwrap();
+        db_ops
+            .import_batch("feed_a", "meta.csv", parse_hash_list(format!("{},Mirai,,2016-08-01", hash).as_bytes()).unwrap())
+            .unwrap();
+        // Later feeds only fill in what is still missing
+        db_ops
+            .import_batch(
+                "feed_b",
+                "meta.csv",
+                vec![Signature {
+                    hash: hash.to_owned(),
+                    name: Some("Other".to_owned()),
+                    severity: Some(Severity::Critical),
+                    first_seen: None,
+                }],
+            )
+            .unwrap();
+
+        let found = db_ops.hashes_exist(&[hash.to_owned()]).unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all(db_dir).unwrap();
+
+        let info = &found[hash];
+        assert_eq!(info.name.as_deref(), Some("Mirai"));
+        assert_eq!(info.severity, Some(Severity::Critical));
+        assert_eq!(info.first_seen.as_deref(), Some("2016-08-01"));
+        assert_eq!(info.source.as_deref(), Some("feed_a"));
+        assert!(info.added.is_some());
+    }
+
+    #[test]
+    fn test_local_dir_source() {
+        std::fs::create_dir_all(LOCAL_DIR_LOC).unwrap();
+        std::fs::write(
+            format!("{}/list.md5", LOCAL_DIR_LOC),
+            "93fe4fb85a682907137b0b1051991332\n",
+        )
+        .unwrap();
+        std::fs::write(format!("{}/readme.pdf", LOCAL_DIR_LOC), "ignored").unwrap();
+        let source = LocalDirSource::new("usb", LOCAL_DIR_LOC);
+
+        let batches = source.list_batches().unwrap();
+        let content = source.fetch_batch("list.md5").unwrap();
+        let escape = source.fetch_batch("../Cargo.toml");
+        std::fs::remove_dir_all(LOCAL_DIR_LOC).unwrap();
+
+        assert_eq!(batches, vec!["list.md5".to_owned()]);
+        assert_eq!(source.parse_batch("list.md5", &content).unwrap().len(), 1);
+        assert!(matches!(escape, Err(SourceError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_parse_removal_list() {
+        let content = b"# Retracted\n93FE4FB85A682907137B0B1051991332,Signed Windows driver\nec2112c9c243d837247217baf351ab79\nnot a hash;reason\n";
+        let removals = parse_removal_list(content).unwrap();
+
+        assert_eq!(removals.len(), 2);
+        assert_eq!(removals[0].hash, "93fe4fb85a682907137b0b1051991332");
+        assert_eq!(removals[0].reason.as_deref(), Some("Signed Windows driver"));
+        assert_eq!(removals[1].reason, None);
+    }
+
+    #[test]
+    fn test_apply_removals() {
+        let dir = format!("{}_removals", LOCAL_DIR_LOC);
+        let hash = "0d6f5b5e1a2c3d4e5f60718293a4b5c6";
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(format!("{}/list.md5", dir), format!("{}\n", hash)).unwrap();
+        std::fs::write(format!("{}/false-positives.removed", dir), format!("{},Signed driver\n", hash)).unwrap();
+        let source = LocalDirSource::new("removals", &dir);
+        let db_dir = format!("{}_db", dir);
+        let mut db_ops = DBOps::new(&AppPaths::in_dir(Path::new(&db_dir)), None).unwrap();
+        let lists = source.list_removals().unwrap();
+
+        db_ops.download_files(&source, vec!["list.md5".to_owned()], 1);
+        let imported = db_ops.hashes_exist(&[hash.to_owned()]).unwrap();
+        let removed = db_ops
+            .apply_removals("removals", "false-positives.removed", parse_removal_list(format!("{},Signed driver", hash).as_bytes()).unwrap())
+            .unwrap();
+        let retracted = db_ops.hashes_exist(&[hash.to_owned()]).unwrap();
+        // Retracted hashes stay out, even when a batch still contains them
+        db_ops.import_batch("removals", "again.md5", parse_hash_list(hash.as_bytes()).unwrap()).unwrap();
+        let reimported = db_ops.hashes_exist(&[hash.to_owned()]).unwrap();
+        let audit = db_ops.get_removals().unwrap();
+        let pending = db_ops.get_diff_removals(&source).unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&db_dir).unwrap();
+
+        assert_eq!(lists, vec!["false-positives.removed".to_owned()]);
+        assert_eq!(imported.len(), 1);
+        assert_eq!(removed, 1);
+        assert!(retracted.is_empty());
+        assert!(reimported.is_empty());
+        assert_eq!(audit.len(), 1);
+        let entry = &audit[0];
+        assert_eq!(entry.reason.as_deref(), Some("Signed driver"));
+        assert_eq!(entry.list, "false-positives.removed");
+        assert!(entry.was_present);
+        assert!(entry.applied.is_some());
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_download() {
+        let dir = format!("{}_concurrent", LOCAL_DIR_LOC);
+        std::fs::create_dir_all(&dir).unwrap();
+        let hashes: Vec<String> = (0..6u64).map(|nr| format!("{:032x}", 0xc0ffee00 + nr)).collect();
+        for (nr, hash) in hashes.iter().enumerate() {
+            std::fs::write(format!("{}/list{}.md5", dir, nr), format!("{}\n", hash)).unwrap();
+        }
+        let source = LocalDirSource::new("concurrent", &dir);
+        let db_dir = format!("{}_db", dir);
+        let mut db_ops = DBOps::new(&AppPaths::in_dir(Path::new(&db_dir)), None).unwrap();
+
+        let batches = source.list_batches().unwrap();
+        db_ops.download_files(&source, batches, 3);
+        let imported = db_ops.get_db_files("concurrent").unwrap_or_default();
+        let found = db_ops.hashes_exist(&hashes).unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&db_dir).unwrap();
+
+        assert_eq!(imported.len(), 6);
+        assert_eq!(found.len(), 6);
+    }
+
+    #[test]
+    fn test_cancelled_import() {
+        let dir = format!("{}_cancelled", LOCAL_DIR_LOC);
+        std::fs::create_dir_all(&dir).unwrap();
+        for nr in 0..3u64 {
+            std::fs::write(format!("{}/list{}.md5", dir, nr), format!("{:032x}\n", 0xcace1ed0 + nr)).unwrap();
+        }
+        let source = LocalDirSource::new("cancelled", &dir);
+        let db_dir = format!("{}_db", dir);
+        let mut db_ops = DBOps::new(&AppPaths::in_dir(Path::new(&db_dir)), None).unwrap();
+        let cancel = Arc::new(AtomicBool::new(false));
+        db_ops.set_cancel_flag(cancel.clone());
+
+        let not_cancelled = db_ops.cancelled();
+        cancel.store(true, Ordering::SeqCst);
+        let result = db_ops.import_from(&source);
+        let imported = db_ops.get_db_files("cancelled").unwrap_or_default();
+        let cancelled = db_ops.cancelled();
+        drop(db_ops);
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&db_dir).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(not_cancelled, None);
+        assert_eq!(cancelled, Some((0, 3)));
+        assert!(imported.is_empty());
+    }
+
+    #[test]
+    fn test_check_source() {
+        let dir = format!("{}_check", LOCAL_DIR_LOC);
+        std::fs::create_dir_all(&dir).unwrap();
+        for nr in 0..3u64 {
+            let content = format!("{:032x}\n{:032x}\n", 0xc4ec0000 + nr * 2, 0xc4ec0001 + nr * 2);
+            std::fs::write(format!("{}/list{}.md5", dir, nr), content).unwrap();
+        }
+        let source = LocalDirSource::new("check", &dir);
+        let db_dir = format!("{}_db", dir);
+        let mut db_ops = DBOps::new(&AppPaths::in_dir(Path::new(&db_dir)), None).unwrap();
+        db_ops.import_batch("check", "list0.md5", Vec::new()).unwrap();
+
+        let check = db_ops.check_source(&source).unwrap();
+        let listed = db_ops.get_diff_file(&source).unwrap();
+        drop(db_ops);
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&db_dir).unwrap();
+
+        assert_eq!(check.new_files, 2);
+        assert_eq!(check.download_size, 2 * 66);
+        assert_eq!(check.new_hashes, 4);
+        // Nothing was imported by checking
+        assert_eq!(listed.len(), 2);
+    }
+
+    #[test]
+    fn test_import_bundle() {
+        let encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(BUNDLE_LOC).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (path, content) in [
+            ("lists/a.md5", "93fe4fb85a682907137b0b1051991332\n"),
+            ("lists/nested/b.txt", "ec2112c9c243d837247217baf351ab79\n"),
+            ("../escape.md5", "0123456789abcdef0123456789abcdef\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let paths = AppPaths::in_dir(Path::new("test_bundle_import"));
+        let source = open_import(Path::new(BUNDLE_LOC), &paths).unwrap();
+        let batches = source.list_batches().unwrap();
+        let hashes: Vec<Signature> = batches
+            .iter()
+            .flat_map(|batch| {
+                let content = source.fetch_batch(batch).unwrap();
+                source.parse_batch(batch, &content).unwrap()
+            })
+            .collect();
+        drop(source);
+        let leftover = std::fs::read_dir(&paths.cache_dir).unwrap().count();
+        std::fs::remove_file(BUNDLE_LOC).unwrap();
+        std::fs::remove_dir_all("test_bundle_import").unwrap();
+
+        assert_eq!(batches, vec!["a.md5", "b.txt", "escape.md5"]);
+        assert_eq!(leftover, 0);
+        assert_eq!(hashes.len(), 3);
+        assert!(!Path::new("../escape.md5").exists());
+    }
+
+    /// Starts a stand-in mirror serving `VirusShare_00000.md5` to `VirusShare_00002.md5`
+    /// to requests carrying the expected authorization header, and returns its base URL
+    fn start_mirror(expected_auth: &'static str) -> String {
+        let address = start_server(move |request| {
+            let known = ["00000", "00001", "00002"]
+                .iter()
+                .any(|nr| request.path() == format!("/hashfiles/VirusShare_{}.md5", nr));
+            match (request.header("authorization") == Some(expected_auth), known) {
+                (false, _) => MockResponse::status("401 Unauthorized"),
+                (true, false) => MockResponse::status("404 Not Found"),
+                (true, true) => MockResponse::ok("# mirror\n93fe4fb85a682907137b0b1051991332\n"),
+            }
+        });
+        format!("http://{}/hashfiles/", address)
+    }
+
+    #[test]
+    fn test_virusshare_mirror() {
+        let base_url = start_mirror("Bearer secret");
+        let cache_dir = Path::new("test_mirror_cache");
+        let source = VirusShareSource::new(Client::new(), &base_url, "", "secret", cache_dir);
+
+        let latest = source.get_file_list().unwrap();
+        let content = source.fetch_batch("00001").unwrap();
+        let missing = source.fetch_batch("00003");
+        std::fs::remove_dir_all(cache_dir).ok();
+
+        assert_eq!(latest, 2);
+        assert_eq!(source.parse_batch("00001", &content).unwrap().len(), 1);
+        assert!(matches!(missing, Err(SourceError::NotFound(_))));
+    }
+
+    /// Starts a stand-in mirror serving the given amount of hash files without authentication,
+    /// counting the requests for hash files. Returns its base URL.
+    fn start_counting_mirror(files: usize, requests: Arc<AtomicUsize>) -> String {
+        let address = start_server(move |request| {
+            let file_nr = request
+                .path()
+                .strip_prefix("/hashfiles/VirusShare_")
+                .and_then(|rest| rest.strip_suffix(".md5"))
+                .and_then(|file_nr| file_nr.parse::<usize>().ok());
+            if file_nr.is_some() {
+                requests.fetch_add(1, Ordering::SeqCst);
+            }
+            match file_nr {
+                Some(file_nr) if file_nr < files => MockResponse::ok(""),
+                _ => MockResponse::status("404 Not Found"),
+            }
+        });
+        format!("http://{}/hashfiles", address)
+    }
+
+    #[test]
+    fn test_virusshare_discovery() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let base_url = start_counting_mirror(40, requests.clone());
+
+        let cache_dir = Path::new("test_discovery_cache");
+
+        let latest = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir).get_file_list().unwrap();
+        let searched = requests.swap(0, Ordering::SeqCst);
+        // A new instance, as during the next update
+        let cached = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir).get_file_list().unwrap();
+        let rechecked = requests.load(Ordering::SeqCst);
+        std::fs::remove_dir_all(cache_dir).unwrap();
+
+        assert_eq!(latest, 39);
+        assert!(searched <= 16, "{} requests to find the newest file", searched);
+        assert_eq!(cached, 39);
+        assert_eq!(rechecked, 2);
+    }
+
+    #[test]
+    fn test_virusshare_discovery_limit() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        // Answers every file number with success, like a misconfigured catch-all server
+        let base_url = start_counting_mirror(usize::MAX, requests.clone());
+
+        let cache_dir = Path::new("test_discovery_limit_cache");
+
+        let latest = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir).get_file_list();
+        std::fs::remove_dir_all(cache_dir).ok();
+
+        assert!(matches!(latest, Err(SourceError::Rejected(_))));
+        assert!(requests.load(Ordering::SeqCst) <= 20);
+    }
+
+    #[test]
+    fn test_virusshare_discovery_cancelled() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let address = start_server(move |request| {
+            if request.path().starts_with("/hashfiles/VirusShare_") {
+                counted.fetch_add(1, Ordering::SeqCst);
+            }
+            MockResponse::status("503 Service Unavailable")
+        });
+        let cache_dir = Path::new("test_discovery_cancelled");
+        let cancel = Arc::new(AtomicBool::new(true));
+
+        let latest = VirusShareSource::new(Client::new(), &format!("http://{}/hashfiles", address), "", "", cache_dir)
+            .with_cancel_flag(cancel)
+            .get_file_list();
+        std::fs::remove_dir_all(cache_dir).ok();
+
+        assert!(latest.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_virusshare_mirror_basic_auth() {
+        // "pi:secret" in base64
+        let base_url = start_mirror("Basic cGk6c2VjcmV0");
+        let cache_dir = Path::new("test_basic_auth_cache");
+        let authorized = VirusShareSource::new(Client::new(), &base_url, "pi", "secret", cache_dir);
+        let anonymous = VirusShareSource::new(Client::new(), &base_url, "", "", cache_dir);
+
+        let authorized_fetch = authorized.fetch_batch("00000");
+        let anonymous_fetch = anonymous.fetch_batch("00000");
+        std::fs::remove_dir_all(cache_dir).ok();
+
+        assert!(authorized_fetch.is_ok());
+        assert!(matches!(anonymous_fetch, Err(SourceError::Http(_))));
+    }
+
+    #[test]
+    #[ignore = "downloads from virusshare.com"]
+    fn test_virusshare_fetch_batch() {
+        let cache_dir = Path::new("test_virusshare_cache");
+        let source = VirusShareSource::new(Client::new(), DEFAULT_MIRROR_URL, "", "", cache_dir);
+        let content = source.fetch_batch("00003");
+        std::fs::remove_dir_all(cache_dir).ok();
+        let content = content.unwrap();
+        assert!(!source.parse_batch("00003", &content).unwrap().is_empty());
+    }
+
+    #[test]
+    #[ignore = "downloads from virusshare.com"]
+    fn test_virusshare_file_exists() {
+        let source = VirusShareSource::new(Client::new(), DEFAULT_MIRROR_URL, "", "", Path::new("test_virusshare_exists"));
+        assert!(source.file_exists(1).unwrap_or(false));
+    }
+
+    #[test]
+    #[ignore = "downloads from virusshare.com"]
+    fn test_virusshare_get_file_list() {
+        let cache_dir = Path::new("test_virusshare_latest");
+        let source = VirusShareSource::new(Client::new(), DEFAULT_MIRROR_URL, "", "", cache_dir);
+        let latest = source.get_file_list();
+        std::fs::remove_dir_all(cache_dir).ok();
+        assert!(latest.unwrap() > 0);
+    }
+}